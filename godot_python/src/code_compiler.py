@@ -0,0 +1,266 @@
+import ast
+import builtins
+import sys
+import types
+
+import sandbox
+
+TICK_NAME = "__budget_tick__"
+TRACKER_NAME = "__budget_tracker__"
+ATTRIBUTE_BUILTINS = {"getattr", "setattr", "delattr", "hasattr"}
+
+_original_compile = builtins.compile
+_original_exec = builtins.exec
+_original_eval = builtins.eval
+
+
+def is_reserved_name(name):
+    # The budget and the sandbox hooks instrumented code relies on
+    return name in (TICK_NAME, TRACKER_NAME) or (
+        name.startswith("__sandbox_") and name.endswith("__")
+    )
+
+
+def _tick_call():
+    # An attribute of NoneType, which user code can't rebind like a global name
+    func = ast.Attribute(value=ast.Constant(value=None), attr=TICK_NAME, ctx=ast.Load())
+    return ast.Call(func=func, args=[], keywords=[])
+
+
+def _syntax_error(filename, node, message):
+    lineno = getattr(node, "lineno", 1)
+    col_offset = getattr(node, "col_offset", 0)
+    return SyntaxError(message, (filename, lineno, col_offset + 1, None))
+
+
+def _has_docstring(body):
+    return (
+        len(body) > 0
+        and isinstance(body[0], ast.Expr)
+        and isinstance(body[0].value, ast.Constant)
+        and isinstance(body[0].value.value, str)
+    )
+
+
+class BudgetTransformer(ast.NodeTransformer):
+    # Every loop iteration and function call goes through a tick, so the host
+    # can stop code that never returns
+
+    def _instrument_body(self, node):
+        self.generic_visit(node)
+        tick = ast.copy_location(ast.Expr(value=_tick_call()), node)
+        # Keep docstrings as the first statement so __doc__ still works
+        index = 1 if _has_docstring(node.body) else 0
+        node.body.insert(index, tick)
+        return node
+
+    visit_For = _instrument_body
+    visit_AsyncFor = _instrument_body
+    visit_While = _instrument_body
+    visit_FunctionDef = _instrument_body
+    visit_AsyncFunctionDef = _instrument_body
+
+    def visit_comprehension(self, node):
+        self.generic_visit(node)
+        # The tick returns None, so this condition never filters anything out
+        check = ast.Compare(
+            left=_tick_call(), ops=[ast.Is()], comparators=[ast.Constant(value=None)]
+        )
+        node.ifs.append(ast.copy_location(check, node.iter))
+        return node
+
+
+class ReservedNameChecker(ast.NodeVisitor):
+    # User code may not bind the reserved names in any way, or it could switch
+    # the sandbox hooks off
+
+    def __init__(self, filename):
+        self.filename = filename
+
+    def _check(self, node, name):
+        if name is not None and is_reserved_name(name):
+            raise _syntax_error(
+                self.filename, node, f"cannot bind reserved name '{name}'"
+            )
+
+    def visit_Name(self, node):
+        if not isinstance(node.ctx, ast.Load):
+            self._check(node, node.id)
+
+    def visit_Attribute(self, node):
+        if not isinstance(node.ctx, ast.Load):
+            self._check(node, node.attr)
+        self.generic_visit(node)
+
+    def _visit_definition(self, node):
+        self._check(node, node.name)
+        self.generic_visit(node)
+
+    visit_FunctionDef = _visit_definition
+    visit_AsyncFunctionDef = _visit_definition
+    visit_ClassDef = _visit_definition
+    visit_ExceptHandler = _visit_definition
+    visit_MatchAs = _visit_definition
+    visit_MatchStar = _visit_definition
+
+    def visit_MatchMapping(self, node):
+        self._check(node, node.rest)
+        self.generic_visit(node)
+
+    def visit_arg(self, node):
+        self._check(node, node.arg)
+        self.generic_visit(node)
+
+    def visit_alias(self, node):
+        self._check(node, node.asname or node.name.partition(".")[0])
+
+    def _visit_declaration(self, node):
+        for name in node.names:
+            self._check(node, name)
+
+    visit_Global = _visit_declaration
+    visit_Nonlocal = _visit_declaration
+
+
+def _call_stmt(func_name, arg, node):
+    call = ast.Call(
+        func=ast.Name(id=func_name, ctx=ast.Load()),
+        args=[ast.Constant(value=arg)],
+        keywords=[],
+    )
+    return ast.copy_location(ast.Expr(value=call), node)
+
+
+class SandboxTransformer(ast.NodeTransformer):
+    # Applies the sandbox policy to user code: import checks run right before
+    # the import, removed builtins raise NameError when used, attribute reads
+    # go through `sandbox._safe_getattr`
+
+    def __init__(self, filename):
+        self.filename = filename
+
+    def _reject(self, node, message):
+        raise _syntax_error(self.filename, node, message)
+
+    def visit_Import(self, node):
+        checks = [
+            _call_stmt("__sandbox_check_import__", alias.name, node)
+            for alias in node.names
+        ]
+        return checks + [node]
+
+    def visit_ImportFrom(self, node):
+        # Relative imports stay inside the package that is already loaded
+        if node.level > 0:
+            return node
+        return [_call_stmt("__sandbox_check_import__", node.module, node), node]
+
+    def visit_Name(self, node):
+        if not isinstance(node.ctx, ast.Load):
+            return node
+        if sandbox.is_removed_builtin(node.id):
+            call = ast.Call(
+                func=ast.Name(id="__sandbox_removed__", ctx=ast.Load()),
+                args=[ast.Constant(value=node.id)],
+                keywords=[],
+            )
+            return ast.copy_location(call, node)
+        if sandbox.is_blocked_dunder(node.id):
+            self._reject(node, f"access to name '{node.id}' is not allowed")
+        if node.id in ATTRIBUTE_BUILTINS:
+            node.id = f"__sandbox_{node.id}__"
+        return node
+
+    def visit_Attribute(self, node):
+        self.generic_visit(node)
+        if sandbox.is_blocked_dunder(node.attr):
+            self._reject(node, f"access to attribute '{node.attr}' is not allowed")
+        if not isinstance(node.ctx, ast.Load):
+            return node
+        # Reads are checked when they run, as only then is it known whether
+        # they reach into a module
+        call = ast.Call(
+            func=ast.Name(id="__sandbox_getattr__", ctx=ast.Load()),
+            args=[node.value, ast.Constant(value=node.attr)],
+            keywords=[],
+        )
+        return ast.copy_location(call, node)
+
+
+def instrument(tree, filename="<embedded>"):
+    ReservedNameChecker(filename).visit(tree)
+    sandbox.register_user_file(filename)
+    if sandbox.is_active():
+        tree = SandboxTransformer(filename).visit(tree)
+    tree = BudgetTransformer().visit(tree)
+    return ast.fix_missing_locations(tree)
+
+
+def compile_module(source, filename):
+    tree = instrument(ast.parse(source, filename, "exec"), filename)
+    return _original_compile(tree, filename, "exec")
+
+
+def compile_block(source, filename):
+    # Mirrors the interpreter's block-expression mode: run the statements, then
+    # evaluate the trailing expression (if any) for the result
+    tree = ast.parse(source, filename, "exec")
+    expr = None
+    if len(tree.body) > 0 and isinstance(tree.body[-1], ast.Expr):
+        expr = ast.Expression(body=tree.body.pop().value)
+        expr = _original_compile(instrument(expr, filename), filename, "eval")
+    return _original_compile(instrument(tree, filename), filename, "exec"), expr
+
+
+# User code compiled at runtime gets the same instrumentation, otherwise
+# `exec("while True: pass")` would run outside the budget and the sandbox.
+# Code the stdlib generates (namedtuple, dataclasses) is left alone.
+
+
+def _compile(
+    source, filename, mode, flags=0, dont_inherit=False, optimize=-1, **kwargs
+):
+    if flags & ast.PyCF_ONLY_AST or not sandbox.is_user_frame(sys._getframe(1)):
+        return _original_compile(
+            source, filename, mode, flags, dont_inherit, optimize, **kwargs
+        )
+    return _compile_instrumented(source, filename, mode, flags, dont_inherit, optimize)
+
+
+def _compile_instrumented(
+    source, filename, mode, flags=0, dont_inherit=False, optimize=-1
+):
+    if not isinstance(source, ast.AST):
+        if isinstance(source, (bytes, bytearray)):
+            source = source.decode()
+        if mode == "eval":
+            source = source.lstrip(" \t")
+        source = ast.parse(source, filename, mode)
+    tree = instrument(source, filename)
+    return _original_compile(tree, filename, mode, flags, dont_inherit, optimize)
+
+
+def _run_dynamic(run, mode, source, globals, locals):
+    frame = sys._getframe(2)
+    if globals is None:
+        globals = frame.f_globals
+        if locals is None:
+            locals = frame.f_locals
+    elif locals is None:
+        locals = globals
+    if not isinstance(source, types.CodeType) and sandbox.is_user_frame(frame):
+        source = _compile_instrumented(source, "<string>", mode)
+    return run(source, globals, locals)
+
+
+def _exec(source, globals=None, locals=None):
+    return _run_dynamic(_original_exec, "exec", source, globals, locals)
+
+
+def _eval(source, globals=None, locals=None):
+    return _run_dynamic(_original_eval, "eval", source, globals, locals)
+
+
+builtins.compile = _compile
+builtins.exec = _exec
+builtins.eval = _eval
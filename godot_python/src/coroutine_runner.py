@@ -0,0 +1,27 @@
+import inspect
+
+import godot
+import stdin_override
+
+
+def is_coroutine(value):
+    return inspect.isgenerator(value) or inspect.iscoroutine(value)
+
+
+def step(coroutine, value):
+    # StopIteration never reaches the host, finishing is just another outcome
+    while True:
+        try:
+            with stdin_override.running_coroutine():
+                yielded = coroutine.send(value)
+        except StopIteration as stop:
+            return ("finished", stop.value)
+        if isinstance(yielded, stdin_override.ReadLine):
+            # Input that is already there is sent right back
+            value = yielded.line if yielded.line is not None else stdin_override.take_line()
+            if value is not None:
+                continue
+            return ("wait_for_input", yielded.prompt)
+        if isinstance(yielded, godot.WaitForSignal):
+            return ("wait_for_signal", (yielded.signal, yielded.node_path))
+        return ("yielded", yielded)
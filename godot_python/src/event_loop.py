@@ -0,0 +1,154 @@
+import collections
+import heapq
+import inspect
+import sys
+
+try:
+    import asyncio
+    from asyncio import events
+except ImportError:
+    # Builds without the modules asyncio needs can still run everything but `async def`
+    asyncio = None
+
+
+class GodotEventLoop(asyncio.AbstractEventLoop if asyncio is not None else object):
+    """An asyncio loop that only runs when the host calls `poll(delta)`.
+
+    `time()` is game time, the sum of the deltas passed to `poll`, so
+    `asyncio.sleep` pauses and speeds up with the game. Each poll runs the
+    callbacks that are ready at that point, like one iteration of asyncio's
+    own loop; callbacks they schedule run on the next poll.
+
+    There is no I/O: sockets, pipes and subprocesses raise NotImplementedError.
+    """
+
+    def __init__(self):
+        self._time = 0.0
+        self._ready = collections.deque()
+        self._scheduled = []
+        self._running = False
+        self._closed = False
+        self._debug = False
+        self._exception_handler = None
+
+    def time(self):
+        return self._time
+
+    def call_soon(self, callback, *args, context=None):
+        self._check_closed()
+        handle = events.Handle(callback, args, self, context)
+        self._ready.append(handle)
+        return handle
+
+    def call_soon_threadsafe(self, callback, *args, context=None):
+        return self.call_soon(callback, *args, context=context)
+
+    def call_later(self, delay, callback, *args, context=None):
+        return self.call_at(self._time + delay, callback, *args, context=context)
+
+    def call_at(self, when, callback, *args, context=None):
+        self._check_closed()
+        timer = events.TimerHandle(when, callback, args, self, context)
+        heapq.heappush(self._scheduled, timer)
+        timer._scheduled = True
+        return timer
+
+    def _timer_handle_cancelled(self, handle):
+        # Cancelled timers stay in the heap and are skipped once due
+        pass
+
+    def create_future(self):
+        return asyncio.Future(loop=self)
+
+    def create_task(self, coro, *, name=None, context=None):
+        self._check_closed()
+        return asyncio.Task(coro, loop=self, name=name)
+
+    def poll(self, delta):
+        self._check_closed()
+        self._time += max(delta, 0.0)
+        while self._scheduled and self._scheduled[0].when() <= self._time:
+            timer = heapq.heappop(self._scheduled)
+            timer._scheduled = False
+            if not timer.cancelled():
+                self._ready.append(timer)
+
+        previous = events._get_running_loop()
+        events._set_running_loop(self)
+        self._running = True
+        try:
+            for _ in range(len(self._ready)):
+                handle = self._ready.popleft()
+                if not handle.cancelled():
+                    handle._run()
+        finally:
+            self._running = False
+            events._set_running_loop(previous)
+
+    def is_running(self):
+        return self._running
+
+    def is_closed(self):
+        return self._closed
+
+    def close(self):
+        self._ready.clear()
+        self._scheduled.clear()
+        self._closed = True
+
+    def _check_closed(self):
+        if self._closed:
+            raise RuntimeError("Event loop is closed")
+
+    def get_debug(self):
+        return self._debug
+
+    def set_debug(self, enabled):
+        self._debug = enabled
+
+    def get_exception_handler(self):
+        return self._exception_handler
+
+    def set_exception_handler(self, handler):
+        self._exception_handler = handler
+
+    def default_exception_handler(self, context):
+        message = context.get("message", "Unhandled exception in event loop")
+        exception = context.get("exception")
+        if exception is not None:
+            message = f"{message}: {type(exception).__name__}: {exception}"
+        print(message, file=sys.stderr)
+
+    def call_exception_handler(self, context):
+        if self._exception_handler is None:
+            self.default_exception_handler(context)
+        else:
+            self._exception_handler(self, context)
+
+
+loop = GodotEventLoop() if asyncio is not None else None
+
+
+def poll(delta):
+    if loop is not None:
+        loop.poll(delta)
+
+
+def schedule(value):
+    # Coroutines returned to the host become tasks, anything else is left as is
+    if loop is not None and inspect.iscoroutine(value):
+        return loop.create_task(value)
+    return value
+
+
+def is_task(value):
+    return asyncio is not None and isinstance(value, asyncio.Future)
+
+
+def task_state(task):
+    if not task.done():
+        return ("pending", None)
+    if task.cancelled():
+        return ("cancelled", None)
+    # Raises what the task raised
+    return ("done", task.result())
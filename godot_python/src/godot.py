@@ -0,0 +1,121 @@
+# Host API user code can import, e.g. `from godot import wait_for_signal, Vector2`
+
+import sys
+
+import godot_math
+import sandbox as _sandbox
+from godot_math import *
+
+# Value types live in the native module, `import godot.math` works too
+math = godot_math
+sys.modules[__name__ + ".math"] = godot_math
+
+
+class WaitForSignal:
+    """Pauses a coroutine run by the host until a Godot signal fires.
+
+    Use it as `args = yield wait_for_signal(...)` in a generator, or
+    `args = await wait_for_signal(...)` in an `async def`. The signal
+    arguments are sent back: None, the only argument, or a list of them.
+    """
+
+    def __init__(self, signal, node_path=""):
+        self.signal = signal
+        # Relative to the node the host set as signal source, empty for the node itself
+        self.node_path = node_path
+
+    def __await__(self):
+        return (yield self)
+
+    def __repr__(self):
+        return f"WaitForSignal({self.signal!r}, {self.node_path!r})"
+
+
+def wait_for_signal(signal, node_path=""):
+    return WaitForSignal(signal, node_path)
+
+
+class StringName(str):
+    """A Godot StringName, compares equal to the plain string."""
+
+    def __repr__(self):
+        return f"StringName({str.__repr__(self)})"
+
+
+class NodePath(str):
+    def __repr__(self):
+        return f"NodePath({str.__repr__(self)})"
+
+
+class RID(int):
+    """Opaque id of a server resource, only useful to pass back to Godot."""
+
+    def __repr__(self):
+        return f"RID({int.__repr__(self)})"
+
+
+class _PackedArray(list):
+    """A list that converts back to the same Packed*Array. PackedByteArray is `bytes`."""
+
+    def __repr__(self):
+        return f"{type(self).__name__}({list.__repr__(self)})"
+
+
+class PackedInt32Array(_PackedArray):
+    pass
+
+
+class PackedInt64Array(_PackedArray):
+    pass
+
+
+class PackedFloat32Array(_PackedArray):
+    pass
+
+
+class PackedFloat64Array(_PackedArray):
+    pass
+
+
+class PackedStringArray(_PackedArray):
+    pass
+
+
+class PackedVector2Array(_PackedArray):
+    pass
+
+
+class PackedVector3Array(_PackedArray):
+    pass
+
+
+class PackedVector4Array(_PackedArray):
+    pass
+
+
+class PackedColorArray(_PackedArray):
+    pass
+
+
+# `from godot import *` must not shadow the stdlib `math` module
+__all__ = [
+    name
+    for name in list(globals())
+    if not name.startswith("_") and name not in ("sys", "godot_math", "math")
+]
+
+
+def _export_host_module(module):
+    """Makes a native module the host adds after startup importable and re-exports its
+    public names, e.g. `Object` and the singletons of the engine module in Godot.
+    It stays out when the sandbox policy does not allow importing it."""
+    try:
+        _sandbox.check_import(module.__name__)
+    except ImportError:
+        return
+    sys.modules[module.__name__] = module
+    for name in dir(module):
+        if not name.startswith("_"):
+            globals()[name] = getattr(module, name)
+            if name not in __all__:
+                __all__.append(name)
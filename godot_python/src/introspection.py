@@ -0,0 +1,63 @@
+import inspect
+
+_PARAMETER_KINDS = {
+    inspect.Parameter.POSITIONAL_ONLY: "positional_only",
+    inspect.Parameter.POSITIONAL_OR_KEYWORD: "positional_or_keyword",
+    inspect.Parameter.VAR_POSITIONAL: "var_positional",
+    inspect.Parameter.KEYWORD_ONLY: "keyword_only",
+    inspect.Parameter.VAR_KEYWORD: "var_keyword",
+}
+
+
+def _member_kind(value):
+    if inspect.isclass(value):
+        return "class"
+    if inspect.ismodule(value):
+        return "module"
+    if callable(value):
+        return "function"
+    return "value"
+
+
+def list_members(module):
+    # Private names are left out, like `from module import *` does
+    return [
+        (name, _member_kind(value), type(value).__name__)
+        for name, value in sorted(vars(module).items())
+        if not name.startswith("_")
+    ]
+
+
+def _format_annotation(annotation):
+    if annotation is inspect.Parameter.empty:
+        return None
+    if isinstance(annotation, str):
+        return annotation
+    return inspect.formatannotation(annotation)
+
+
+def get_signature(module, path):
+    # `path` may name a method, e.g. "Player.jump"
+    function = module
+    for part in path.split("."):
+        function = getattr(function, part)
+    if not callable(function):
+        raise TypeError(f"'{path}' is not callable")
+
+    signature = inspect.signature(function)
+    parameters = [
+        (
+            parameter.name,
+            _PARAMETER_KINDS[parameter.kind],
+            parameter.default is not inspect.Parameter.empty,
+            None if parameter.default is inspect.Parameter.empty else parameter.default,
+            _format_annotation(parameter.annotation),
+        )
+        for parameter in signature.parameters.values()
+    ]
+    return (
+        path,
+        parameters,
+        _format_annotation(signature.return_annotation),
+        inspect.getdoc(function),
+    )
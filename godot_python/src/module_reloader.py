@@ -0,0 +1,103 @@
+import ast
+import sys
+
+import code_compiler
+
+# Last source executed for each module, used to tell which functions changed
+_sources = {}
+
+
+def remember(name, source):
+    _sources[name] = source
+
+
+def _function_fingerprints(source):
+    # ast.dump leaves out line numbers, so moving a function is not a change
+    try:
+        tree = ast.parse(source)
+    except SyntaxError:
+        return {}
+
+    functions = {}
+    for node in tree.body:
+        if isinstance(node, (ast.FunctionDef, ast.AsyncFunctionDef)):
+            functions[node.name] = ast.dump(node)
+        elif isinstance(node, ast.ClassDef):
+            for item in node.body:
+                if isinstance(item, (ast.FunctionDef, ast.AsyncFunctionDef)):
+                    functions[f"{node.name}.{item.name}"] = ast.dump(item)
+    return functions
+
+
+def _is_dunder(name):
+    return name.startswith("__") and name.endswith("__")
+
+
+def _rebind(namespace, replacements):
+    rebound = False
+    for key, value in list(namespace.items()):
+        new_value = replacements.get(id(value))
+        if new_value is not None and new_value[0] is value:
+            namespace[key] = new_value[1]
+            rebound = True
+    return rebound
+
+
+def reload(name, source, extra_namespaces):
+    module = sys.modules[name]
+    namespace = module.__dict__
+    file_name = namespace.get("__file__", name)
+    code = code_compiler.compile_module(source, file_name)
+
+    old_namespace = dict(namespace)
+    for key in list(namespace):
+        if not _is_dunder(key):
+            del namespace[key]
+    try:
+        exec(code, namespace)
+    except BaseException:
+        namespace.clear()
+        namespace.update(old_namespace)
+        raise
+
+    persisted = []
+    for key in namespace.get("__persist__", ()):
+        if key in old_namespace:
+            namespace[key] = old_namespace[key]
+            persisted.append(key)
+
+    # Functions and classes other modules imported with `from name import x`
+    # are swapped for the new definitions with the same name
+    replacements = {}
+    for key, old_value in old_namespace.items():
+        if _is_dunder(key) or key not in namespace:
+            continue
+        if getattr(old_value, "__module__", None) != name:
+            continue
+        replacements[id(old_value)] = (old_value, namespace[key])
+
+    updated_modules = []
+    for other_name, other in list(sys.modules.items()):
+        if other is None or other is module:
+            continue
+        other_namespace = getattr(other, "__dict__", None)
+        if isinstance(other_namespace, dict) and _rebind(other_namespace, replacements):
+            updated_modules.append(other_name)
+    for other_namespace in extra_namespaces:
+        _rebind(other_namespace, replacements)
+
+    old_functions = _function_fingerprints(_sources.get(name, ""))
+    new_functions = _function_fingerprints(source)
+    _sources[name] = source
+
+    return {
+        "added": sorted(f for f in new_functions if f not in old_functions),
+        "removed": sorted(f for f in old_functions if f not in new_functions),
+        "changed": sorted(
+            f
+            for f in new_functions
+            if f in old_functions and new_functions[f] != old_functions[f]
+        ),
+        "persisted": persisted,
+        "updated_modules": sorted(updated_modules),
+    }
@@ -0,0 +1,82 @@
+import importlib
+import sys
+from importlib.machinery import ModuleSpec
+
+import code_compiler
+import module_reloader
+import sandbox
+
+# Set by the host: dotted name -> (source, file path, is package), or None when
+# no module source has that module
+find_source = None
+
+
+def _is_taken(top_level):
+    # A module the stdlib or the VM already provides, which a module source must
+    # not replace, or a `sys.py` would stand in for `sys` everywhere
+    if top_level in sys.builtin_module_names:
+        return True
+    module = sys.modules.get(top_level)
+    if module is not None:
+        spec = getattr(module, "__spec__", None)
+        return spec is None or spec.loader is not PackageLoader
+    return any(
+        finder.find_spec(top_level, None) is not None
+        for finder in sys.meta_path
+        if finder is not PackageFinder and hasattr(finder, "find_spec")
+    )
+
+
+def _find(fullname):
+    if find_source is None or _is_taken(fullname.partition(".")[0]):
+        return None
+    return find_source(fullname)
+
+
+def is_known_module(fullname):
+    return _find(fullname) is not None
+
+
+class PackageFinder:
+    # Finds modules in the host's module sources, so imports between them
+    # (including relative ones) resolve like they would on disk
+
+    @classmethod
+    def find_spec(cls, fullname, path=None, target=None):
+        entry = _find(fullname)
+        if entry is None:
+            return None
+        _, file_path, is_package = entry
+        spec = ModuleSpec(fullname, PackageLoader, origin=file_path, is_package=is_package)
+        spec.has_location = True
+        return spec
+
+
+class PackageLoader:
+    @classmethod
+    def create_module(cls, spec):
+        return None
+
+    @classmethod
+    def exec_module(cls, module):
+        # Looked up again on every exec, so importlib.reload sees the current source
+        name = module.__spec__.name
+        entry = _find(name)
+        if entry is None:
+            raise ImportError(f"No module named '{name}'")
+        source, file_path, _ = entry
+        sandbox.register_user_module(name)
+        module.__file__ = file_path
+        module_reloader.remember(name, source)
+        code = code_compiler.compile_module(source, file_path)
+        exec(code, module.__dict__)
+
+
+def import_module(fullname):
+    if _find(fullname) is None:
+        raise ImportError(f"Module name '{fullname}' is taken by another module")
+    return importlib.import_module(fullname)
+
+
+sandbox.set_user_module_resolver(is_known_module)
+sys.meta_path.insert(0, PackageFinder)
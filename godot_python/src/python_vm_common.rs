@@ -1,186 +1,987 @@
+pub mod coroutine;
+pub mod event_loop;
+pub mod execution_budget;
+pub mod godot_math;
+pub mod introspection;
+pub mod module_reloader;
+pub mod module_source;
+pub mod object_handles;
+pub mod output;
+pub mod package_loader;
 pub mod python_converter;
+pub mod python_error;
 pub mod rust_stdout;
+pub mod sandbox_policy;
+pub mod stdin_mode;
 
-use std::collections::HashMap;
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    rc::Rc,
+};
 
+use coroutine::CoroutineStep;
+use event_loop::TaskState;
+use execution_budget::{install_budget_tick, BudgetTracker, ExecutionBudget};
+use godot_math::create_godot_math;
+use introspection::{FunctionSignature, ModuleMember};
+use module_reloader::ReloadDiff;
+use module_source::{InMemorySource, ModuleSource};
+use object_handles::{HandleId, ObjectHandles};
+use output::{BufferMode, CapturedOutput, OutputChunk, OutputMarkup, OutputSinks, OutputStream};
+use package_loader::{with_missing_packages, PackageFile};
 use python_converter::unwrap_error;
-use rust_stdout::{create_rust_stdout, rust_stdout::RustStdout};
+use python_error::PythonError;
+use rust_stdout::{create_rust_stdout, rust_stdout::RustStream};
 use rustpython_vm::{
-    builtins::{PyStr, PyStrRef},
+    builtins::{PyCode, PyStr, PyStrRef, PyTuple},
     convert::ToPyObject,
     function::FuncArgs,
-    import::import_source,
-    Interpreter, PyObjectRef, PyPayload,
+    import::{import_codeobj, import_source},
+    protocol::{PyIter, PyIterReturn},
+    scope::Scope,
+    AsObject, Interpreter, PyObjectRef, PyPayload, PyRef, PyResult, TryFromObject, VirtualMachine,
 };
+use sandbox_policy::SandboxPolicy;
+use stdin_mode::StdinMode;
+
+/// Rust modules the VM registers, `rust_stdout` backs `sys.stdout` and `sys.stderr`
+const NATIVE_MODULES: [&str; 2] = ["rust_stdout", "godot_math"];
+
+/// Python helpers the VM imports for itself, in import order. They are left out of `list_modules`.
+const HELPER_MODULES: [(&str, &str); 9] = [
+    ("sandbox", include_str!("sandbox.py")),
+    ("code_compiler", include_str!("code_compiler.py")),
+    ("module_reloader", include_str!("module_reloader.py")),
+    ("package_loader", include_str!("package_loader.py")),
+    ("introspection", include_str!("introspection.py")),
+    ("godot", include_str!("godot.py")),
+    ("stdin_override", include_str!("stdin_override.py")),
+    ("coroutine_runner", include_str!("coroutine_runner.py")),
+    ("event_loop", include_str!("event_loop.py")),
+];
 
 pub struct CommonPythonVM {
     pub interpreter: Interpreter,
-    modules: HashMap<String, PyObjectRef>,
+    /// Modules the host loaded. Loading runs Python, which may load modules in turn,
+    /// so it never stays borrowed while Python runs.
+    modules: RefCell<HashMap<String, PyObjectRef>>,
+    /// The VM's own Python modules, kept apart so loading a module can't replace one
+    helpers: HashMap<String, PyObjectRef>,
+    /// Names of the modules `add_host_module` added, reserved like the helpers.
+    /// Shared with the finder, which skips reserved names in module sources.
+    host_modules: Rc<RefCell<Vec<String>>>,
+    sessions: HashMap<String, Scope>,
+    budget_tracker: Rc<BudgetTracker>,
+    default_budget: ExecutionBudget,
+    budget_override: Cell<Option<ExecutionBudget>>,
+    /// Searched front to back by the `sys.meta_path` finder
+    module_sources: Rc<RefCell<Vec<(String, Box<dyn ModuleSource>)>>>,
+    handles: ObjectHandles,
+    /// Shared with the `RustStream`s behind `sys.stdout` and `sys.stderr`
+    output: Rc<OutputSinks>,
 }
 
 impl CommonPythonVM {
     pub fn init() -> Self {
+        Self::init_with_policy(SandboxPolicy::unrestricted())
+    }
+
+    pub fn init_with_policy(policy: SandboxPolicy) -> Self {
         let interpreter = rustpython::InterpreterConfig::new()
             .init_stdlib()
             .init_hook(Box::new(|vm| {
-                vm.add_native_module("rust_stdout".to_owned(), create_rust_stdout());
+                vm.add_native_module(NATIVE_MODULES[0].to_owned(), create_rust_stdout());
+                vm.add_native_module(NATIVE_MODULES[1].to_owned(), create_godot_math());
             }))
             .interpreter();
 
+        let output = Rc::new(OutputSinks::new());
         let stdout_override_module = interpreter.enter(|vm| {
-            let r = import_source(
-                vm,
-                "stdout_override",
-                r#"
-import sys
-from rust_stdout import rs_print, rs_print_err
+            let module = import_source(vm, "stdout_override", include_str!("stdout_override.py"))
+                .expect("stdout_override to import");
+            vm.call_method(
+                &module,
+                "set_rust_stdout",
+                (
+                    RustStream::new(OutputStream::Stdout, output.clone()).to_pyobject(vm),
+                    RustStream::new(OutputStream::Stderr, output.clone()).to_pyobject(vm),
+                ),
+            )
+            .expect("stdout_override to accept the Rust streams");
+            module
+        });
+
+        let budget_tracker = Rc::new(BudgetTracker::new());
+        let host_modules = Rc::new(RefCell::new(Vec::new()));
+        let module_sources: Rc<RefCell<Vec<(String, Box<dyn ModuleSource>)>>> =
+            Rc::new(RefCell::new(Vec::new()));
+        let mut helpers: HashMap<String, PyObjectRef> = HashMap::new();
+        helpers.insert("stdout_override".to_owned(), stdout_override_module);
 
-class CustomStream:
+        let helper_modules = interpreter.enter(|vm| {
+            install_budget_tick(vm, budget_tracker.clone());
 
-  def __init__(self, callback, start="", end=""):
-    self.callback = callback
-    self.buffer = start
-    self.start = start
-    self.end = end
+            let helper_modules: HashMap<String, PyObjectRef> = HELPER_MODULES
+                .iter()
+                .map(|(name, source)| {
+                    let module = import_source(vm, name, source)
+                        .unwrap_or_else(|_| panic!("{} to import", name));
+                    (name.to_string(), module)
+                })
+                .collect();
 
+            let sources = module_sources.clone();
+            let reserved = host_modules.clone();
+            let find_source = vm.new_function(
+                "find_source",
+                move |args: FuncArgs, vm: &VirtualMachine| -> PyResult {
+                    let (module_name,): (PyStrRef,) = args.bind(vm)?;
+                    if is_reserved_module_name(module_name.as_str(), &reserved.borrow()) {
+                        return Ok(vm.ctx.none());
+                    }
+                    let found = sources
+                        .borrow()
+                        .iter()
+                        .find_map(|(_, source)| source.find_module(module_name.as_str()));
+                    Ok(match found {
+                        Some(file) => vm
+                            .new_tuple((file.source, file.path, file.is_package))
+                            .into(),
+                        None => vm.ctx.none(),
+                    })
+                },
+            );
+            helper_modules["package_loader"]
+                .set_attr("find_source", find_source, vm)
+                .expect("package_loader to accept find_source");
 
-  def write(self, message):
-    self.buffer += message
-    if "\n" == message:
-      self.buffer += self.end
-      self.flush()
+            let to_py_list = |names: &Vec<String>| -> PyObjectRef {
+                vm.ctx
+                    .new_list(
+                        names
+                            .iter()
+                            .map(|name| vm.ctx.new_str(name.as_str()).into())
+                            .collect(),
+                    )
+                    .into()
+            };
+            let internal_modules: Vec<String> = HELPER_MODULES
+                .iter()
+                .map(|(name, _)| name.to_string())
+                .chain(["stdout_override".to_string()])
+                .collect();
+            let allowed_modules = match &policy.allowed_modules {
+                Some(modules) => to_py_list(modules),
+                None => vm.ctx.none(),
+            };
+            vm.call_method(
+                &helper_modules["sandbox"],
+                "configure",
+                (
+                    allowed_modules,
+                    to_py_list(&policy.removed_builtins),
+                    vm.ctx.new_bool(policy.allow_dunder_access).to_pyobject(vm),
+                    to_py_list(&internal_modules),
+                ),
+            )
+            .expect("sandbox policy to apply");
 
-        
-  def flush(self):
-    if self.buffer:
-      self.callback(self.buffer)
-      self.buffer = self.start
+            helper_modules
+        });
+        helpers.extend(helper_modules);
 
+        Self {
+            interpreter,
+            modules: RefCell::new(HashMap::new()),
+            helpers,
+            host_modules,
+            sessions: HashMap::new(),
+            budget_tracker,
+            default_budget: ExecutionBudget::unlimited(),
+            budget_override: Cell::new(None),
+            module_sources,
+            handles: ObjectHandles::new(),
+            output,
+        }
+    }
 
-def set_rust_stdout(rstd):
-  # Set the custom stream as the new stdout
-  sys.stdout = CustomStream(rstd.rs_print)
-  sys.stderr = CustomStream(
-    rstd.rs_print_err, start="[color=red]", end="[/color]"
-  )
+    /// Adds a native module only one host has, e.g. the engine API of the Godot build,
+    /// and re-exports its public names from `godot`, see `_export_host_module` in `godot.py`.
+    pub fn add_host_module(&self, make_module: impl FnOnce(&VirtualMachine) -> PyObjectRef) {
+        self.interpreter.enter(|vm| {
+            let module = make_module(vm);
+            let name = module
+                .get_attr("__name__", vm)
+                .and_then(|name| String::try_from_object(vm, name))
+                .expect("host modules to have a name");
+            self.host_modules.borrow_mut().push(name);
+            vm.call_method(&self.helpers["godot"], "_export_host_module", (module,))
+                .expect("godot to export the host module");
+        });
+    }
 
+    /// Lets `import` find modules in `source`. Sources added later are searched
+    /// first; adding a source under an existing name replaces it.
+    pub fn add_module_source(&self, name: String, source: Box<dyn ModuleSource>) {
+        let mut sources = self.module_sources.borrow_mut();
+        sources.retain(|(n, _)| *n != name);
+        sources.insert(0, (name, source));
+    }
 
-sys.stdout = CustomStream(rs_print)
-sys.stderr = CustomStream(rs_print_err, start="[color=red]", end="[/color]")
-"#,
-            );
+    /// Modules already imported from the source stay loaded.
+    pub fn remove_module_source(&mut self, name: &str) -> bool {
+        let mut sources = self.module_sources.borrow_mut();
+        let count = sources.len();
+        sources.retain(|(n, _)| n != name);
+        sources.len() != count
+    }
 
-            // if let Err(e) = r {
-            //     print!("Error: {:?}", e);
-            // }
-
-            match r {
-                Ok(value) => value,
-                Err(error) => {
-                    let mut s = "Error: ".to_string();
-                    error.args().into_iter().for_each({
-                        |v| {
-                            s.push_str(&format!("{:?}\n", v));
-                        }
-                    });
-                    print!("{}", s);
-                    vm.ctx.new_str(s).to_pyobject(vm)
-                }
-            }
-        });
+    /// Sets the budget applied to every later eval, module load and function call.
+    pub fn set_execution_budget(&mut self, budget: ExecutionBudget) {
+        self.default_budget = budget;
+    }
 
-        let mut modules: HashMap<String, PyObjectRef> = HashMap::new();
-        modules.insert("stdout_override".to_owned(), stdout_override_module);
+    pub fn execution_budget(&self) -> ExecutionBudget {
+        self.budget_override.get().unwrap_or(self.default_budget)
+    }
 
-        Self {
-            interpreter,
-            modules,
+    /// Runs `f` with `budget` in place of the default budget, for a single call.
+    pub fn with_execution_budget<R>(
+        &self,
+        budget: ExecutionBudget,
+        f: impl FnOnce(&Self) -> R,
+    ) -> R {
+        let previous = self.budget_override.replace(Some(budget));
+        let r = f(self);
+        self.budget_override.set(previous);
+        r
+    }
+
+    fn compile_with(
+        &self,
+        vm: &VirtualMachine,
+        function_name: &str,
+        code: &str,
+        filename: &str,
+    ) -> PyResult {
+        let compiler = self.helpers.get("code_compiler").unwrap();
+        vm.call_method(
+            compiler,
+            function_name,
+            (code.to_owned(), filename.to_owned()),
+        )
+    }
+
+    fn downcast_code(vm: &VirtualMachine, code: PyObjectRef) -> PyResult<PyRef<PyCode>> {
+        code.downcast::<PyCode>()
+            .map_err(|_| vm.new_type_error("expected a code object".to_owned()))
+    }
+
+    /// Runs statements followed by an optional trailing expression, returning the
+    /// expression's value like `run_block_expr`, but with budget instrumentation.
+    fn run_block(&self, vm: &VirtualMachine, scope: Scope, code: &str) -> PyResult {
+        let compiled = self.compile_with(vm, "compile_block", code, "<embedded>")?;
+        let compiled = compiled
+            .downcast::<PyTuple>()
+            .map_err(|_| vm.new_type_error("expected compiled code".to_owned()))?;
+        let (block, expr) = (
+            compiled.as_slice()[0].clone(),
+            compiled.as_slice()[1].clone(),
+        );
+
+        let _run = self.budget_tracker.start(self.execution_budget());
+        vm.run_code_obj(Self::downcast_code(vm, block)?, scope.clone())?;
+        if vm.is_none(&expr) {
+            Ok(vm.ctx.none())
+        } else {
+            vm.run_code_obj(Self::downcast_code(vm, expr)?, scope)
         }
     }
 
+    /// Sends what Python writes to `sys.stdout` to `closure`, one chunk per flush.
     pub fn setup_stdout<T>(&mut self, closure: T)
     where
-        T: Fn(String) + 'static,
+        T: Fn(OutputChunk) + 'static,
+    {
+        self.output
+            .set_sink(OutputStream::Stdout, Some(Box::new(closure)));
+    }
+
+    pub fn setup_stderr<T>(&mut self, closure: T)
+    where
+        T: Fn(OutputChunk) + 'static,
+    {
+        self.output
+            .set_sink(OutputStream::Stderr, Some(Box::new(closure)));
+    }
+
+    /// Replaces the sink of `stream` after flushing what the old one is owed.
+    /// `None` disconnects it, the output goes to the Godot or browser console again.
+    pub fn set_output_sink(
+        &mut self,
+        stream: OutputStream,
+        sink: Option<Box<dyn Fn(OutputChunk)>>,
+    ) {
+        self.output.flush(stream);
+        self.output.set_sink(stream, sink);
+    }
+
+    /// Shows `closure` every chunk of both streams, in addition to the sinks, e.g. to
+    /// notify listeners. Captured output is not observed.
+    pub fn set_output_observer<T>(&mut self, closure: T)
+    where
+        T: Fn(&OutputChunk) + 'static,
     {
-        let mut rust_stdout = RustStdout::new();
-        rust_stdout.set_stdout_fn(Box::new(closure));
+        self.output.set_observer(Some(Box::new(closure)));
+    }
+
+    /// Sets when `stream` passes buffered output on, see `BufferMode`.
+    pub fn set_output_buffering(&mut self, stream: OutputStream, mode: BufferMode) {
+        self.output.set_buffer_mode(stream, mode);
+    }
+
+    /// Hands what both streams still buffer to the host. Evals and calls do this when they end.
+    pub fn flush_output(&self) {
+        self.output.flush_all();
+    }
+
+    /// Runs `f` and returns what it wrote to `sys.stdout` and `sys.stderr` next to its
+    /// result. The output does not reach the sinks. Tasks `f` starts are not captured
+    /// once it returns.
+    pub fn with_captured_output<R>(&self, f: impl FnOnce(&Self) -> R) -> (R, CapturedOutput) {
+        self.output.begin_capture();
+        let r = f(self);
+        (r, self.output.end_capture())
+    }
 
-        // rust_stdout.set_stderr_fn(Box::new(|s| {
-        //     self.std_err(s);
-        // }));
+    /// Fills `OutputChunk::formatted` for both streams. `None` leaves the text as is.
+    pub fn set_output_markup(&mut self, markup: Option<Box<dyn OutputMarkup>>) {
+        self.output.set_markup(markup);
+    }
 
+    /// Routes `input()` and `sys.stdin` to the host. Lines pushed with `push_stdin`
+    /// are read first; when none is left, `request_line(prompt)` is called and may
+    /// return one right away. Otherwise `mode` decides whether `input()` fails or waits.
+    pub fn setup_stdin<T>(&mut self, request_line: T, mode: StdinMode)
+    where
+        T: Fn(String) -> Option<String> + 'static,
+    {
         self.interpreter.enter(|vm| {
-            let f = self
-                .modules
-                .get("stdout_override")
-                .unwrap()
-                .get_attr("set_rust_stdout", vm);
+            let request_line = vm.new_function(
+                "request_line",
+                move |args: FuncArgs, vm: &VirtualMachine| -> PyResult {
+                    let (prompt,): (PyStrRef,) = args.bind(vm)?;
+                    Ok(match request_line(prompt.as_str().to_owned()) {
+                        Some(line) => vm.ctx.new_str(line).into(),
+                        None => vm.ctx.none(),
+                    })
+                },
+            );
+            vm.call_method(
+                self.helpers.get("stdin_override").unwrap(),
+                "configure",
+                (request_line, mode == StdinMode::Suspending),
+            )
+            .expect("stdin_override to accept the host callback");
+        });
+    }
 
-            let rstd_pyobj = rust_stdout.to_pyobject(vm);
+    /// Stops asking the host for lines, `input()` and `sys.stdin` then only read what
+    /// `push_stdin` queued.
+    pub fn clear_stdin(&mut self, mode: StdinMode) {
+        self.interpreter.enter(|vm| {
+            vm.call_method(
+                self.helpers.get("stdin_override").unwrap(),
+                "configure",
+                (vm.ctx.none(), mode == StdinMode::Suspending),
+            )
+            .expect("stdin_override to accept no host callback");
+        });
+    }
 
-            match f {
-                Ok(f) => {
-                    let _ = f.call((rstd_pyobj,), vm);
-                }
-                Err(error) => {
-                    let _ =
-                        rstd_pyobj.call((format!("Error setting rust_stdout: {:?}", error),), vm);
-                }
-            }
+    /// Queues a line for `input()` or `sys.stdin`, or hands it to the oldest
+    /// `await input()` waiting for one.
+    pub fn push_stdin(&self, line: String) {
+        self.interpreter.enter(|vm| {
+            vm.call_method(
+                self.helpers.get("stdin_override").unwrap(),
+                "push_line",
+                (line,),
+            )
+            .expect("stdin_override to queue the line");
         });
     }
 
-    pub fn eval(&self, code: String) -> Result<PyObjectRef, String> {
+    /// Takes the oldest pushed line, for resuming a coroutine that waits for input.
+    pub fn take_stdin_line(&self) -> Option<String> {
         self.interpreter.enter(|vm| {
+            let line = vm
+                .call_method(self.helpers.get("stdin_override").unwrap(), "take_line", ())
+                .ok()?;
+            if vm.is_none(&line) {
+                return None;
+            }
+            String::try_from_object(vm, line).ok()
+        })
+    }
+
+    pub fn eval(&self, code: String) -> Result<PyObjectRef, PythonError> {
+        let r = self.interpreter.enter(|vm| {
             let scope = vm.new_scope_with_builtins();
-            let output = vm.run_block_expr(scope, &code);
+            let output = self.run_block(vm, scope, &code);
             // godot_print!("Output: {:?}", output);
 
             match output {
                 Ok(value) => Ok(value),
                 Err(error) => Err(unwrap_error(vm, error)),
             }
+        });
+        self.flush_output();
+        r
+    }
+
+    /// Creates a named session whose globals persist between `eval_in_session` calls.
+    /// Creating a session that already exists resets it.
+    pub fn create_session(&mut self, session_name: String) {
+        let scope = self.interpreter.enter(|vm| vm.new_scope_with_builtins());
+        self.sessions.insert(session_name, scope);
+    }
+
+    pub fn eval_in_session(
+        &self,
+        session_name: String,
+        code: String,
+    ) -> Result<PyObjectRef, PythonError> {
+        let r = self.interpreter.enter(|vm| {
+            let scope = match self.sessions.get(&session_name) {
+                Some(s) => s.clone(),
+                None => {
+                    return Err(PythonError::host(
+                        "LookupError",
+                        format!("Session not found: {:?}", session_name),
+                    ));
+                }
+            };
+            let output = self.run_block(vm, scope, &code);
+
+            match output {
+                Ok(value) => Ok(value),
+                Err(error) => Err(unwrap_error(vm, error)),
+            }
+        });
+        self.flush_output();
+        r
+    }
+
+    /// Returns a copy of the session globals as a dict, without `__builtins__`.
+    pub fn get_session_globals(&self, session_name: String) -> Result<PyObjectRef, PythonError> {
+        self.interpreter.enter(|vm| {
+            let scope = match self.sessions.get(&session_name) {
+                Some(s) => s,
+                None => {
+                    return Err(PythonError::host(
+                        "LookupError",
+                        format!("Session not found: {:?}", session_name),
+                    ));
+                }
+            };
+
+            let globals = vm.ctx.new_dict();
+            for (key, value) in &scope.globals {
+                if let Ok(name) = String::try_from_object(vm, key.clone()) {
+                    if name == "__builtins__" {
+                        continue;
+                    }
+                }
+                let _ = globals.set_item(&*key, value, vm);
+            }
+            Ok(globals.into())
         })
     }
 
+    /// Drops every global defined in the session, keeping the session itself alive.
+    pub fn clear_session(&mut self, session_name: String) -> bool {
+        if !self.sessions.contains_key(&session_name) {
+            return false;
+        }
+        self.create_session(session_name);
+        true
+    }
+
+    pub fn drop_session(&mut self, session_name: String) -> bool {
+        self.sessions.remove(&session_name).is_some()
+    }
+
+    pub fn list_sessions(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.sessions.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Fails for the names of the VM's own modules, e.g. `sandbox` or `godot`,
+    /// and for modules inside them.
+    fn check_module_name(&self, module_name: &str) -> Result<(), PythonError> {
+        if is_reserved_module_name(module_name, &self.host_modules.borrow()) {
+            return Err(PythonError::host(
+                "ValueError",
+                format!("Module name {:?} is reserved by the VM", module_name),
+            ));
+        }
+        Ok(())
+    }
+
     pub fn load_module(
-        &mut self,
+        &self,
         module_name: String,
         module_code: String,
-    ) -> Result<PyObjectRef, String> {
-        self.interpreter.enter(|vm| {
-            let result = import_source(vm, &module_name, &module_code);
+    ) -> Result<PyObjectRef, PythonError> {
+        self.check_module_name(&module_name)?;
+        let r = self.interpreter.enter(|vm| {
+            let result = vm
+                .call_method(
+                    self.helpers.get("sandbox").unwrap(),
+                    "register_user_module",
+                    (module_name.clone(),),
+                )
+                .and_then(|_| self.compile_with(vm, "compile_module", &module_code, &module_name))
+                .and_then(|code| {
+                    let code = Self::downcast_code(vm, code)?;
+                    let _run = self.budget_tracker.start(self.execution_budget());
+                    import_codeobj(vm, &module_name, code, false)
+                })
+                .and_then(|module| {
+                    vm.call_method(
+                        self.helpers.get("module_reloader").unwrap(),
+                        "remember",
+                        (module_name.clone(), module_code.clone()),
+                    )?;
+                    Ok(module)
+                });
             // godot_print!("Result: {:?}", result);
 
             match result {
-                Ok(value) => {
-                    self.modules.insert(module_name, value.clone());
-                    Ok(value)
-                }
+                Ok(value) => Ok(value),
+                Err(error) => Err(unwrap_error(vm, error)),
+            }
+        });
+
+        self.flush_output();
+
+        if let Ok(value) = &r {
+            self.modules.borrow_mut().insert(module_name, value.clone());
+        }
+        r
+    }
+
+    /// Re-executes a loaded module with new source, keeping the same module object.
+    ///
+    /// Globals the new source lists in `__persist__` keep their old value, and
+    /// functions or classes other modules (and sessions) imported with
+    /// `from module import x` are replaced by the new definitions. If the new
+    /// source raises, the module is left as it was.
+    pub fn reload_module(
+        &self,
+        module_name: String,
+        module_code: String,
+    ) -> Result<ReloadDiff, PythonError> {
+        if !self.modules.borrow().contains_key(&module_name) {
+            return Err(PythonError::host(
+                "ModuleNotFoundError",
+                format!("Module not found: {:?}", module_name),
+            ));
+        }
+
+        let r = self.interpreter.enter(|vm| {
+            let session_globals: Vec<PyObjectRef> = self
+                .sessions
+                .values()
+                .map(|scope| scope.globals.clone().into())
+                .collect();
+
+            let _run = self.budget_tracker.start(self.execution_budget());
+            let result = vm
+                .call_method(
+                    self.helpers.get("module_reloader").unwrap(),
+                    "reload",
+                    (
+                        module_name.clone(),
+                        module_code,
+                        vm.ctx.new_list(session_globals),
+                    ),
+                )
+                .and_then(|diff| ReloadDiff::from_py_dict(vm, &diff));
+
+            match result {
+                Ok(diff) => Ok(diff),
                 Err(error) => Err(unwrap_error(vm, error)),
             }
+        });
+
+        self.flush_output();
+        r
+    }
+
+    /// Registers the files of a package as a module source named after the
+    /// package and imports them, parents first. Imports between them work,
+    /// relative ones included. Returns the top-level package module.
+    pub fn load_package(
+        &self,
+        package_name: String,
+        files: Vec<PackageFile>,
+    ) -> Result<PyObjectRef, PythonError> {
+        self.check_module_name(&package_name)?;
+        for file in &files {
+            self.check_module_name(&file.module_name)?;
+        }
+        let mut files = with_missing_packages(files);
+        files.sort_by_key(|f| f.module_name.matches('.').count());
+
+        let module_names: Vec<String> = files.iter().map(|f| f.module_name.clone()).collect();
+        self.add_module_source(
+            package_name.clone(),
+            Box::new(InMemorySource::from_files(files)),
+        );
+
+        let r = self.interpreter.enter(|vm| {
+            let loader = self.helpers.get("package_loader").unwrap();
+            let _run = self.budget_tracker.start(self.execution_budget());
+            let mut loaded = Vec::new();
+            for module_name in &module_names {
+                match vm.call_method(loader, "import_module", (module_name.clone(),)) {
+                    Ok(module) => loaded.push((module_name.clone(), module)),
+                    Err(error) => return Err(unwrap_error(vm, error)),
+                }
+            }
+            Ok(loaded)
+        });
+
+        self.flush_output();
+        let loaded = r?;
+        let mut modules = self.modules.borrow_mut();
+        for (module_name, module) in loaded {
+            modules.insert(module_name, module);
+        }
+        match modules.get(&package_name) {
+            Some(package) => Ok(package.clone()),
+            None => Err(PythonError::host(
+                "ModuleNotFoundError",
+                format!("Package not found: {:?}", package_name),
+            )),
+        }
+    }
+
+    /// Names of the modules loaded by the host, sorted.
+    pub fn list_modules(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.modules.borrow().keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    fn introspect<R>(
+        &self,
+        module_name: &str,
+        f: impl FnOnce(&VirtualMachine, &PyObjectRef, &PyObjectRef) -> PyResult<R>,
+    ) -> Result<R, PythonError> {
+        let module = match self.modules.borrow().get(module_name).cloned() {
+            Some(m) => m,
+            None => {
+                return Err(PythonError::host(
+                    "ModuleNotFoundError",
+                    format!("Module not found: {:?}", module_name),
+                ));
+            }
+        };
+
+        self.interpreter.enter(|vm| {
+            let introspection = self.helpers.get("introspection").unwrap();
+            f(vm, introspection, &module).map_err(|error| unwrap_error(vm, error))
+        })
+    }
+
+    /// The public globals of a loaded module, sorted by name.
+    pub fn list_members(&self, module_name: String) -> Result<Vec<ModuleMember>, PythonError> {
+        self.introspect(&module_name, |vm, introspection, module| {
+            let members = vm.call_method(introspection, "list_members", (module.clone(),))?;
+            ModuleMember::from_py_list(vm, members)
+        })
+    }
+
+    /// Describes the parameters of a function, method (`Class.method`) or class.
+    pub fn get_signature(
+        &self,
+        module_name: String,
+        function_name: String,
+    ) -> Result<FunctionSignature, PythonError> {
+        self.introspect(&module_name, |vm, introspection, module| {
+            let signature = vm.call_method(
+                introspection,
+                "get_signature",
+                (module.clone(), function_name),
+            )?;
+            FunctionSignature::from_py_tuple(vm, signature)
+        })
+    }
+
+    /// The objects held for the host. Host handle types keep a clone to release
+    /// their object when they are dropped.
+    pub fn handles(&self) -> &ObjectHandles {
+        &self.handles
+    }
+
+    /// Keeps `value` alive for the host, see `ObjectHandles::register`.
+    pub fn register_handle(&self, value: PyObjectRef) -> HandleId {
+        self.handles.register(value)
+    }
+
+    pub fn release_handle(&self, handle: HandleId) -> bool {
+        self.handles.release(handle)
+    }
+
+    pub fn get_handle_object(&self, handle: HandleId) -> Result<PyObjectRef, PythonError> {
+        match self.handles.get(handle) {
+            Some(object) => Ok(object),
+            None => Err(PythonError::host(
+                "ReferenceError",
+                format!("Object handle {} was released", handle),
+            )),
+        }
+    }
+
+    fn with_handle<R>(
+        &self,
+        handle: HandleId,
+        f: impl FnOnce(&VirtualMachine, PyObjectRef) -> PyResult<R>,
+    ) -> Result<R, PythonError> {
+        let object = self.get_handle_object(handle)?;
+        self.interpreter
+            .enter(|vm| f(vm, object).map_err(|error| unwrap_error(vm, error)))
+    }
+
+    pub fn get_handle_attr(
+        &self,
+        handle: HandleId,
+        name: String,
+    ) -> Result<PyObjectRef, PythonError> {
+        self.with_handle(handle, |vm, object| {
+            let attr_name: PyStrRef = PyStr::from(name).into_ref(&vm.ctx);
+            object.get_attr(&attr_name, vm)
+        })
+    }
+
+    pub fn set_handle_attr(
+        &self,
+        handle: HandleId,
+        name: String,
+        value: PyObjectRef,
+    ) -> Result<(), PythonError> {
+        self.with_handle(handle, |vm, object| {
+            let attr_name: PyStrRef = PyStr::from(name).into_ref(&vm.ctx);
+            object.set_attr(&attr_name, value, vm)
+        })
+    }
+
+    /// Calls the object itself, e.g. a function or a class.
+    pub fn call_handle(
+        &self,
+        handle: HandleId,
+        f_args: FuncArgs,
+    ) -> Result<PyObjectRef, PythonError> {
+        let r = self.with_handle(handle, |vm, object| {
+            let _run = self.budget_tracker.start(self.execution_budget());
+            object.call_with_args(f_args, vm)
+        });
+        self.flush_output();
+        r
+    }
+
+    pub fn call_handle_method(
+        &self,
+        handle: HandleId,
+        method_name: String,
+        f_args: FuncArgs,
+    ) -> Result<PyObjectRef, PythonError> {
+        let r = self.with_handle(handle, |vm, object| {
+            let attr_name: PyStrRef = PyStr::from(method_name).into_ref(&vm.ctx);
+            let method = object.get_attr(&attr_name, vm)?;
+            let _run = self.budget_tracker.start(self.execution_budget());
+            method.call_with_args(f_args, vm)
+        });
+        self.flush_output();
+        r
+    }
+
+    /// Registers `iter(object)` and returns its handle, for use with `next_handle_item`.
+    pub fn iter_handle(&self, handle: HandleId) -> Result<HandleId, PythonError> {
+        self.with_handle(handle, |vm, object| {
+            let iterator: PyObjectRef = object.get_iter(vm)?.into();
+            Ok(self.handles.register(iterator))
+        })
+    }
+
+    /// The next item of an iterator handle, or `None` once it is exhausted.
+    pub fn next_handle_item(&self, iterator: HandleId) -> Result<Option<PyObjectRef>, PythonError> {
+        self.with_handle(iterator, |vm, object| {
+            let _run = self.budget_tracker.start(self.execution_budget());
+            match PyIter::new(object).next(vm)? {
+                PyIterReturn::Return(item) => Ok(Some(item)),
+                PyIterReturn::StopIteration(_) => Ok(None),
+            }
+        })
+    }
+
+    pub fn handle_repr(&self, handle: HandleId) -> Result<String, PythonError> {
+        self.with_handle(handle, |vm, object| {
+            Ok(object.repr(vm)?.as_str().to_owned())
+        })
+    }
+
+    pub fn handle_type_name(&self, handle: HandleId) -> Result<String, PythonError> {
+        self.with_handle(handle, |_, object| Ok(object.class().name().to_string()))
+    }
+
+    /// Advances the event loop by `delta` seconds of game time and runs the
+    /// callbacks that are ready, resuming the tasks whose awaits completed.
+    /// Hosts call it once per frame.
+    pub fn poll(&self, delta: f64) -> Result<(), PythonError> {
+        let r = self.interpreter.enter(|vm| {
+            let _run = self.budget_tracker.start(self.execution_budget());
+            vm.call_method(self.helpers.get("event_loop").unwrap(), "poll", (delta,))
+                .map(|_| ())
+                .map_err(|error| unwrap_error(vm, error))
+        });
+        self.flush_output();
+        r
+    }
+
+    /// True for the `asyncio.Task`s `call_python_function` returns for coroutines.
+    pub fn is_task(&self, value: &PyObjectRef) -> bool {
+        self.interpreter.enter(|vm| {
+            vm.call_method(
+                self.helpers.get("event_loop").unwrap(),
+                "is_task",
+                (value.clone(),),
+            )
+            .and_then(|r| r.try_to_bool(vm))
+            .unwrap_or(false)
+        })
+    }
+
+    /// The outcome of a task handle, or `None` while it is pending. A task that
+    /// raised gives back what it raised, a cancelled one a `CancelledError`.
+    pub fn task_result(&self, handle: HandleId) -> Option<Result<PyObjectRef, PythonError>> {
+        let state = self.with_handle(handle, |vm, task| {
+            let state = vm.call_method(
+                self.helpers.get("event_loop").unwrap(),
+                "task_state",
+                (task,),
+            )?;
+            TaskState::from_py_tuple(vm, state)
+        });
+        match state {
+            Ok(TaskState::Pending) => None,
+            Ok(TaskState::Done(value)) => Some(Ok(value)),
+            Ok(TaskState::Cancelled) => Some(Err(PythonError::host(
+                "CancelledError",
+                "The task was cancelled".to_owned(),
+            ))),
+            Err(error) => Some(Err(error)),
+        }
+    }
+
+    /// Calls a generator function or `async def` and registers the generator or
+    /// coroutine it returns, for use with `resume_handle`.
+    pub fn start_coroutine(
+        &self,
+        module_name: String,
+        function_name: String,
+        f_args: FuncArgs,
+    ) -> Result<HandleId, PythonError> {
+        let coroutine = self.call_function(module_name, function_name.clone(), f_args)?;
+        self.interpreter.enter(|vm| {
+            let runner = self.helpers.get("coroutine_runner").unwrap();
+            let is_coroutine = vm
+                .call_method(runner, "is_coroutine", (coroutine.clone(),))
+                .and_then(|r| r.try_to_bool(vm))
+                .map_err(|error| unwrap_error(vm, error))?;
+            if !is_coroutine {
+                return Err(PythonError::host(
+                    "TypeError",
+                    format!(
+                        "{} returned {}, not a generator or coroutine",
+                        function_name,
+                        coroutine.class().name()
+                    ),
+                ));
+            }
+            Ok(self.handles.register(coroutine))
         })
     }
 
+    /// Sends `value` into a generator or coroutine handle and runs it to its next
+    /// `yield` (or `await` of a `godot.wait_for_signal`). The first resume of a
+    /// fresh one must send `None`, like Python's `send`.
+    pub fn resume_handle(
+        &self,
+        handle: HandleId,
+        value: PyObjectRef,
+    ) -> Result<CoroutineStep, PythonError> {
+        let r = self.with_handle(handle, |vm, coroutine| {
+            let runner = self.helpers.get("coroutine_runner").unwrap();
+            let _run = self.budget_tracker.start(self.execution_budget());
+            let step = vm.call_method(runner, "step", (coroutine, value))?;
+            CoroutineStep::from_py_tuple(vm, step)
+        });
+        self.flush_output();
+        r
+    }
+
+    /// Calls a function of a loaded module. A coroutine it returns, i.e. the
+    /// function is an `async def`, is scheduled on the event loop and its
+    /// `asyncio.Task` is returned instead, see `poll`.
     pub fn call_python_function(
         &self,
         module_name: String,
         function_name: String,
         f_args: FuncArgs,
-    ) -> Result<PyObjectRef, String> {
+    ) -> Result<PyObjectRef, PythonError> {
+        let value = self.call_function(module_name, function_name, f_args)?;
         self.interpreter.enter(|vm| {
-            let module_r = self.modules.get(&module_name);
+            vm.call_method(
+                self.helpers.get("event_loop").unwrap(),
+                "schedule",
+                (value,),
+            )
+            .map_err(|error| unwrap_error(vm, error))
+        })
+    }
+
+    fn call_function(
+        &self,
+        module_name: String,
+        function_name: String,
+        f_args: FuncArgs,
+    ) -> Result<PyObjectRef, PythonError> {
+        let r = self.interpreter.enter(|vm| {
+            let module_r = self.modules.borrow().get(&module_name).cloned();
             let module = match module_r {
                 Some(m) => m,
                 None => {
-                    return Err(format!("Error: Module not found: {:?}", module_name));
+                    return Err(PythonError::host(
+                        "ModuleNotFoundError",
+                        format!("Module not found: {:?}", module_name),
+                    ));
                 }
             };
 
             let attr_name: PyStrRef = PyStr::from(function_name).into_ref(&vm.ctx);
             // let scope = virt.new_scope_with_builtins();
-            let exec_fn = module.get_attr(&attr_name, vm).unwrap();
+            let exec_fn = match module.get_attr(&attr_name, vm) {
+                Ok(f) => f,
+                Err(error) => return Err(unwrap_error(vm, error)),
+            };
 
+            let _run = self.budget_tracker.start(self.execution_budget());
             let result = exec_fn.call_with_args(f_args, vm);
 
             match result {
@@ -189,13 +990,39 @@ sys.stderr = CustomStream(rs_print_err, start="[color=red]", end="[/color]")
             }
 
             // Variant::from("Success")
-        })
+        });
+        self.flush_output();
+        r
     }
 }
 
+/// Tells whether the top-level package of `module_name` is one of the VM's
+/// helpers, native modules or `host_modules`.
+fn is_reserved_module_name(module_name: &str, host_modules: &[String]) -> bool {
+    let top_level = module_name.split('.').next().unwrap_or_default();
+    HELPER_MODULES.iter().any(|(name, _)| *name == top_level)
+        || top_level == "stdout_override"
+        || NATIVE_MODULES.contains(&top_level)
+        || host_modules.iter().any(|name| name == top_level)
+}
+
 #[cfg(test)]
 pub mod tests {
-    use rustpython_vm::{builtins::PyInt, function::KwArgs, TryFromObject};
+    use std::io::{Cursor, Write};
+
+    use introspection::{MemberKind, ParameterKind};
+    use module_source::ZipSource;
+    use output::BBCodeMarkup;
+    use proptest::{
+        collection::{btree_map, btree_set, vec},
+        prelude::*,
+        test_runner::{Config, TestCaseError, TestRunner},
+    };
+    use python_converter::{classify_py_value, py_function_name, PyValue};
+    use rustpython_vm::{
+        builtins::{PyDict, PyInt},
+        function::KwArgs,
+    };
     use wasm_bindgen_test::wasm_bindgen_test;
 
     use super::*;
@@ -231,13 +1058,13 @@ rs_print
         let r2 = common_vm
             .eval(
                 r#"
-from stdout_override import CustomStream
-CustomStream
+import sys
+type(sys.stdout)
 "#
                 .to_string(),
             )
             .unwrap();
-        assert_eq!(format!("{:?}", r2), "[PyObject [PyType CustomStream]]");
+        assert_eq!(format!("{:?}", r2), "[PyObject [PyType RustStream]]");
     }
 
     #[test]
@@ -257,37 +1084,1661 @@ CustomStream
     }
 
     #[test]
-    fn test_load_module() {
-        test_load_module_common()
+    fn test_sessions() {
+        test_sessions_common()
     }
     #[wasm_bindgen_test]
-    fn test_load_module_web() {
-        test_load_module_common()
+    fn test_sessions_web() {
+        test_sessions_common()
     }
-    fn test_load_module_common() {
+    fn test_sessions_common() {
         let mut common_vm = CommonPythonVM::init();
+        common_vm.create_session("console".to_string());
+
         let _ = common_vm
+            .eval_in_session("console".to_string(), "x = 40".to_string())
+            .unwrap();
+        let r = common_vm
+            .eval_in_session("console".to_string(), "x + 2".to_string())
+            .unwrap();
+        let globals = common_vm
+            .get_session_globals("console".to_string())
+            .unwrap();
+        common_vm.interpreter.enter(|vm| {
+            assert_eq!(i64::try_from_object(vm, r).unwrap(), 42);
+            let globals = globals.downcast::<PyDict>().unwrap();
+            assert!(globals.contains_key("x", vm));
+            assert!(!globals.contains_key("__builtins__", vm));
+        });
+
+        // Plain eval does not see session globals
+        assert!(common_vm.eval("x".to_string()).is_err());
+
+        assert!(common_vm.clear_session("console".to_string()));
+        assert!(common_vm
+            .eval_in_session("console".to_string(), "x".to_string())
+            .is_err());
+
+        assert_eq!(common_vm.list_sessions(), vec!["console".to_string()]);
+        assert!(common_vm.drop_session("console".to_string()));
+        assert!(!common_vm.drop_session("console".to_string()));
+        assert!(common_vm
+            .eval_in_session("console".to_string(), "1".to_string())
+            .is_err());
+    }
+
+    #[test]
+    fn test_python_error() {
+        test_python_error_common()
+    }
+    #[wasm_bindgen_test]
+    fn test_python_error_web() {
+        test_python_error_common()
+    }
+    fn test_python_error_common() {
+        let common_vm = CommonPythonVM::init();
+        let _ = common_vm
+            .load_module(
+                "error_module".to_string(),
+                r#"
+class GameError(Exception):
+  pass
+
+def inner():
+  raise KeyError("missing")
+
+def outer():
+  try:
+    inner()
+  except KeyError as e:
+    raise GameError("outer failed") from e
+        "#
+                .to_string(),
+            )
+            .unwrap();
+
+        let error = common_vm
+            .call_python_function(
+                "error_module".to_string(),
+                "outer".to_string(),
+                FuncArgs::default(),
+            )
+            .unwrap_err();
+        assert_eq!(error.type_name, "GameError");
+        assert_eq!(error.module, "error_module");
+        assert_eq!(error.message, "outer failed");
+        assert_eq!(error.traceback.last().unwrap().function, "outer");
+
+        let cause = error.cause.as_ref().unwrap();
+        assert_eq!(cause.type_name, "KeyError");
+        assert_eq!(cause.module, "builtins");
+        let functions: Vec<&str> = cause
+            .traceback
+            .iter()
+            .map(|frame| frame.function.as_str())
+            .collect();
+        assert_eq!(functions, vec!["outer", "inner"]);
+        assert!(error
+            .to_string()
+            .contains("The above exception was the direct cause"));
+
+        let syntax_error = common_vm.eval("1 +".to_string()).unwrap_err();
+        assert_eq!(syntax_error.type_name, "SyntaxError");
+        assert_eq!(syntax_error.traceback.len(), 1);
+
+        let missing = common_vm
+            .call_python_function(
+                "missing_module".to_string(),
+                "f".to_string(),
+                FuncArgs::default(),
+            )
+            .unwrap_err();
+        assert_eq!(missing.type_name, "ModuleNotFoundError");
+    }
+
+    #[test]
+    fn test_execution_budget() {
+        test_execution_budget_common()
+    }
+    #[wasm_bindgen_test]
+    fn test_execution_budget_web() {
+        test_execution_budget_common()
+    }
+    fn test_execution_budget_common() {
+        let mut common_vm = CommonPythonVM::init();
+        common_vm.set_execution_budget(ExecutionBudget::from_limits(1000, 0));
+
+        let error = common_vm
+            .eval("while True:\n  pass".to_string())
+            .unwrap_err();
+        assert_eq!(error.type_name, "TimeoutError");
+
+        // Budget errors can be caught by the running code
+        let caught = common_vm
+            .eval(
+                r#"
+caught = False
+try:
+  while True:
+    pass
+except TimeoutError:
+  caught = True
+caught
+"#
+                .to_string(),
+            )
+            .unwrap();
+
+        // The VM stays usable afterwards
+        let r = common_vm
+            .eval("sum([i for i in range(10)])".to_string())
+            .unwrap();
+        common_vm.interpreter.enter(|vm| {
+            assert!(bool::try_from_object(vm, caught).unwrap());
+            assert_eq!(i64::try_from_object(vm, r).unwrap(), 45);
+        });
+
+        let _ = common_vm
+            .load_module(
+                "budget_module".to_string(),
+                r#"
+def spin():
+  "Loops forever"
+  while True:
+    pass
+        "#
+                .to_string(),
+            )
+            .unwrap();
+        let doc = common_vm
+            .eval("from budget_module import spin\nspin.__doc__".to_string())
+            .unwrap();
+        common_vm.interpreter.enter(|vm| {
+            assert_eq!(String::try_from_object(vm, doc).unwrap(), "Loops forever");
+        });
+
+        common_vm.set_execution_budget(ExecutionBudget::unlimited());
+        let error =
+            common_vm.with_execution_budget(ExecutionBudget::from_limits(0, 20), |common_vm| {
+                common_vm.call_python_function(
+                    "budget_module".to_string(),
+                    "spin".to_string(),
+                    FuncArgs::default(),
+                )
+            });
+        assert_eq!(error.unwrap_err().type_name, "TimeoutError");
+        assert!(common_vm.execution_budget().is_unlimited());
+    }
+
+    #[test]
+    fn test_nested_budget() {
+        test_nested_budget_common()
+    }
+    #[wasm_bindgen_test]
+    fn test_nested_budget_web() {
+        test_nested_budget_common()
+    }
+    fn test_nested_budget_common() {
+        let mut common_vm = CommonPythonVM::init();
+        common_vm.set_execution_budget(ExecutionBudget::from_limits(1000, 0));
+        let common_vm = Rc::new(common_vm);
+        let host_vm = Rc::downgrade(&common_vm);
+        common_vm.add_host_module(move |vm| {
+            let module = vm.new_module("host_api", vm.ctx.new_dict(), None);
+            let reenter = vm.new_function(
+                "reenter",
+                move |_args: FuncArgs, vm: &VirtualMachine| -> PyResult {
+                    host_vm
+                        .upgrade()
+                        .unwrap()
+                        .eval("0".to_string())
+                        .map_err(|error| vm.new_runtime_error(error.to_string()))
+                },
+            );
+            module.as_object().set_attr("reenter", reenter, vm).unwrap();
+            module.into()
+        });
+
+        // Calls back into the VM count against the running call's budget
+        let error = common_vm
+            .eval("import host_api\nwhile True:\n  host_api.reenter()".to_string())
+            .unwrap_err();
+        assert_eq!(error.type_name, "TimeoutError");
+        // The next call starts with a fresh budget
+        assert!(common_vm
+            .eval("sum([i for i in range(10)])".to_string())
+            .is_ok());
+    }
+
+    #[test]
+    fn test_budget_tick_rebinding() {
+        test_budget_tick_rebinding_common()
+    }
+    #[wasm_bindgen_test]
+    fn test_budget_tick_rebinding_web() {
+        test_budget_tick_rebinding_common()
+    }
+    fn test_budget_tick_rebinding_common() {
+        let mut common_vm = CommonPythonVM::init();
+        common_vm.set_execution_budget(ExecutionBudget::from_limits(1000, 0));
+
+        // Rebinding the tick is a syntax error, and dynamic ways around that
+        // don't reach the tick the instrumented code calls
+        for code in [
+            "__budget_tick__ = lambda: None",
+            "import builtins
+builtins.__budget_tick__ = None",
+            "def __sandbox_check_import__(name): pass",
+            "from os import path as __budget_tracker__",
+            "for __budget_tick__ in []: pass",
+        ] {
+            let error = common_vm.eval(code.to_string()).unwrap_err();
+            assert_eq!(error.type_name, "SyntaxError", "{}", code);
+        }
+        for code in [
+            "globals()['__budget_tick__'] = lambda: None
+while True: pass",
+            "import builtins
+setattr(builtins, '__budget_tick__', lambda: None)
+while True: pass",
+            "exec('while True: pass')",
+            "eval('[i for i in iter(int, 1)]')",
+            "exec(compile('while True: pass', 'spin', 'exec'))",
+            // Code can't pass for a stdlib module by renaming itself
+            "__name__ = 'json'
+exec('while True: pass')",
+            "__name__ = 'json'
+exec(compile('while True: pass', 'spin', 'exec'))",
+        ] {
+            let error = common_vm.eval(code.to_string()).unwrap_err();
+            assert_eq!(error.type_name, "TimeoutError", "{}", code);
+        }
+
+        // Removing the tracker stops the code instead of lifting the budget
+        let error = common_vm
+            .eval(
+                "import builtins
+delattr(builtins, '__budget_tracker__')
+while True: pass"
+                    .to_string(),
+            )
+            .unwrap_err();
+        assert_eq!(error.type_name, "RuntimeError");
+    }
+
+    #[test]
+    fn test_add_host_module() {
+        test_add_host_module_common()
+    }
+    #[wasm_bindgen_test]
+    fn test_add_host_module_web() {
+        test_add_host_module_common()
+    }
+    fn test_add_host_module_common() {
+        let make_module = |vm: &VirtualMachine| -> PyObjectRef {
+            let module = vm.new_module("host_api", vm.ctx.new_dict(), None);
+            module
+                .as_object()
+                .set_attr("answer", vm.ctx.new_int(42), vm)
+                .unwrap();
+            module.into()
+        };
+
+        let common_vm = CommonPythonVM::init();
+        common_vm.add_host_module(make_module);
+        for code in [
+            "from godot import answer\nanswer",
+            "import godot\ngodot.answer",
+            "import host_api\nhost_api.answer",
+        ] {
+            let r = common_vm.eval(code.to_string()).unwrap();
+            common_vm.interpreter.enter(|vm| {
+                assert_eq!(i64::try_from_object(vm, r).unwrap(), 42, "{}", code);
+            });
+        }
+
+        // Only exported when the sandbox allows importing it
+        let common_vm = CommonPythonVM::init_with_policy(SandboxPolicy::restricted());
+        common_vm.add_host_module(make_module);
+        let error = common_vm
+            .eval("import godot\ngodot.answer".to_string())
+            .unwrap_err();
+        assert_eq!(error.type_name, "AttributeError");
+        let error = common_vm.eval("import host_api".to_string()).unwrap_err();
+        assert_eq!(error.type_name, "ImportError");
+    }
+
+    #[test]
+    fn test_sandbox_policy() {
+        test_sandbox_policy_common()
+    }
+    #[wasm_bindgen_test]
+    fn test_sandbox_policy_web() {
+        test_sandbox_policy_common()
+    }
+    fn test_sandbox_policy_common() {
+        let common_vm = CommonPythonVM::init_with_policy(SandboxPolicy {
+            allowed_modules: Some(vec!["math".to_string(), "json".to_string()]),
+            removed_builtins: vec!["open".to_string(), "exec".to_string()],
+            allow_dunder_access: false,
+        });
+
+        let r = common_vm
+            .eval("import math\nmath.floor(2.5)".to_string())
+            .unwrap();
+        common_vm.interpreter.enter(|vm| {
+            assert_eq!(i64::try_from_object(vm, r).unwrap(), 2);
+        });
+        // Allowed modules may import anything they need internally
+        assert!(common_vm
+            .eval("import json\njson.dumps({'a': 1})".to_string())
+            .is_ok());
+
+        for blocked in [
+            "import os",
+            "import os.path",
+            "from socket import socket",
+            "import subprocess",
+        ] {
+            let error = common_vm.eval(blocked.to_string()).unwrap_err();
+            assert_eq!(error.type_name, "ImportError", "{}", blocked);
+        }
+
+        // Blocked imports can be handled like any other ImportError
+        let caught = common_vm
+            .eval(
+                r#"
+try:
+  import os
+  caught = False
+except ImportError:
+  caught = True
+caught
+"#
+                .to_string(),
+            )
+            .unwrap();
+        common_vm.interpreter.enter(|vm| {
+            assert!(bool::try_from_object(vm, caught).unwrap());
+        });
+
+        assert_eq!(
+            common_vm
+                .eval("open('secrets.txt')".to_string())
+                .unwrap_err()
+                .type_name,
+            "NameError"
+        );
+        assert_eq!(
+            common_vm
+                .eval("exec('1')".to_string())
+                .unwrap_err()
+                .type_name,
+            "NameError"
+        );
+        assert_eq!(
+            common_vm
+                .eval("().__class__.__bases__".to_string())
+                .unwrap_err()
+                .type_name,
+            "SyntaxError"
+        );
+        assert_eq!(
+            common_vm
+                .eval("getattr((), '__class__')".to_string())
+                .unwrap_err()
+                .type_name,
+            "AttributeError"
+        );
+
+        // Modules loaded by the host are always importable
+        let _ = common_vm
+            .load_module(
+                "player_helpers".to_string(),
+                "import math\nSPEED = math.floor(3.7)".to_string(),
+            )
+            .unwrap();
+        let speed = common_vm
+            .eval("from player_helpers import SPEED\nSPEED".to_string())
+            .unwrap();
+        common_vm.interpreter.enter(|vm| {
+            assert_eq!(i64::try_from_object(vm, speed).unwrap(), 3);
+        });
+        assert_eq!(
+            common_vm
+                .load_module("bad_helpers".to_string(), "import os".to_string())
+                .unwrap_err()
+                .type_name,
+            "ImportError"
+        );
+    }
+
+    #[test]
+    fn test_sandbox_escapes() {
+        test_sandbox_escapes_common()
+    }
+    #[wasm_bindgen_test]
+    fn test_sandbox_escapes_web() {
+        test_sandbox_escapes_common()
+    }
+    fn test_sandbox_escapes_common() {
+        let common_vm = CommonPythonVM::init_with_policy(SandboxPolicy::restricted());
+        for (escape, type_name) in [
+            ("import typing\ntyping.sys.modules['os']", "AttributeError"),
+            ("import random\nrandom._os", "AttributeError"),
+            ("import collections\ncollections._sys", "AttributeError"),
+            ("import random\ngetattr(random, '_os')", "AttributeError"),
+            ("from typing import sys", "ImportError"),
+            ("from random import _os", "ImportError"),
+            ("import operator", "ImportError"),
+            ("import godot\ngodot._sandbox", "AttributeError"),
+            // Imports are checked by the file the code came from, not by `__name__`
+            ("__name__ = 'json'\nfrom typing import sys", "ImportError"),
+        ] {
+            let error = common_vm.eval(escape.to_string()).unwrap_err();
+            assert_eq!(error.type_name, type_name, "{}", escape);
+        }
+
+        // Module sources can't stand in for the stdlib or the VM's helpers
+        common_vm.add_module_source(
+            "shadows".to_string(),
+            Box::new(InMemorySource::from_files(
+                ["sys", "os", "shutil", "sandbox"]
+                    .iter()
+                    .map(|name| PackageFile {
+                        module_name: name.to_string(),
+                        path: format!("memory://{}.py", name),
+                        source: "SHADOW = True\n".to_string(),
+                        is_package: *name == "os",
+                    })
+                    .collect(),
+            )),
+        );
+        for name in ["sys", "os", "shutil", "sandbox"] {
+            let error = common_vm.eval(format!("import {}", name)).unwrap_err();
+            assert_eq!(error.type_name, "ImportError", "{}", name);
+        }
+        let error = common_vm
+            .load_package(
+                "shutil".to_string(),
+                vec![PackageFile {
+                    module_name: "shutil".to_string(),
+                    path: "memory://shutil.py".to_string(),
+                    source: "SHADOW = True\n".to_string(),
+                    is_package: false,
+                }],
+            )
+            .unwrap_err();
+        assert_eq!(error.type_name, "ImportError");
+
+        // Public attributes, allowed submodules and private attributes of objects still work
+        let r = common_vm
+            .eval(
+                r#"
+import json
+import collections
+class Counter:
+  def __init__(self):
+    self._count = 2
+json.decoder.JSONDecoder().decode("40") + Counter()._count + len(collections.OrderedDict())
+"#
+                .to_string(),
+            )
+            .unwrap();
+        common_vm.interpreter.enter(|vm| {
+            assert_eq!(i64::try_from_object(vm, r).unwrap(), 42);
+        });
+
+        // The VM's helpers stay hidden even when every module is allowed
+        let common_vm = CommonPythonVM::init_with_policy(SandboxPolicy {
+            allowed_modules: None,
+            removed_builtins: vec!["open".to_string()],
+            allow_dunder_access: true,
+        });
+        for helper in ["sandbox", "code_compiler", "stdout_override"] {
+            let error = common_vm.eval(format!("import {}", helper)).unwrap_err();
+            assert_eq!(error.type_name, "ImportError", "{}", helper);
+        }
+        assert!(common_vm.eval("import os".to_string()).is_ok());
+    }
+
+    #[test]
+    fn test_load_package() {
+        test_load_package_common()
+    }
+    #[wasm_bindgen_test]
+    fn test_load_package_web() {
+        test_load_package_common()
+    }
+    fn test_load_package_common() {
+        let common_vm = CommonPythonVM::init();
+        let files = vec![
+            (
+                "__init__.py",
+                "from . import util\nfrom .util import double\n",
+            ),
+            ("util.py", "def double(x):\n  return x * 2\n"),
+            (
+                "ai/brain.py",
+                "from ..util import double\n\ndef think():\n  return double(21)\n",
+            ),
+            ("README.md", "not python"),
+        ]
+        .into_iter()
+        .filter_map(|(path, source)| {
+            PackageFile::from_relative_path("game", "res://game", path, source.to_string())
+        })
+        .collect::<Vec<_>>();
+        assert_eq!(files.len(), 3);
+
+        let _ = common_vm.load_package("game".to_string(), files).unwrap();
+
+        let thought = common_vm
+            .call_python_function(
+                "game.ai.brain".to_string(),
+                "think".to_string(),
+                FuncArgs::default(),
+            )
+            .unwrap();
+        let doubled = common_vm
+            .eval("import game\ngame.double(4)".to_string())
+            .unwrap();
+        let file = common_vm
+            .eval("import game.util\ngame.util.__file__".to_string())
+            .unwrap();
+        // `ai` has no `__init__.py`, its empty package sits in the same directory
+        let package_file = common_vm
+            .eval("import game.ai\ngame.ai.__file__".to_string())
+            .unwrap();
+        common_vm.interpreter.enter(|vm| {
+            assert_eq!(i64::try_from_object(vm, thought).unwrap(), 42);
+            assert_eq!(i64::try_from_object(vm, doubled).unwrap(), 8);
+            assert_eq!(
+                String::try_from_object(vm, file).unwrap(),
+                "res://game/util.py"
+            );
+            assert_eq!(
+                String::try_from_object(vm, package_file).unwrap(),
+                "res://game/ai/__init__.py"
+            );
+        });
+    }
+
+    #[test]
+    fn test_reserved_module_names() {
+        test_reserved_module_names_common()
+    }
+    #[wasm_bindgen_test]
+    fn test_reserved_module_names_web() {
+        test_reserved_module_names_common()
+    }
+    fn test_reserved_module_names_common() {
+        let common_vm = CommonPythonVM::init();
+        for name in [
+            "sandbox",
+            "godot",
+            "godot.extra",
+            "event_loop",
+            "godot_math",
+        ] {
+            let error = common_vm
+                .load_module(name.to_string(), "x = 1".to_string())
+                .unwrap_err();
+            assert_eq!(error.type_name, "ValueError", "{}", name);
+        }
+        let files = vec![PackageFile::from_relative_path(
+            "module_reloader",
+            "res://module_reloader",
+            "__init__.py",
+            "x = 1".to_string(),
+        )
+        .unwrap()];
+        let error = common_vm
+            .load_package("module_reloader".to_string(), files)
+            .unwrap_err();
+        assert_eq!(error.type_name, "ValueError");
+        let error = common_vm
+            .reload_module("module_reloader".to_string(), "x = 1".to_string())
+            .unwrap_err();
+        assert_eq!(error.type_name, "ModuleNotFoundError");
+
+        // The helpers keep working
+        let _ = common_vm
+            .load_module("helpers_intact".to_string(), "x = 1".to_string())
+            .unwrap();
+        let r = common_vm
+            .eval("import godot, sandbox\ncallable(godot.wait_for_signal) and callable(sandbox.configure)".to_string())
+            .unwrap();
+        common_vm.interpreter.enter(|vm| {
+            assert!(bool::try_from_object(vm, r).unwrap());
+        });
+        assert_eq!(common_vm.list_modules(), vec!["helpers_intact".to_string()]);
+    }
+
+    #[test]
+    fn test_reentrant_module_load() {
+        test_reentrant_module_load_common()
+    }
+    #[wasm_bindgen_test]
+    fn test_reentrant_module_load_web() {
+        test_reentrant_module_load_common()
+    }
+    fn test_reentrant_module_load_common() {
+        // A host function that loads a module and calls into it, like a Godot
+        // signal handler or Callable reached from Python would
+        let common_vm = Rc::new(CommonPythonVM::init());
+        let host_vm = Rc::downgrade(&common_vm);
+        common_vm.add_host_module(move |vm| {
+            let module = vm.new_module("host_api", vm.ctx.new_dict(), None);
+            let load_helper = vm.new_function(
+                "load_helper",
+                move |_args: FuncArgs, vm: &VirtualMachine| -> PyResult {
+                    let common_vm = host_vm.upgrade().unwrap();
+                    common_vm
+                        .load_module(
+                            "helper".to_string(),
+                            "def answer():\n  return 42".to_string(),
+                        )
+                        .and_then(|_| {
+                            common_vm.call_python_function(
+                                "helper".to_string(),
+                                "answer".to_string(),
+                                FuncArgs::default(),
+                            )
+                        })
+                        .map_err(|error| vm.new_runtime_error(error.to_string()))
+                },
+            );
+            module
+                .as_object()
+                .set_attr("load_helper", load_helper, vm)
+                .unwrap();
+            module.into()
+        });
+
+        // The module's top level runs while its own load is in progress
+        let source = "import host_api\nANSWER = host_api.load_helper()".to_string();
+        let _ = common_vm
+            .load_module("outer".to_string(), source.clone())
+            .unwrap();
+        let _ = common_vm
+            .reload_module("outer".to_string(), source)
+            .unwrap();
+        let answer = common_vm
+            .eval("from outer import ANSWER\nANSWER".to_string())
+            .unwrap();
+        common_vm.interpreter.enter(|vm| {
+            assert_eq!(i64::try_from_object(vm, answer).unwrap(), 42);
+        });
+        assert_eq!(
+            common_vm.list_modules(),
+            vec!["helper".to_string(), "outer".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_module_sources() {
+        test_module_sources_common()
+    }
+    #[wasm_bindgen_test]
+    fn test_module_sources_web() {
+        test_module_sources_common()
+    }
+    fn test_module_sources_common() {
+        let mut common_vm = CommonPythonVM::init();
+        let config = |value: i64| {
+            let mut source = InMemorySource::new();
+            source.insert(PackageFile {
+                module_name: "config".to_string(),
+                path: "memory://config.py".to_string(),
+                source: format!("VALUE = {}\n", value),
+                is_package: false,
+            });
+            Box::new(source)
+        };
+        common_vm.add_module_source("memory".to_string(), config(1));
+
+        let first = common_vm
+            .eval("import config\nconfig.VALUE".to_string())
+            .unwrap();
+        common_vm.add_module_source("memory".to_string(), config(2));
+        let reloaded = common_vm
+            .eval("import config, importlib\nimportlib.reload(config).VALUE".to_string())
+            .unwrap();
+        let file = common_vm
+            .eval("import config\nconfig.__file__".to_string())
+            .unwrap();
+
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options =
+            zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        for (path, source) in [
+            ("mods/__init__.py", ""),
+            ("mods/greet.py", "def greet():\n  raise ValueError('hi')\n"),
+            ("notes.txt", "not python"),
+        ] {
+            writer.start_file(path, options).unwrap();
+            writer.write_all(source.as_bytes()).unwrap();
+        }
+        let bytes = writer.finish().unwrap().into_inner();
+        common_vm.add_module_source(
+            "zip".to_string(),
+            Box::new(ZipSource::new(bytes, "mods.zip").unwrap()),
+        );
+
+        let error = common_vm
+            .eval("from mods.greet import greet\ngreet()".to_string())
+            .unwrap_err();
+        assert_eq!(error.type_name, "ValueError");
+        assert_eq!(
+            error.traceback.last().unwrap().file,
+            "mods.zip/mods/greet.py"
+        );
+
+        assert!(common_vm.remove_module_source("zip"));
+        assert!(!common_vm.remove_module_source("zip"));
+        let missing = common_vm.eval("import mods.other".to_string()).unwrap_err();
+        assert_eq!(missing.type_name, "ModuleNotFoundError");
+
+        common_vm.interpreter.enter(|vm| {
+            assert_eq!(i64::try_from_object(vm, first).unwrap(), 1);
+            assert_eq!(i64::try_from_object(vm, reloaded).unwrap(), 2);
+            assert_eq!(
+                String::try_from_object(vm, file).unwrap(),
+                "memory://config.py"
+            );
+        });
+    }
+
+    #[test]
+    fn test_load_module() {
+        test_load_module_common()
+    }
+    #[wasm_bindgen_test]
+    fn test_load_module_web() {
+        test_load_module_common()
+    }
+    fn test_load_module_common() {
+        let common_vm = CommonPythonVM::init();
+        let _ = common_vm
+            .load_module(
+                "test_module".to_string(),
+                r#"
+def hello():
+  return "Hello, World!2"
+        "#
+                .to_string(),
+            )
+            .unwrap();
+        let hello = common_vm
+            .eval(
+                r#"from test_module import hello
+hello()"#
+                    .to_string(),
+            )
+            .unwrap();
+        common_vm.interpreter.enter(|vm| {
+            assert_eq!(
+                String::try_from_object(vm, hello).unwrap(),
+                "Hello, World!2"
+            );
+        });
+    }
+
+    #[test]
+    fn test_reload_module() {
+        test_reload_module_common()
+    }
+    #[wasm_bindgen_test]
+    fn test_reload_module_web() {
+        test_reload_module_common()
+    }
+    fn test_reload_module_common() {
+        let mut common_vm = CommonPythonVM::init();
+        common_vm
+            .load_module(
+                "char_module".to_string(),
+                r#"
+__persist__ = ["presses"]
+presses = 0
+
+def speed():
+  return 1
+
+def press():
+  global presses
+  presses += 1
+  return presses
+
+def old():
+  pass
+"#
+                .to_string(),
+            )
+            .unwrap();
+        common_vm
+            .load_module(
+                "user_module".to_string(),
+                "from char_module import speed\n\ndef get_speed():\n  return speed()\n".to_string(),
+            )
+            .unwrap();
+        common_vm.create_session("repl".to_string());
+        common_vm
+            .eval_in_session(
+                "repl".to_string(),
+                "from char_module import speed, press\npress()".to_string(),
+            )
+            .unwrap();
+
+        let diff = common_vm
+            .reload_module(
+                "char_module".to_string(),
+                r#"
+__persist__ = ["presses"]
+presses = 0
+
+
+def speed():
+  return 2
+
+def press():
+  global presses
+  presses += 1
+  return presses
+
+def new():
+  pass
+"#
+                .to_string(),
+            )
+            .unwrap();
+        assert_eq!(diff.added, vec!["new".to_string()]);
+        assert_eq!(diff.removed, vec!["old".to_string()]);
+        assert_eq!(diff.changed, vec!["speed".to_string()]);
+        assert_eq!(diff.persisted, vec!["presses".to_string()]);
+        assert_eq!(diff.updated_modules, vec!["user_module".to_string()]);
+
+        let dependent = common_vm
+            .call_python_function(
+                "user_module".to_string(),
+                "get_speed".to_string(),
+                FuncArgs::default(),
+            )
+            .unwrap();
+        let in_session = common_vm
+            .eval_in_session("repl".to_string(), "(speed(), press())".to_string())
+            .unwrap();
+
+        let error = common_vm
+            .reload_module("char_module".to_string(), "raise ValueError()".to_string())
+            .unwrap_err();
+        assert_eq!(error.type_name, "ValueError");
+        let kept = common_vm
+            .call_python_function(
+                "char_module".to_string(),
+                "speed".to_string(),
+                FuncArgs::default(),
+            )
+            .unwrap();
+
+        common_vm.interpreter.enter(|vm| {
+            assert_eq!(i64::try_from_object(vm, dependent).unwrap(), 2);
+            let in_session = <(i64, i64)>::try_from_object(vm, in_session).unwrap();
+            assert_eq!(in_session, (2, 2));
+            assert_eq!(i64::try_from_object(vm, kept).unwrap(), 2);
+        });
+    }
+
+    #[test]
+    fn test_introspection() {
+        test_introspection_common()
+    }
+    #[wasm_bindgen_test]
+    fn test_introspection_web() {
+        test_introspection_common()
+    }
+    fn test_introspection_common() {
+        let common_vm = CommonPythonVM::init();
+        common_vm
+            .load_module(
+                "shapes".to_string(),
+                r#"
+import math
+
+SIDES = 4
+_cache = {}
+
+def area(width: float, height: float = 2.0, *rest, scale, **options) -> float:
+  """Area of a rectangle."""
+  return width * height
+
+class Square:
+  def grow(self, by=1, /):
+    pass
+"#
+                .to_string(),
+            )
+            .unwrap();
+
+        assert_eq!(common_vm.list_modules(), vec!["shapes".to_string()]);
+
+        let members = common_vm.list_members("shapes".to_string()).unwrap();
+        let summary: Vec<(&str, MemberKind)> = members
+            .iter()
+            .map(|member| (member.name.as_str(), member.kind))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("SIDES", MemberKind::Value),
+                ("Square", MemberKind::Class),
+                ("area", MemberKind::Function),
+                ("math", MemberKind::Module),
+            ]
+        );
+        assert_eq!(members[0].type_name, "int");
+
+        let signature = common_vm
+            .get_signature("shapes".to_string(), "area".to_string())
+            .unwrap();
+        assert_eq!(signature.doc.as_deref(), Some("Area of a rectangle."));
+        assert_eq!(signature.return_annotation.as_deref(), Some("float"));
+        let parameters: Vec<(&str, ParameterKind, Option<&str>, bool)> = signature
+            .parameters
+            .iter()
+            .map(|p| {
+                (
+                    p.name.as_str(),
+                    p.kind,
+                    p.annotation.as_deref(),
+                    p.default.is_some(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            parameters,
+            vec![
+                (
+                    "width",
+                    ParameterKind::PositionalOrKeyword,
+                    Some("float"),
+                    false
+                ),
+                (
+                    "height",
+                    ParameterKind::PositionalOrKeyword,
+                    Some("float"),
+                    true
+                ),
+                ("rest", ParameterKind::VarPositional, None, false),
+                ("scale", ParameterKind::KeywordOnly, None, false),
+                ("options", ParameterKind::VarKeyword, None, false),
+            ]
+        );
+        common_vm.interpreter.enter(|vm| {
+            let default = signature.parameters[1].default.clone().unwrap();
+            assert_eq!(f64::try_from_object(vm, default).unwrap(), 2.0);
+        });
+
+        let method = common_vm
+            .get_signature("shapes".to_string(), "Square.grow".to_string())
+            .unwrap();
+        assert_eq!(method.parameters[1].kind, ParameterKind::PositionalOnly);
+
+        let error = common_vm
+            .get_signature("shapes".to_string(), "SIDES".to_string())
+            .unwrap_err();
+        assert_eq!(error.type_name, "TypeError");
+        let error = common_vm.list_members("missing".to_string()).unwrap_err();
+        assert_eq!(error.type_name, "ModuleNotFoundError");
+    }
+
+    #[test]
+    fn test_object_handles() {
+        test_object_handles_common()
+    }
+    #[wasm_bindgen_test]
+    fn test_object_handles_web() {
+        test_object_handles_common()
+    }
+    fn test_object_handles_common() {
+        let common_vm = CommonPythonVM::init();
+        let player = common_vm
+            .eval(
+                r#"
+class Player:
+  def __init__(self):
+    self.hp = 10
+  def hit(self, damage):
+    self.hp -= damage
+    return self.hp
+Player()"#
+                    .to_string(),
+            )
+            .unwrap();
+
+        let handle = common_vm.register_handle(player.clone());
+        assert_eq!(common_vm.register_handle(player), handle);
+        assert_eq!(common_vm.handles().len(), 1);
+        assert_eq!(common_vm.handle_type_name(handle).unwrap(), "Player");
+
+        let damage = common_vm.interpreter.enter(|vm| vm.ctx.new_int(3).into());
+        let hp = common_vm
+            .call_handle_method(
+                handle,
+                "hit".to_string(),
+                FuncArgs::new(vec![damage], KwArgs::default()),
+            )
+            .unwrap();
+        let ten = common_vm.interpreter.enter(|vm| vm.ctx.new_int(10).into());
+        common_vm
+            .set_handle_attr(handle, "hp".to_string(), ten)
+            .unwrap();
+        let reset_hp = common_vm.get_handle_attr(handle, "hp".to_string()).unwrap();
+        let missing = common_vm
+            .get_handle_attr(handle, "mana".to_string())
+            .unwrap_err();
+        assert_eq!(missing.type_name, "AttributeError");
+
+        let generator = common_vm
+            .eval("(i * i for i in range(3))".to_string())
+            .unwrap();
+        let generator = common_vm.register_handle(generator);
+        let iterator = common_vm.iter_handle(generator).unwrap();
+        let mut squares = Vec::new();
+        while let Some(item) = common_vm.next_handle_item(iterator).unwrap() {
+            squares.push(item);
+        }
+
+        let builder = common_vm.eval("tuple".to_string()).unwrap();
+        let builder = common_vm.register_handle(builder);
+        let built = common_vm.call_handle(builder, FuncArgs::default()).unwrap();
+
+        common_vm.interpreter.enter(|vm| {
+            assert_eq!(i64::try_from_object(vm, hp).unwrap(), 7);
+            assert_eq!(i64::try_from_object(vm, reset_hp).unwrap(), 10);
+            let squares: Vec<i64> = squares
+                .into_iter()
+                .map(|item| i64::try_from_object(vm, item).unwrap())
+                .collect();
+            assert_eq!(squares, vec![0, 1, 4]);
+            assert!(built.downcast_ref::<PyTuple>().is_some());
+        });
+
+        // Registered twice, so the first release keeps it alive
+        assert!(common_vm.release_handle(handle));
+        assert!(common_vm.get_handle_object(handle).is_ok());
+        assert!(common_vm.release_handle(handle));
+        assert!(!common_vm.release_handle(handle));
+        let released = common_vm.handle_repr(handle).unwrap_err();
+        assert_eq!(released.type_name, "ReferenceError");
+        assert_eq!(common_vm.handles().len(), 3);
+    }
+
+    #[test]
+    fn test_coroutines() {
+        test_coroutines_common()
+    }
+    #[wasm_bindgen_test]
+    fn test_coroutines_web() {
+        test_coroutines_common()
+    }
+    fn test_coroutines_common() {
+        let common_vm = CommonPythonVM::init_with_policy(SandboxPolicy::restricted());
+        common_vm
+            .load_module(
+                "intro".to_string(),
+                r#"
+from godot import wait_for_signal
+
+def cutscene(name):
+    answer = yield "hello " + name
+    pressed = yield wait_for_signal("pressed", "Button")
+    return (answer, pressed)
+
+async def fade():
+    await wait_for_signal("timeout")
+    return "faded"
+
+def not_a_coroutine():
+    return 1
+"#
+                .to_string(),
+            )
+            .unwrap();
+
+        let none = common_vm.interpreter.enter(|vm| vm.ctx.none());
+        let int = |value: i64| -> PyObjectRef {
+            common_vm
+                .interpreter
+                .enter(|vm| vm.ctx.new_int(value).into())
+        };
+        let name = common_vm
+            .interpreter
+            .enter(|vm| vm.ctx.new_str("bob").into());
+        let cutscene = common_vm
+            .start_coroutine(
+                "intro".to_string(),
+                "cutscene".to_string(),
+                FuncArgs::new(vec![name], KwArgs::default()),
+            )
+            .unwrap();
+
+        let greeting = match common_vm.resume_handle(cutscene, none.clone()).unwrap() {
+            CoroutineStep::Yielded(value) => value,
+            step => panic!("expected a yield, got {:?}", step),
+        };
+        match common_vm.resume_handle(cutscene, int(42)).unwrap() {
+            CoroutineStep::WaitForSignal { signal, node_path } => {
+                assert_eq!(signal, "pressed");
+                assert_eq!(node_path, "Button");
+            }
+            step => panic!("expected a signal wait, got {:?}", step),
+        }
+        let result = match common_vm.resume_handle(cutscene, int(7)).unwrap() {
+            CoroutineStep::Finished(value) => value,
+            step => panic!("expected the return, got {:?}", step),
+        };
+
+        let fade = common_vm
+            .start_coroutine("intro".to_string(), "fade".to_string(), FuncArgs::default())
+            .unwrap();
+        match common_vm.resume_handle(fade, none.clone()).unwrap() {
+            CoroutineStep::WaitForSignal { signal, node_path } => {
+                assert_eq!(signal, "timeout");
+                assert_eq!(node_path, "");
+            }
+            step => panic!("expected a signal wait, got {:?}", step),
+        }
+        let faded = common_vm.resume_handle(fade, none).unwrap();
+        assert!(faded.is_finished());
+
+        let error = common_vm
+            .start_coroutine(
+                "intro".to_string(),
+                "not_a_coroutine".to_string(),
+                FuncArgs::default(),
+            )
+            .unwrap_err();
+        assert_eq!(error.type_name, "TypeError");
+
+        common_vm.interpreter.enter(|vm| {
+            assert_eq!(String::try_from_object(vm, greeting).unwrap(), "hello bob");
+            let (answer, pressed) = <(i64, i64)>::try_from_object(vm, result).unwrap();
+            assert_eq!((answer, pressed), (42, 7));
+            if let CoroutineStep::Finished(value) = faded {
+                assert_eq!(String::try_from_object(vm, value).unwrap(), "faded");
+            }
+        });
+    }
+
+    #[test]
+    fn test_event_loop() {
+        test_event_loop_common()
+    }
+    #[wasm_bindgen_test]
+    fn test_event_loop_web() {
+        test_event_loop_common()
+    }
+    fn test_event_loop_common() {
+        let common_vm = CommonPythonVM::init();
+        common_vm
             .load_module(
-                "test_module".to_string(),
+                "timers".to_string(),
                 r#"
-def hello():
-  return "Hello, World!2"
-        "#
+import asyncio
+
+async def countdown(seconds):
+    await asyncio.sleep(seconds)
+    return "liftoff"
+
+async def broken():
+    await asyncio.sleep(0)
+    raise ValueError("no fuel")
+
+def plain():
+    return 3
+"#
                 .to_string(),
             )
             .unwrap();
-        let hello = common_vm
-            .eval(
-                r#"from test_module import hello
-hello()"#
-                    .to_string(),
+
+        let seconds = common_vm
+            .interpreter
+            .enter(|vm| vm.ctx.new_float(1.0).into());
+        let task = common_vm
+            .call_python_function(
+                "timers".to_string(),
+                "countdown".to_string(),
+                FuncArgs::new(vec![seconds], KwArgs::default()),
+            )
+            .unwrap();
+        assert!(common_vm.is_task(&task));
+        let task = common_vm.register_handle(task);
+
+        let plain = common_vm
+            .call_python_function(
+                "timers".to_string(),
+                "plain".to_string(),
+                FuncArgs::default(),
+            )
+            .unwrap();
+        assert!(!common_vm.is_task(&plain));
+
+        // Sleeping follows the polled time, not the wall clock
+        common_vm.poll(0.0).unwrap();
+        common_vm.poll(0.5).unwrap();
+        assert!(common_vm.task_result(task).is_none());
+        common_vm.poll(0.6).unwrap();
+        common_vm.poll(0.0).unwrap();
+        let result = common_vm.task_result(task).unwrap().unwrap();
+
+        let broken = common_vm
+            .call_python_function(
+                "timers".to_string(),
+                "broken".to_string(),
+                FuncArgs::default(),
             )
             .unwrap();
+        let broken = common_vm.register_handle(broken);
+        for _ in 0..3 {
+            common_vm.poll(0.016).unwrap();
+        }
+        let error = common_vm.task_result(broken).unwrap().unwrap_err();
+        assert_eq!(error.type_name, "ValueError");
+
         common_vm.interpreter.enter(|vm| {
-            assert_eq!(
-                String::try_from_object(vm, hello).unwrap(),
-                "Hello, World!2"
-            );
+            assert_eq!(String::try_from_object(vm, result).unwrap(), "liftoff");
+        });
+    }
+
+    #[test]
+    fn test_output_streams() {
+        test_output_streams_common()
+    }
+    #[wasm_bindgen_test]
+    fn test_output_streams_web() {
+        test_output_streams_common()
+    }
+    fn test_output_streams_common() {
+        let mut common_vm = CommonPythonVM::init();
+        let stdout = Rc::new(RefCell::new(Vec::new()));
+        let stderr = Rc::new(RefCell::new(Vec::new()));
+        let (out, err) = (stdout.clone(), stderr.clone());
+        common_vm.setup_stdout(move |chunk| out.borrow_mut().push(chunk));
+        common_vm.setup_stderr(move |chunk| err.borrow_mut().push(chunk));
+        let observed = Rc::new(RefCell::new(Vec::new()));
+        let seen = observed.clone();
+        common_vm.set_output_observer(move |chunk| {
+            seen.borrow_mut()
+                .push((chunk.stream, chunk.formatted.clone()))
+        });
+
+        common_vm
+            .load_module(
+                "greeter".to_string(),
+                r#"
+import sys
+
+def greet():
+    print("hello")
+    sys.stderr.write("oops")
+    sys.stderr.flush()
+"#
+                .to_string(),
+            )
+            .unwrap();
+        common_vm
+            .call_python_function(
+                "greeter".to_string(),
+                "greet".to_string(),
+                FuncArgs::default(),
+            )
+            .unwrap();
+        common_vm.eval("print('from eval')".to_string()).unwrap();
+
+        {
+            let stdout = stdout.borrow();
+            assert_eq!(stdout.len(), 2);
+            assert_eq!(stdout[0].stream, OutputStream::Stdout);
+            assert_eq!(stdout[0].text, "hello\n");
+            assert_eq!(stdout[0].formatted, "hello\n");
+            assert_eq!(stdout[0].source.as_deref(), Some("greeter"));
+            assert!(stdout[0].timestamp_msec > 0.0);
+            assert_eq!(stdout[1].text, "from eval\n");
+            assert_eq!(stdout[1].source, None);
+
+            let stderr = stderr.borrow();
+            assert_eq!(stderr.len(), 1);
+            assert_eq!(stderr[0].stream, OutputStream::Stderr);
+            assert_eq!(stderr[0].text, "oops");
+        }
+
+        common_vm.set_output_markup(Some(Box::new(BBCodeMarkup::default())));
+        common_vm
+            .eval("import sys\nprint('plain')\nprint('bad', file=sys.stderr)".to_string())
+            .unwrap();
+        assert_eq!(stdout.borrow()[2].formatted, "plain\n");
+        let stderr = stderr.borrow();
+        assert_eq!(stderr[1].text, "bad\n");
+        assert_eq!(stderr[1].formatted, "[color=red]bad\n[/color]");
+
+        // The observer sees both streams in the order they were written
+        assert_eq!(
+            *observed.borrow(),
+            vec![
+                (OutputStream::Stdout, "hello\n".to_string()),
+                (OutputStream::Stderr, "oops".to_string()),
+                (OutputStream::Stdout, "from eval\n".to_string()),
+                (OutputStream::Stdout, "plain\n".to_string()),
+                (OutputStream::Stderr, "[color=red]bad\n[/color]".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_output_sink_replaced_while_running() {
+        test_output_sink_replaced_while_running_common()
+    }
+    #[wasm_bindgen_test]
+    fn test_output_sink_replaced_while_running_web() {
+        test_output_sink_replaced_while_running_common()
+    }
+    fn test_output_sink_replaced_while_running_common() {
+        let common_vm = CommonPythonVM::init();
+        let first = Rc::new(RefCell::new(Vec::<String>::new()));
+        let second = Rc::new(RefCell::new(Vec::<String>::new()));
+        let observed = Rc::new(RefCell::new(0));
+
+        // The sink and the observer replace themselves on their first chunk
+        let sinks = Rc::downgrade(&common_vm.output);
+        let (seen, later) = (first.clone(), second.clone());
+        common_vm.output.set_sink(
+            OutputStream::Stdout,
+            Some(Box::new(move |chunk| {
+                seen.borrow_mut().push(chunk.text);
+                let later = later.clone();
+                sinks.upgrade().unwrap().set_sink(
+                    OutputStream::Stdout,
+                    Some(Box::new(move |chunk| later.borrow_mut().push(chunk.text))),
+                );
+            })),
+        );
+        let sinks = Rc::downgrade(&common_vm.output);
+        let count = observed.clone();
+        common_vm.output.set_observer(Some(Box::new(move |_| {
+            *count.borrow_mut() += 1;
+            sinks.upgrade().unwrap().set_observer(None);
+        })));
+
+        common_vm
+            .eval("print('one')\nprint('two')".to_string())
+            .unwrap();
+        assert_eq!(*first.borrow(), vec!["one\n".to_string()]);
+        assert_eq!(*second.borrow(), vec!["two\n".to_string()]);
+        assert_eq!(*observed.borrow(), 1);
+    }
+
+    #[test]
+    fn test_output_buffering() {
+        test_output_buffering_common()
+    }
+    #[wasm_bindgen_test]
+    fn test_output_buffering_web() {
+        test_output_buffering_common()
+    }
+    fn test_output_buffering_common() {
+        let mut common_vm = CommonPythonVM::init();
+        let chunks = Rc::new(RefCell::new(Vec::<String>::new()));
+        let collected = chunks.clone();
+        common_vm.setup_stdout(move |chunk| collected.borrow_mut().push(chunk.text));
+        let printed = |common_vm: &CommonPythonVM, code: &str| -> Vec<String> {
+            common_vm.eval(format!("import sys\n{}", code)).unwrap();
+            chunks.borrow_mut().drain(..).collect()
+        };
+
+        // Line buffered by default
+        assert_eq!(printed(&common_vm, r#"print("a\nb")"#), vec!["a\n", "b\n"]);
+        assert_eq!(
+            printed(&common_vm, r#"sys.stdout.write("x\n")"#),
+            vec!["x\n"]
+        );
+        assert_eq!(
+            printed(&common_vm, r#"print("partial", end="")"#),
+            vec!["partial"]
+        );
+        assert_eq!(
+            printed(&common_vm, "print('a', end='')\nprint('b')"),
+            vec!["ab\n"]
+        );
+        assert_eq!(
+            printed(&common_vm, "sys.stdout.write('one\\ntwo')"),
+            vec!["one\n", "two"]
+        );
+        assert_eq!(
+            printed(
+                &common_vm,
+                "print('x', flush=True, end='')\nprint('y', end='')"
+            ),
+            vec!["x", "y"]
+        );
+
+        common_vm.set_output_buffering(OutputStream::Stdout, BufferMode::Unbuffered);
+        assert_eq!(printed(&common_vm, "print('a')"), vec!["a", "\n"]);
+
+        common_vm.set_output_buffering(OutputStream::Stdout, BufferMode::Full);
+        assert_eq!(
+            printed(&common_vm, "print('a')\nprint('b')"),
+            vec!["a\nb\n"]
+        );
+        assert_eq!(
+            printed(&common_vm, "print('a')\nsys.stdout.flush()\nprint('b')"),
+            vec!["a\n", "b\n"]
+        );
+        assert_eq!(
+            printed(&common_vm, "print('z' * 9000, end='')")
+                .iter()
+                .map(|chunk| chunk.len())
+                .collect::<Vec<_>>(),
+            vec![9000]
+        );
+
+        // Calls flush when they end, like evals
+        common_vm
+            .load_module(
+                "printer".to_string(),
+                "def show():\n    print('shown', end='')".to_string(),
+            )
+            .unwrap();
+        common_vm
+            .call_python_function(
+                "printer".to_string(),
+                "show".to_string(),
+                FuncArgs::default(),
+            )
+            .unwrap();
+        assert_eq!(*chunks.borrow(), vec!["shown"]);
+    }
+
+    #[test]
+    fn test_captured_output() {
+        test_captured_output_common()
+    }
+    #[wasm_bindgen_test]
+    fn test_captured_output_web() {
+        test_captured_output_common()
+    }
+    fn test_captured_output_common() {
+        let mut common_vm = CommonPythonVM::init();
+        let chunks = Rc::new(RefCell::new(Vec::<String>::new()));
+        let (out, err) = (chunks.clone(), chunks.clone());
+        common_vm.setup_stdout(move |chunk| out.borrow_mut().push(chunk.text));
+        common_vm.setup_stderr(move |chunk| err.borrow_mut().push(chunk.text));
+        common_vm
+            .load_module(
+                "graded".to_string(),
+                r#"
+import sys
+
+def solve(n):
+    print("thinking", end="")
+    sys.stderr.write("warning\n")
+    return n * 2
+"#
+                .to_string(),
+            )
+            .unwrap();
+
+        let n = common_vm.interpreter.enter(|vm| vm.ctx.new_int(21).into());
+        let args = FuncArgs::new(vec![n], KwArgs::default());
+        let (r, output) = common_vm.with_captured_output(|common_vm| {
+            common_vm.call_python_function("graded".to_string(), "solve".to_string(), args)
+        });
+        let value = r.unwrap();
+        common_vm.interpreter.enter(|vm| {
+            assert_eq!(i64::try_from_object(vm, value).unwrap(), 42);
+        });
+        assert_eq!(
+            output,
+            CapturedOutput {
+                stdout: "thinking".to_string(),
+                stderr: "warning\n".to_string(),
+            }
+        );
+
+        // Output before an exception is kept
+        let (r, output) = common_vm
+            .with_captured_output(|common_vm| common_vm.eval("print('before')\n1 / 0".to_string()));
+        assert_eq!(r.unwrap_err().type_name, "ZeroDivisionError");
+        assert_eq!(output.stdout, "before\n");
+        assert_eq!(output.stderr, "");
+
+        // Nested captures only see their own output
+        let ((_, inner), outer) = common_vm.with_captured_output(|common_vm| {
+            common_vm.eval("print('outer')".to_string()).unwrap();
+            common_vm.with_captured_output(|common_vm| common_vm.eval("print('inner')".to_string()))
+        });
+        assert_eq!(inner.stdout, "inner\n");
+        assert_eq!(outer.stdout, "outer\n");
+
+        // None of it reached the sinks, which get output again afterwards
+        assert!(chunks.borrow().is_empty());
+        common_vm.eval("print('global')".to_string()).unwrap();
+        assert_eq!(*chunks.borrow(), vec!["global\n"]);
+    }
+
+    #[test]
+    fn test_stdin() {
+        test_stdin_common()
+    }
+    #[wasm_bindgen_test]
+    fn test_stdin_web() {
+        test_stdin_common()
+    }
+    fn test_stdin_common() {
+        let mut common_vm = CommonPythonVM::init();
+        let output = Rc::new(RefCell::new(String::new()));
+        let written = output.clone();
+        common_vm.setup_stdout(move |chunk| written.borrow_mut().push_str(&chunk.text));
+        let prompts = Rc::new(RefCell::new(Vec::new()));
+        let seen = prompts.clone();
+        common_vm.setup_stdin(
+            move |prompt| {
+                seen.borrow_mut().push(prompt);
+                None
+            },
+            StdinMode::Blocking,
+        );
+
+        common_vm.push_stdin("first".to_string());
+        common_vm.push_stdin("second\n".to_string());
+        let first = common_vm.eval("input('> ')".to_string()).unwrap();
+        let second = common_vm
+            .eval("import sys\nsys.stdin.readline()".to_string())
+            .unwrap();
+        let eof = common_vm.eval("input('name? ')".to_string()).unwrap_err();
+        assert_eq!(eof.type_name, "EOFError");
+        assert_eq!(*prompts.borrow(), vec!["name? ".to_string()]);
+        assert_eq!(*output.borrow(), "> name? ");
+
+        // Once disconnected, the host is not asked anymore
+        common_vm.clear_stdin(StdinMode::Blocking);
+        let eof = common_vm.eval("input()".to_string()).unwrap_err();
+        assert_eq!(eof.type_name, "EOFError");
+        assert_eq!(prompts.borrow().len(), 1);
+
+        // A replaced sink gets nothing more
+        let replaced = Rc::new(RefCell::new(String::new()));
+        let written = replaced.clone();
+        common_vm.setup_stdout(move |chunk| written.borrow_mut().push_str(&chunk.text));
+        common_vm.eval("print('later')".to_string()).unwrap();
+        assert_eq!(*output.borrow(), "> name? ");
+        assert_eq!(*replaced.borrow(), "later\n");
+
+        common_vm.setup_stdin(|_| None, StdinMode::Suspending);
+        // Outside a coroutine nothing could resume the caller once the line arrives
+        let misused = common_vm.eval("input('name? ')".to_string()).unwrap_err();
+        assert_eq!(misused.type_name, "RuntimeError");
+        common_vm
+            .load_module(
+                "terminal".to_string(),
+                r#"
+def ask():
+    name = yield input("name? ")
+    return "hi " + name
+
+async def ask_async():
+    return "hi " + await input("name? ")
+"#
+                .to_string(),
+            )
+            .unwrap();
+
+        let none = common_vm.interpreter.enter(|vm| vm.ctx.none());
+        let ask = common_vm
+            .start_coroutine(
+                "terminal".to_string(),
+                "ask".to_string(),
+                FuncArgs::default(),
+            )
+            .unwrap();
+        match common_vm.resume_handle(ask, none).unwrap() {
+            CoroutineStep::WaitForInput { prompt } => assert_eq!(prompt, "name? "),
+            step => panic!("expected an input wait, got {:?}", step),
+        }
+        assert_eq!(common_vm.take_stdin_line(), None);
+        common_vm.push_stdin("ann".to_string());
+        let line = common_vm.take_stdin_line().unwrap();
+        let line = common_vm
+            .interpreter
+            .enter(|vm| vm.ctx.new_str(line).into());
+        let asked = common_vm.resume_handle(ask, line).unwrap();
+
+        let task = common_vm
+            .call_python_function(
+                "terminal".to_string(),
+                "ask_async".to_string(),
+                FuncArgs::default(),
+            )
+            .unwrap();
+        let task = common_vm.register_handle(task);
+        common_vm.poll(0.0).unwrap();
+        common_vm.poll(0.0).unwrap();
+        assert!(common_vm.task_result(task).is_none());
+        common_vm.push_stdin("bo".to_string());
+        common_vm.poll(0.0).unwrap();
+        let asked_async = common_vm.task_result(task).unwrap().unwrap();
+
+        common_vm.interpreter.enter(|vm| {
+            assert_eq!(String::try_from_object(vm, first).unwrap(), "first");
+            assert_eq!(String::try_from_object(vm, second).unwrap(), "second\n");
+            match asked {
+                CoroutineStep::Finished(value) => {
+                    assert_eq!(String::try_from_object(vm, value).unwrap(), "hi ann")
+                }
+                step => panic!("expected the return, got {:?}", step),
+            }
+            assert_eq!(String::try_from_object(vm, asked_async).unwrap(), "hi bo");
         });
     }
 
@@ -300,7 +2751,7 @@ hello()"#
         test_call_python_function_common()
     }
     fn test_call_python_function_common() {
-        let mut common_vm = CommonPythonVM::init();
+        let common_vm = CommonPythonVM::init();
         let _ = common_vm
             .load_module(
                 "test_module".to_string(),
@@ -364,6 +2815,416 @@ def hello_kwargs(s, n=1):
         });
     }
 
+    #[test]
+    fn test_godot_math() {
+        test_godot_math_common()
+    }
+    #[wasm_bindgen_test]
+    fn test_godot_math_web() {
+        test_godot_math_common()
+    }
+    fn test_godot_math_common() {
+        let common_vm = CommonPythonVM::init();
+        let r = common_vm
+            .eval(
+                r#"
+import godot.math
+from godot import Vector2, Vector2i, Color, Rect2, Transform2D, StringName
+
+v = Vector2(1, 2.5)
+assert v == godot.math.Vector2(x=1.0, y=2.5)
+assert v != Vector2(1, 2) and v != (1.0, 2.5)
+assert hash(v) == hash(Vector2(1, 2.5))
+assert len({Vector2i(1, 2), Vector2i(1, 2), Vector2i(2, 1)}) == 2
+x, y = v
+assert (x, y) == (1.0, 2.5) and v.x == 1.0
+
+assert Color(1, 0, 0).a == 1.0
+assert Rect2(size=Vector2(4, 3)).position == Vector2()
+assert Transform2D().x == Vector2(1, 0)
+assert StringName("idle") == "idle"
+
+try:
+    v.x = 3
+    immutable = False
+except AttributeError:
+    immutable = True
+assert immutable
+
+repr(Rect2(Vector2(1, 2), Vector2(3, 4)))
+"#
+                .to_string(),
+            )
+            .unwrap();
+        common_vm.interpreter.enter(|vm| {
+            assert_eq!(
+                String::try_from_object(vm, r).unwrap(),
+                "Rect2(Vector2(1.0, 2.0), Vector2(3.0, 4.0))"
+            );
+        });
+
+        for (code, error) in [
+            ("Vector2(1, 2, 3)", "TypeError"),
+            ("Vector2(z=1)", "TypeError"),
+            ("Vector2i(1.5)", "TypeError"),
+            ("Rect2((0, 0), (1, 1))", "TypeError"),
+        ] {
+            let e = common_vm
+                .eval(format!("from godot import *\n{}", code))
+                .unwrap_err();
+            assert_eq!(e.type_name, error, "{}", code);
+        }
+
+        // Only the value types come with `import *`, not `math` over the stdlib one
+        let r = common_vm
+            .eval("import math\nfrom godot import *\nmath.floor(2.5)".to_string())
+            .unwrap();
+        common_vm.interpreter.enter(|vm| {
+            assert_eq!(i64::try_from_object(vm, r).unwrap(), 2);
+        });
+    }
+
+    #[test]
+    fn test_godot_math_operators() {
+        test_godot_math_operators_common()
+    }
+    #[wasm_bindgen_test]
+    fn test_godot_math_operators_web() {
+        test_godot_math_operators_common()
+    }
+    fn test_godot_math_operators_common() {
+        let common_vm = CommonPythonVM::init();
+        common_vm
+            .eval(
+                r#"
+from godot.math import *
+
+v = Vector2(3, 4)
+assert v + Vector2(1, 1) == Vector2(4, 5)
+assert v - Vector2(1, 1) == Vector2(2, 3)
+assert v * 2 == 2 * v == Vector2(6, 8)
+assert v * Vector2(2, 0.5) == Vector2(6, 2)
+assert v / 2 == Vector2(1.5, 2)
+assert -v == Vector2(-3, -4)
+assert v.length() == 5.0 and v.length_squared() == 25.0
+assert v.normalized() == Vector2(0.6, 0.8)
+assert Vector2().normalized() == Vector2()
+assert v.dot(Vector2(1, 0)) == 3.0
+assert v.distance_to(Vector2(0, 0)) == 5.0
+assert Vector2(0, 0).lerp(Vector2(10, 20), 0.5) == Vector2(5, 10)
+assert Vector2(1, 0).cross(Vector2(0, 1)) == 1.0
+assert Vector3(1, 0, 0).cross(Vector3(0, 1, 0)) == Vector3(0, 0, 1)
+# Godot computes in 32-bit floats
+assert Vector2(0.1, 0).x != 0.1 and (Vector2(0.1, 0) * 3).x == Vector2(0.3, 0).x
+
+# Dividing by a float zero gives inf like in Godot, integer vectors raise
+assert (Vector2(1, 0) / 0).x == float("inf")
+assert Vector2i(7, -7) / 2 == Vector2i(3, -3)
+assert Vector2i(3, 4) * 0.5 == Vector2(1.5, 2)
+assert Vector3i(1, 2, 3) + Vector3i(1, 1, 1) == Vector3i(2, 3, 4)
+assert Vector2i(3, 4).length() == 5.0
+
+assert Color(1, 0, 0) * 0.5 == Color(0.5, 0, 0, 0.5)
+assert Color(0, 0, 0).lerp(Color(1, 1, 1), 0.5) == Color(0.5, 0.5, 0.5)
+
+t = Transform2D(Vector2(0, 1), Vector2(-1, 0), Vector2(10, 0))
+assert t * Vector2(1, 0) == Vector2(10, 1)
+assert t.affine_inverse() * (t * Vector2(2, 3)) == Vector2(2, 3)
+assert Transform2D() * t == t
+b = Basis(Vector3(2, 0, 0), Vector3(0, 2, 0), Vector3(0, 0, 2))
+assert b * Vector3(1, 2, 3) == Vector3(2, 4, 6)
+assert b.determinant() == 8.0
+assert Transform3D(b, Vector3(1, 1, 1)) * Vector3(1, 0, 0) == Vector3(3, 1, 1)
+"#
+                .to_string(),
+            )
+            .unwrap();
+
+        for (code, error) in [
+            ("Vector2(1, 2) + 1", "TypeError"),
+            ("1 / Vector2(1, 2)", "TypeError"),
+            ("Vector2(1, 2) + Vector3(1, 2, 3)", "TypeError"),
+            ("Vector2i(1, 2) / 0", "ZeroDivisionError"),
+            ("Vector2i(1, 2) / Vector2i(1, 0)", "ZeroDivisionError"),
+            (
+                "Transform2D(Vector2(), Vector2()).affine_inverse()",
+                "ValueError",
+            ),
+        ] {
+            let e = common_vm
+                .eval(format!("from godot.math import *\n{}", code))
+                .unwrap_err();
+            assert_eq!(e.type_name, error, "{}", code);
+        }
+    }
+
+    #[test]
+    fn test_py_value() {
+        test_py_value_common()
+    }
+    #[wasm_bindgen_test]
+    fn test_py_value_web() {
+        test_py_value_common()
+    }
+    fn test_py_value_common() {
+        let common_vm = CommonPythonVM::init();
+        let r = common_vm
+            .eval("[True, 1, 1.0, 2**70, b'ab', bytearray(b'c'), (1,), {2}, None]".to_string())
+            .unwrap();
+        common_vm.interpreter.enter(|vm| {
+            let items = match classify_py_value(vm, &r) {
+                PyValue::Sequence(items) => items,
+                other => panic!("list classified as {:?}", other),
+            };
+            let kinds: Vec<String> = items
+                .iter()
+                .map(|item| format!("{:?}", classify_py_value(vm, item)))
+                .map(|kind| kind.split('(').next().unwrap().to_string())
+                .collect();
+            assert_eq!(
+                kinds,
+                vec![
+                    "Bool", "Int", "Float", "BigInt", "Bytes", "Bytes", "Sequence", "Sequence",
+                    "None"
+                ]
+            );
+            assert!(matches!(
+                classify_py_value(vm, &items[3]),
+                PyValue::BigInt(digits) if digits == "1180591620717411303424"
+            ));
+        });
+
+        let mut runner = TestRunner::new(Config {
+            cases: 64,
+            failure_persistence: None,
+            ..Config::default()
+        });
+        common_vm.interpreter.enter(|vm| {
+            runner
+                .run(&sample_strategy(), |sample| {
+                    let value = sample_to_py(vm, &sample);
+                    assert_classified(vm, &sample, &value)?;
+                    assert_round_tripped(vm, &sample, &round_trip(vm, &value))
+                })
+                .unwrap();
+        });
+    }
+
+    #[test]
+    fn test_py_function_name() {
+        test_py_function_name_common()
+    }
+    #[wasm_bindgen_test]
+    fn test_py_function_name_web() {
+        test_py_function_name_common()
+    }
+    fn test_py_function_name_common() {
+        let common_vm = CommonPythonVM::init();
+        let r = common_vm
+            .eval("(lambda: 1, [].append, len, int, 1)".to_string())
+            .unwrap();
+        common_vm.interpreter.enter(|vm| {
+            let items = match classify_py_value(vm, &r) {
+                PyValue::Sequence(items) => items,
+                other => panic!("tuple classified as {:?}", other),
+            };
+            let names: Vec<Option<String>> = items
+                .iter()
+                .map(|item| py_function_name(vm, item))
+                .collect();
+            assert_eq!(
+                names,
+                vec![
+                    Some("<lambda>".to_string()),
+                    Some("append".to_string()),
+                    Some("len".to_string()),
+                    None,
+                    None
+                ]
+            );
+        });
+    }
+
+    /// A value to build in Python and expect back from `classify_py_value`.
+    #[derive(Debug, Clone)]
+    enum Sample {
+        None,
+        Bool(bool),
+        Int(i128),
+        Float(f64),
+        Str(String),
+        Bytes(Vec<u8>),
+        List(Vec<Sample>),
+        Tuple(Vec<Sample>),
+        Set(Vec<i64>),
+        Dict(Vec<(String, Sample)>),
+    }
+
+    fn sample_strategy() -> impl Strategy<Value = Sample> {
+        let leaf = prop_oneof![
+            Just(Sample::None),
+            any::<bool>().prop_map(Sample::Bool),
+            any::<i64>().prop_map(|int| Sample::Int(int as i128)),
+            any::<i128>().prop_map(Sample::Int),
+            any::<f64>().prop_map(Sample::Float),
+            any::<String>().prop_map(Sample::Str),
+            vec(any::<u8>(), 0..16).prop_map(Sample::Bytes),
+            btree_set(any::<i64>(), 0..4).prop_map(|set| Sample::Set(set.into_iter().collect())),
+        ];
+        leaf.prop_recursive(3, 32, 4, |inner| {
+            prop_oneof![
+                vec(inner.clone(), 0..4).prop_map(Sample::List),
+                vec(inner.clone(), 0..4).prop_map(Sample::Tuple),
+                btree_map("[a-z]{1,8}", inner, 0..4)
+                    .prop_map(|map| Sample::Dict(map.into_iter().collect())),
+            ]
+        })
+    }
+
+    fn sample_to_py(vm: &VirtualMachine, sample: &Sample) -> PyObjectRef {
+        let items = |items: &Vec<Sample>| items.iter().map(|item| sample_to_py(vm, item)).collect();
+        match sample {
+            Sample::None => vm.ctx.none(),
+            Sample::Bool(boolean) => vm.ctx.new_bool(*boolean).into(),
+            Sample::Int(int) => vm.ctx.new_int(*int).into(),
+            Sample::Float(float) => vm.ctx.new_float(*float).into(),
+            Sample::Str(string) => vm.ctx.new_str(string.as_str()).into(),
+            Sample::Bytes(bytes) => vm.ctx.new_bytes(bytes.clone()).into(),
+            Sample::List(list) => vm.ctx.new_list(items(list)).into(),
+            Sample::Tuple(tuple) => vm.ctx.new_tuple(items(tuple)).into(),
+            Sample::Set(set) => {
+                let list = vm
+                    .ctx
+                    .new_list(set.iter().map(|int| vm.ctx.new_int(*int).into()).collect());
+                vm.builtins
+                    .as_object()
+                    .get_attr("set", vm)
+                    .and_then(|set_type| set_type.call((list,), vm))
+                    .unwrap()
+            }
+            Sample::Dict(dict) => {
+                let py_dict = vm.ctx.new_dict();
+                dict.iter().for_each(|(key, value)| {
+                    py_dict
+                        .set_item(key.as_str(), sample_to_py(vm, value), vm)
+                        .unwrap();
+                });
+                py_dict.into()
+            }
+        }
+    }
+
+    fn assert_classified(
+        vm: &VirtualMachine,
+        sample: &Sample,
+        value: &PyObjectRef,
+    ) -> Result<(), TestCaseError> {
+        match (sample, classify_py_value(vm, value)) {
+            (Sample::None, PyValue::None) => {}
+            (Sample::Bool(expected), PyValue::Bool(boolean)) => prop_assert_eq!(*expected, boolean),
+            (Sample::Int(expected), PyValue::Int(int)) => prop_assert_eq!(*expected, int as i128),
+            (Sample::Int(expected), PyValue::BigInt(digits)) => {
+                prop_assert!(i64::try_from(*expected).is_err());
+                prop_assert_eq!(expected.to_string(), digits);
+            }
+            (Sample::Float(expected), PyValue::Float(float)) => {
+                prop_assert_eq!(expected.to_bits(), float.to_bits())
+            }
+            (Sample::Str(expected), PyValue::Str(string)) => prop_assert_eq!(expected, &string),
+            (Sample::Bytes(expected), PyValue::Bytes(bytes)) => prop_assert_eq!(expected, &bytes),
+            (Sample::List(expected) | Sample::Tuple(expected), PyValue::Sequence(items)) => {
+                prop_assert_eq!(expected.len(), items.len());
+                for (expected, item) in expected.iter().zip(items.iter()) {
+                    assert_classified(vm, expected, item)?;
+                }
+            }
+            (Sample::Set(expected), PyValue::Sequence(items)) => {
+                let mut ints = items
+                    .into_iter()
+                    .map(|item| i64::try_from_object(vm, item).unwrap())
+                    .collect::<Vec<_>>();
+                ints.sort();
+                prop_assert_eq!(expected, &ints);
+            }
+            (Sample::Dict(expected), PyValue::Dict(items)) => {
+                prop_assert_eq!(expected.len(), items.len());
+                for ((expected_key, expected), (key, item)) in expected.iter().zip(items.iter()) {
+                    prop_assert_eq!(
+                        expected_key,
+                        &String::try_from_object(vm, key.clone()).unwrap()
+                    );
+                    assert_classified(vm, expected, item)?;
+                }
+            }
+            (sample, value) => prop_assert!(false, "{:?} classified as {:?}", sample, value),
+        }
+        Ok(())
+    }
+
+    /// `value` handed to the host and back, without a host: classified, then rebuilt
+    /// with `PyValue::into_py_object` like the host converters do.
+    fn round_trip(vm: &VirtualMachine, value: &PyObjectRef) -> PyObjectRef {
+        match classify_py_value(vm, value) {
+            PyValue::Sequence(items) => {
+                PyValue::Sequence(items.iter().map(|item| round_trip(vm, item)).collect())
+            }
+            PyValue::Dict(items) => PyValue::Dict(
+                items
+                    .iter()
+                    .map(|(key, item)| (round_trip(vm, key), round_trip(vm, item)))
+                    .collect(),
+            ),
+            other => other,
+        }
+        .into_py_object(vm)
+    }
+
+    /// Like `assert_classified`, and `value` has the exact type it had before the
+    /// round trip, except for tuples and sets, which come back as lists.
+    fn assert_round_tripped(
+        vm: &VirtualMachine,
+        sample: &Sample,
+        value: &PyObjectRef,
+    ) -> Result<(), TestCaseError> {
+        let types = &vm.ctx.types;
+        let expected_type = match sample {
+            Sample::None => types.none_type,
+            Sample::Bool(_) => types.bool_type,
+            Sample::Int(_) => types.int_type,
+            Sample::Float(_) => types.float_type,
+            Sample::Str(_) => types.str_type,
+            Sample::Bytes(_) => types.bytes_type,
+            Sample::List(_) | Sample::Tuple(_) | Sample::Set(_) => types.list_type,
+            Sample::Dict(_) => types.dict_type,
+        };
+        prop_assert!(
+            value.class().is(expected_type),
+            "{:?} came back as {}",
+            sample,
+            &*value.class().name()
+        );
+        match (sample, classify_py_value(vm, value)) {
+            (Sample::List(expected) | Sample::Tuple(expected), PyValue::Sequence(items)) => {
+                prop_assert_eq!(expected.len(), items.len());
+                for (expected, item) in expected.iter().zip(items.iter()) {
+                    assert_round_tripped(vm, expected, item)?;
+                }
+            }
+            (Sample::Dict(expected), PyValue::Dict(items)) => {
+                prop_assert_eq!(expected.len(), items.len());
+                for ((expected_key, expected), (key, item)) in expected.iter().zip(items.iter()) {
+                    prop_assert_eq!(
+                        expected_key,
+                        &String::try_from_object(vm, key.clone()).unwrap()
+                    );
+                    assert_round_tripped(vm, expected, item)?;
+                }
+            }
+            _ => assert_classified(vm, sample, value)?,
+        }
+        Ok(())
+    }
+
     // #[test]
     // fn test_load_module() {
     //     let (interp, _) = create_interpreter();
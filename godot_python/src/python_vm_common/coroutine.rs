@@ -0,0 +1,37 @@
+use rustpython_vm::{PyObjectRef, PyResult, TryFromObject, VirtualMachine};
+
+/// What a generator or coroutine did when it was resumed.
+#[derive(Debug, Clone)]
+pub enum CoroutineStep {
+    /// It yielded a value and can be resumed again
+    Yielded(PyObjectRef),
+    /// It yielded `godot.wait_for_signal(signal, node_path)` and waits for that signal
+    WaitForSignal { signal: String, node_path: String },
+    /// It yielded or awaited `input(prompt)` in suspending mode and waits for a pushed line
+    WaitForInput { prompt: String },
+    /// It returned, with the return value
+    Finished(PyObjectRef),
+}
+
+impl CoroutineStep {
+    /// Reads the `(outcome, value)` tuple returned by `coroutine_runner.step`.
+    pub fn from_py_tuple(vm: &VirtualMachine, step: PyObjectRef) -> PyResult<Self> {
+        let (outcome, value) = <(String, PyObjectRef)>::try_from_object(vm, step)?;
+        match outcome.as_str() {
+            "yielded" => Ok(Self::Yielded(value)),
+            "wait_for_signal" => {
+                let (signal, node_path) = <(String, String)>::try_from_object(vm, value)?;
+                Ok(Self::WaitForSignal { signal, node_path })
+            }
+            "wait_for_input" => Ok(Self::WaitForInput {
+                prompt: String::try_from_object(vm, value)?,
+            }),
+            "finished" => Ok(Self::Finished(value)),
+            _ => Err(vm.new_value_error(format!("unknown coroutine step {:?}", outcome))),
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        matches!(self, Self::Finished(_))
+    }
+}
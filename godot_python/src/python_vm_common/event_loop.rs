@@ -0,0 +1,23 @@
+use rustpython_vm::{PyObjectRef, PyResult, TryFromObject, VirtualMachine};
+
+/// Where an `asyncio.Task` handed to the host is at.
+#[derive(Debug, Clone)]
+pub enum TaskState {
+    Pending,
+    /// It returned, with the return value
+    Done(PyObjectRef),
+    Cancelled,
+}
+
+impl TaskState {
+    /// Reads the `(state, value)` tuple returned by `event_loop.task_state`.
+    pub fn from_py_tuple(vm: &VirtualMachine, state: PyObjectRef) -> PyResult<Self> {
+        let (state, value) = <(String, PyObjectRef)>::try_from_object(vm, state)?;
+        match state.as_str() {
+            "pending" => Ok(Self::Pending),
+            "done" => Ok(Self::Done(value)),
+            "cancelled" => Ok(Self::Cancelled),
+            _ => Err(vm.new_value_error(format!("unknown task state {:?}", state))),
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use std::{cell::Cell, rc::Rc, time::Duration};
+
+use rustpython_vm::{
+    class::PyClassImpl, function::FuncArgs, pyclass, AsObject, PyPayload, PyResult, VirtualMachine,
+};
+
+/// Instrumented code calls `None.__budget_tick__()`. `NoneType` is shared by all
+/// interpreters but Python code can't modify it, so the tick can't be rebound.
+pub const TICK_NAME: &str = "__budget_tick__";
+/// Where the tick finds the tracker of the interpreter running it, in its builtins
+pub const TRACKER_NAME: &str = "__budget_tracker__";
+
+/// Limits for a single call into Python. Instructions are counted as loop
+/// iterations and function calls, which the code compiler instruments.
+///
+/// Both limits are only checked at those ticks, so time spent in native code, e.g.
+/// `any(iter(int, 1))` or a long `sorted`, isn't interrupted.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ExecutionBudget {
+    pub max_instructions: Option<u64>,
+    pub timeout: Option<Duration>,
+}
+
+impl ExecutionBudget {
+    pub fn unlimited() -> Self {
+        Self::default()
+    }
+
+    /// Builds a budget from host-friendly values, where zero or less means no limit.
+    pub fn from_limits(max_instructions: i64, timeout_msec: i64) -> Self {
+        Self {
+            max_instructions: if max_instructions > 0 {
+                Some(max_instructions as u64)
+            } else {
+                None
+            },
+            timeout: if timeout_msec > 0 {
+                Some(Duration::from_millis(timeout_msec as u64))
+            } else {
+                None
+            },
+        }
+    }
+
+    pub fn is_unlimited(&self) -> bool {
+        self.max_instructions.is_none() && self.timeout.is_none()
+    }
+}
+
+/// Tracks how much of the active budget the running call has used.
+#[derive(Debug, Default)]
+pub struct BudgetTracker {
+    budget: Cell<ExecutionBudget>,
+    used: Cell<u64>,
+    started_at_msec: Cell<f64>,
+    /// Calls into Python in progress, more than one when Python calls back into the VM
+    depth: Cell<usize>,
+}
+
+/// One call into Python, see `BudgetTracker::start`.
+pub struct BudgetRun<'a> {
+    tracker: &'a BudgetTracker,
+}
+
+impl Drop for BudgetRun<'_> {
+    fn drop(&mut self) {
+        self.tracker.depth.set(self.tracker.depth.get() - 1);
+    }
+}
+
+impl BudgetTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts counting against `budget` until the returned run is dropped. Calls that
+    /// start while another one runs, e.g. from a host function Python called, count
+    /// against the outer call's budget instead, or a loop calling back into the VM
+    /// would reset it on every iteration.
+    #[must_use]
+    pub fn start(&self, budget: ExecutionBudget) -> BudgetRun<'_> {
+        if self.depth.get() == 0 {
+            self.budget.set(budget);
+            self.used.set(0);
+            self.started_at_msec.set(now_msec());
+        }
+        self.depth.set(self.depth.get() + 1);
+        BudgetRun { tracker: self }
+    }
+
+    pub fn used(&self) -> u64 {
+        self.used.get()
+    }
+
+    pub fn tick(&self, vm: &VirtualMachine) -> PyResult<()> {
+        let used = self.used.get() + 1;
+        self.used.set(used);
+
+        let budget = self.budget.get();
+        if let Some(max_instructions) = budget.max_instructions {
+            if used > max_instructions {
+                return Err(vm.new_exception_msg(
+                    vm.ctx.exceptions.timeout_error.to_owned(),
+                    format!(
+                        "Execution budget of {} instructions exhausted",
+                        max_instructions
+                    ),
+                ));
+            }
+        }
+        if let Some(timeout) = budget.timeout {
+            let elapsed = now_msec() - self.started_at_msec.get();
+            if elapsed > timeout.as_secs_f64() * 1000.0 {
+                return Err(vm.new_exception_msg(
+                    vm.ctx.exceptions.timeout_error.to_owned(),
+                    format!("Execution timed out after {} ms", timeout.as_millis()),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[pyclass(module = false, name = "BudgetTracker")]
+#[derive(Debug, PyPayload)]
+struct PyBudgetTracker(Rc<BudgetTracker>);
+
+#[pyclass]
+impl PyBudgetTracker {}
+
+/// Makes the code `vm` runs count against `tracker`. Without its tracker the tick
+/// raises, so removing it stops the code rather than lifting the budget.
+pub fn install_budget_tick(vm: &VirtualMachine, tracker: Rc<BudgetTracker>) {
+    PyBudgetTracker::make_class(&vm.ctx);
+    vm.builtins
+        .as_object()
+        .set_attr(TRACKER_NAME, PyBudgetTracker(tracker).into_ref(&vm.ctx), vm)
+        .expect("builtins to accept the budget tracker");
+
+    let tick = vm.new_function(
+        TICK_NAME,
+        |_args: FuncArgs, vm: &VirtualMachine| -> PyResult {
+            let tracker = vm
+                .builtins
+                .as_object()
+                .get_attr(TRACKER_NAME, vm)
+                .ok()
+                .and_then(|tracker| tracker.downcast::<PyBudgetTracker>().ok())
+                .ok_or_else(|| {
+                    vm.new_runtime_error("The execution budget tracker was removed".to_owned())
+                })?;
+            tracker.0.tick(vm)?;
+            Ok(vm.ctx.none())
+        },
+    );
+    vm.ctx
+        .types
+        .none_type
+        .set_attr(vm.ctx.intern_str(TICK_NAME), tick.into());
+}
+
+pub(crate) fn now_msec() -> f64 {
+    cfg_if::cfg_if! {
+        if #[cfg(target_arch = "wasm32")] {
+            js_sys::Date::now()
+        } else {
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs_f64() * 1000.0)
+                .unwrap_or(0.0)
+        }
+    }
+}
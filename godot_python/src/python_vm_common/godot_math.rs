@@ -0,0 +1,837 @@
+use rustpython_vm::{
+    builtins::{PyFloat, PyInt, PyModule, PyTypeRef},
+    common::hash::PyHash,
+    function::{FuncArgs, PyComparisonValue},
+    protocol::PyNumberMethods,
+    pyclass, pymodule,
+    types::{AsNumber, Comparable, Constructor, Hashable, Iterable, PyComparisonOp, Representable},
+    Py, PyObject, PyObjectRef, PyPayload, PyRef, PyResult, TryFromObject, VirtualMachine,
+};
+
+pub fn create_godot_math() -> Box<fn(&VirtualMachine) -> PyRef<PyModule>> {
+    Box::new(godot_math::make_module)
+}
+
+/// A component of a math type: a float (Godot's `real_t`), an int, or another math type.
+trait MathField: Sized {
+    fn to_py(self, vm: &VirtualMachine) -> PyObjectRef;
+    fn from_py(value: PyObjectRef, vm: &VirtualMachine) -> PyResult<Self>;
+}
+
+impl MathField for f32 {
+    fn to_py(self, vm: &VirtualMachine) -> PyObjectRef {
+        vm.ctx.new_float(self as f64).into()
+    }
+
+    fn from_py(value: PyObjectRef, vm: &VirtualMachine) -> PyResult<Self> {
+        f64::try_from_object(vm, value).map(|value| value as f32)
+    }
+}
+
+impl MathField for i32 {
+    fn to_py(self, vm: &VirtualMachine) -> PyObjectRef {
+        vm.ctx.new_int(self).into()
+    }
+
+    fn from_py(value: PyObjectRef, vm: &VirtualMachine) -> PyResult<Self> {
+        i32::try_from_object(vm, value)
+    }
+}
+
+/// Binds positional and keyword arguments to `fields`, in order. Fields not given are None.
+fn bind_fields(
+    type_name: &str,
+    fields: &[&str],
+    args: FuncArgs,
+    vm: &VirtualMachine,
+) -> PyResult<Vec<Option<PyObjectRef>>> {
+    let FuncArgs { args, mut kwargs } = args;
+    if args.len() > fields.len() {
+        return Err(vm.new_type_error(format!(
+            "{}() takes at most {} arguments ({} given)",
+            type_name,
+            fields.len(),
+            args.len()
+        )));
+    }
+
+    let mut positional = args.into_iter();
+    let bound = fields
+        .iter()
+        .map(|field| match positional.next() {
+            Some(_) if kwargs.contains_key(*field) => Err(vm.new_type_error(format!(
+                "{}() got multiple values for argument '{}'",
+                type_name, field
+            ))),
+            Some(value) => Ok(Some(value)),
+            None => Ok(kwargs.swap_remove(*field)),
+        })
+        .collect::<PyResult<Vec<_>>>()?;
+    if let Some(name) = kwargs.keys().next() {
+        return Err(vm.new_type_error(format!(
+            "{}() got an unexpected keyword argument '{}'",
+            type_name, name
+        )));
+    }
+    Ok(bound)
+}
+
+/// Declares an immutable, hashable value type of `godot.math`. Every field is a
+/// read-only attribute, a constructor argument with a default, and part of
+/// `repr()`, equality and iteration, e.g. `x, y = Vector2(1, 2)`. The optional
+/// `with(...) { ... }` adds slot traits and methods to the class.
+macro_rules! math_type {
+    (
+        $(#[$doc:meta])* $py_name:tt, $name:ident { $($field:ident: $ty:ty = $default:expr),* $(,)? }
+        $(with($($with:ident),*) { $($methods:tt)* })?
+    ) => {
+        $(#[$doc])*
+        #[pyclass(module = "godot.math", name = $py_name)]
+        #[derive(Debug, Clone, Copy, PartialEq, PyPayload)]
+        pub struct $name {
+            $(pub $field: $ty,)*
+        }
+
+        impl Default for $name {
+            fn default() -> Self {
+                Self { $($field: $default,)* }
+            }
+        }
+
+        impl MathField for $name {
+            fn to_py(self, vm: &VirtualMachine) -> PyObjectRef {
+                self.into_ref(&vm.ctx).into()
+            }
+
+            fn from_py(value: PyObjectRef, vm: &VirtualMachine) -> PyResult<Self> {
+                match value.downcast_ref::<Self>() {
+                    Some(value) => Ok(**value),
+                    None => Err(vm.new_type_error(format!(
+                        "expected {}, got {}",
+                        $py_name,
+                        value.class().name()
+                    ))),
+                }
+            }
+        }
+
+        impl $name {
+            fn components(&self, vm: &VirtualMachine) -> Vec<PyObjectRef> {
+                vec![$(self.$field.to_py(vm)),*]
+            }
+        }
+
+        #[pyclass(with(Constructor, Comparable, Hashable, Representable, Iterable $($(, $with)*)?))]
+        impl $name {
+            $(
+                #[pygetset]
+                fn $field(&self, vm: &VirtualMachine) -> PyObjectRef {
+                    self.$field.to_py(vm)
+                }
+            )*
+
+            $($($methods)*)?
+        }
+
+        impl Constructor for $name {
+            type Args = FuncArgs;
+
+            fn py_new(cls: PyTypeRef, args: FuncArgs, vm: &VirtualMachine) -> PyResult {
+                let mut bound =
+                    bind_fields($py_name, &[$(stringify!($field)),*], args, vm)?.into_iter();
+                let mut value = Self::default();
+                $(
+                    if let Some(field) = bound.next().flatten() {
+                        value.$field = MathField::from_py(field, vm)?;
+                    }
+                )*
+                value.into_ref_with_type(vm, cls).map(Into::into)
+            }
+        }
+
+        impl Comparable for $name {
+            fn cmp(
+                zelf: &Py<Self>,
+                other: &PyObject,
+                op: PyComparisonOp,
+                _vm: &VirtualMachine,
+            ) -> PyResult<PyComparisonValue> {
+                op.eq_only(|| match other.downcast_ref::<Self>() {
+                    Some(other) => Ok(PyComparisonValue::Implemented(**zelf == **other)),
+                    None => Ok(PyComparisonValue::NotImplemented),
+                })
+            }
+        }
+
+        impl Hashable for $name {
+            fn hash(zelf: &Py<Self>, vm: &VirtualMachine) -> PyResult<PyHash> {
+                vm.ctx.new_tuple(zelf.components(vm)).as_object().hash(vm)
+            }
+        }
+
+        impl Representable for $name {
+            fn repr_str(zelf: &Py<Self>, vm: &VirtualMachine) -> PyResult<String> {
+                let components = zelf
+                    .components(vm)
+                    .iter()
+                    .map(|component| component.repr(vm).map(|repr| repr.as_str().to_owned()))
+                    .collect::<PyResult<Vec<_>>>()?;
+                Ok(format!("{}({})", $py_name, components.join(", ")))
+            }
+        }
+
+        impl Iterable for $name {
+            fn iter(zelf: PyRef<Self>, vm: &VirtualMachine) -> PyResult {
+                vm.call_method(vm.ctx.new_tuple(zelf.components(vm)).as_object(), "__iter__", ())
+            }
+        }
+    };
+}
+
+/// Component-wise access to the vector types and Color.
+trait Components: Copy {
+    type Scalar: Copy;
+
+    fn splat(value: Self::Scalar) -> Self;
+    fn map(self, f: impl Fn(Self::Scalar) -> Self::Scalar) -> Self;
+    fn zip(self, other: Self, f: impl Fn(Self::Scalar, Self::Scalar) -> Self::Scalar) -> Self;
+    fn to_vec(self) -> Vec<Self::Scalar>;
+}
+
+macro_rules! components {
+    ($name:ident, $scalar:ty, $($field:ident),*) => {
+        impl Components for $name {
+            type Scalar = $scalar;
+
+            fn splat(value: $scalar) -> Self {
+                Self { $($field: value),* }
+            }
+
+            fn map(self, f: impl Fn($scalar) -> $scalar) -> Self {
+                Self { $($field: f(self.$field)),* }
+            }
+
+            fn zip(self, other: Self, f: impl Fn($scalar, $scalar) -> $scalar) -> Self {
+                Self { $($field: f(self.$field, other.$field)),* }
+            }
+
+            fn to_vec(self) -> Vec<$scalar> {
+                vec![$(self.$field),*]
+            }
+        }
+    };
+}
+
+/// Godot's arithmetic on vectors and colors: component-wise with the same type,
+/// and scaling by a number with `*` on either side or `/` on the right. Other
+/// operands are NotImplemented, so Python raises its usual TypeError.
+#[derive(Clone, Copy, PartialEq)]
+enum Arithmetic {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+impl Arithmetic {
+    fn takes_number(self, on_left: bool) -> bool {
+        match self {
+            Arithmetic::Add | Arithmetic::Subtract => false,
+            Arithmetic::Multiply => true,
+            Arithmetic::Divide => !on_left,
+        }
+    }
+
+    /// In `real_t` like Godot, dividing by zero gives inf or nan.
+    fn float(self, a: f32, b: f32) -> f32 {
+        match self {
+            Arithmetic::Add => a + b,
+            Arithmetic::Subtract => a - b,
+            Arithmetic::Multiply => a * b,
+            Arithmetic::Divide => a / b,
+        }
+    }
+
+    /// Wraps on overflow and truncates division like Godot. The caller rules out dividing by zero.
+    fn int(self, a: i32, b: i32) -> i32 {
+        match self {
+            Arithmetic::Add => a.wrapping_add(b),
+            Arithmetic::Subtract => a.wrapping_sub(b),
+            Arithmetic::Multiply => a.wrapping_mul(b),
+            Arithmetic::Divide => a.wrapping_div(b),
+        }
+    }
+}
+
+/// Both operands as a `V`, a number operand becomes a `V` with every component set to it.
+fn operands<V: Components + PyPayload>(
+    a: &PyObject,
+    b: &PyObject,
+    op: Arithmetic,
+    number: impl Fn(&PyObject) -> Option<V::Scalar>,
+) -> Option<(V, V)> {
+    match (a.downcast_ref::<V>(), b.downcast_ref::<V>()) {
+        (Some(a), Some(b)) => Some((**a, **b)),
+        (Some(a), None) if op.takes_number(false) => number(b).map(|b| (**a, V::splat(b))),
+        (None, Some(b)) if op.takes_number(true) => number(a).map(|a| (V::splat(a), **b)),
+        _ => None,
+    }
+}
+
+fn float_arithmetic<V: Components<Scalar = f32> + MathField + PyPayload>(
+    a: &PyObject,
+    b: &PyObject,
+    op: Arithmetic,
+    vm: &VirtualMachine,
+) -> PyResult {
+    let number = |value: &PyObject| {
+        if value.payload_is::<PyInt>() || value.payload_is::<PyFloat>() {
+            f64::try_from_object(vm, value.to_owned())
+                .ok()
+                .map(|value| value as f32)
+        } else {
+            None
+        }
+    };
+    Ok(match operands::<V>(a, b, op, number) {
+        Some((a, b)) => a.zip(b, |a, b| op.float(a, b)).to_py(vm),
+        None => vm.ctx.not_implemented(),
+    })
+}
+
+/// An integer vector and the float vector a float operand turns it into.
+trait IntVector: Components<Scalar = i32> + MathField + PyPayload {
+    type Float: Components<Scalar = f32> + MathField + PyPayload + From<Self>;
+}
+
+fn int_arithmetic<V: IntVector>(
+    a: &PyObject,
+    b: &PyObject,
+    op: Arithmetic,
+    vm: &VirtualMachine,
+) -> PyResult {
+    // Like in Godot, `Vector2i(3, 4) * 0.5` is `Vector2(1.5, 2.0)`
+    if a.payload_is::<PyFloat>() || b.payload_is::<PyFloat>() {
+        let promote = |value: &PyObject| match value.downcast_ref::<V>() {
+            Some(vector) => V::Float::from(**vector).to_py(vm),
+            None => value.to_owned(),
+        };
+        return float_arithmetic::<V::Float>(&promote(a), &promote(b), op, vm);
+    }
+
+    let number = |value: &PyObject| {
+        if value.payload_is::<PyInt>() {
+            i32::try_from_object(vm, value.to_owned()).ok()
+        } else {
+            None
+        }
+    };
+    match operands::<V>(a, b, op, number) {
+        Some((_, b)) if op == Arithmetic::Divide && b.to_vec().contains(&0) => {
+            Err(vm.new_zero_division_error("integer division by zero".to_owned()))
+        }
+        Some((a, b)) => Ok(a.zip(b, |a, b| op.int(a, b)).to_py(vm)),
+        None => Ok(vm.ctx.not_implemented()),
+    }
+}
+
+/// `+ - * /` and unary `-` through `$arithmetic`, see `Arithmetic`.
+macro_rules! arithmetic_number {
+    ($name:ident, $arithmetic:ident, $negative:expr) => {
+        impl AsNumber for $name {
+            fn as_number() -> &'static PyNumberMethods {
+                static AS_NUMBER: PyNumberMethods = PyNumberMethods {
+                    add: Some(|a, b, vm| $arithmetic::<$name>(a, b, Arithmetic::Add, vm)),
+                    subtract: Some(|a, b, vm| $arithmetic::<$name>(a, b, Arithmetic::Subtract, vm)),
+                    multiply: Some(|a, b, vm| $arithmetic::<$name>(a, b, Arithmetic::Multiply, vm)),
+                    true_divide: Some(|a, b, vm| {
+                        $arithmetic::<$name>(a, b, Arithmetic::Divide, vm)
+                    }),
+                    negative: Some(|number, vm| {
+                        Ok((**$name::number_downcast(number)).map($negative).to_py(vm))
+                    }),
+                    ..PyNumberMethods::NOT_IMPLEMENTED
+                };
+                &AS_NUMBER
+            }
+        }
+    };
+}
+
+fn vector_dot<V: Components<Scalar = f32>>(a: V, b: V) -> f32 {
+    a.zip(b, |a, b| a * b)
+        .to_vec()
+        .into_iter()
+        .reduce(|sum, c| sum + c)
+        .unwrap_or_default()
+}
+
+fn vector_length<V: Components<Scalar = f32>>(v: V) -> f32 {
+    vector_dot(v, v).sqrt()
+}
+
+/// A float vector with Godot's vector methods, plus `$methods`.
+macro_rules! float_vector {
+    ($py_name:tt, $name:ident { $($field:ident),* } { $($methods:tt)* }) => {
+        math_type!($py_name, $name { $($field: f32 = 0.0),* } with(AsNumber) {
+            #[pymethod]
+            fn length(&self) -> f64 {
+                vector_length(*self) as f64
+            }
+
+            #[pymethod]
+            fn length_squared(&self) -> f64 {
+                vector_dot(*self, *self) as f64
+            }
+
+            /// The zero vector stays zero, like in Godot.
+            #[pymethod]
+            fn normalized(&self) -> Self {
+                let length = vector_length(*self);
+                if length == 0.0 {
+                    *self
+                } else {
+                    self.map(|c| c / length)
+                }
+            }
+
+            #[pymethod]
+            fn dot(&self, with: PyRef<Self>) -> f64 {
+                vector_dot(*self, **with) as f64
+            }
+
+            #[pymethod]
+            fn distance_to(&self, to: PyRef<Self>) -> f64 {
+                vector_length(self.zip(**to, |a, b| a - b)) as f64
+            }
+
+            #[pymethod]
+            fn distance_squared_to(&self, to: PyRef<Self>) -> f64 {
+                let difference = self.zip(**to, |a, b| a - b);
+                vector_dot(difference, difference) as f64
+            }
+
+            #[pymethod]
+            fn lerp(&self, to: PyRef<Self>, weight: f64) -> Self {
+                let weight = weight as f32;
+                self.zip(**to, |from, to| from + (to - from) * weight)
+            }
+
+            #[pymethod]
+            fn abs(&self) -> Self {
+                self.map(f32::abs)
+            }
+
+            $($methods)*
+        });
+        components!($name, f32, $($field),*);
+        arithmetic_number!($name, float_arithmetic, |c: f32| -c);
+    };
+}
+
+/// An integer vector, `$float` is what it becomes when combined with a float.
+macro_rules! int_vector {
+    ($py_name:tt, $name:ident { $($field:ident),* } => $float:ident) => {
+        math_type!($py_name, $name { $($field: i32 = 0),* } with(AsNumber) {
+            #[pymethod]
+            fn length(&self) -> f64 {
+                (self.length_squared() as f64).sqrt()
+            }
+
+            #[pymethod]
+            fn length_squared(&self) -> i64 {
+                self.to_vec().into_iter().map(|c| c as i64 * c as i64).sum()
+            }
+
+            #[pymethod]
+            fn abs(&self) -> Self {
+                self.map(i32::wrapping_abs)
+            }
+        });
+        components!($name, i32, $($field),*);
+        arithmetic_number!($name, int_arithmetic, i32::wrapping_neg);
+
+        impl IntVector for $name {
+            type Float = $float;
+        }
+
+        impl From<$name> for $float {
+            fn from(v: $name) -> Self {
+                Self { $($field: v.$field as f32),* }
+            }
+        }
+    };
+}
+
+float_vector!("Vector2", PyVector2 { x, y } {
+    /// The z of the 3D cross product, like Godot's `Vector2.cross`.
+    #[pymethod]
+    fn cross(&self, with: PyRef<Self>) -> f64 {
+        (self.x * with.y - self.y * with.x) as f64
+    }
+
+    #[pymethod]
+    fn angle(&self) -> f64 {
+        self.y.atan2(self.x) as f64
+    }
+});
+float_vector!("Vector3", PyVector3 { x, y, z } {
+    #[pymethod]
+    fn cross(&self, with: PyRef<Self>) -> Self {
+        self.cross_with(**with)
+    }
+});
+float_vector!("Vector4", PyVector4 { x, y, z, w } {});
+int_vector!("Vector2i", PyVector2i { x, y } => PyVector2);
+int_vector!("Vector3i", PyVector3i { x, y, z } => PyVector3);
+int_vector!("Vector4i", PyVector4i { x, y, z, w } => PyVector4);
+
+math_type!(
+    "Color",
+    PyColor {
+        r: f32 = 0.0,
+        g: f32 = 0.0,
+        b: f32 = 0.0,
+        a: f32 = 1.0
+    } with(AsNumber) {
+        #[pymethod]
+        fn lerp(&self, to: PyRef<Self>, weight: f64) -> Self {
+            let weight = weight as f32;
+            self.zip(**to, |from, to| from + (to - from) * weight)
+        }
+    }
+);
+components!(PyColor, f32, r, g, b, a);
+arithmetic_number!(PyColor, float_arithmetic, |c: f32| -c);
+
+math_type!(
+    "Quaternion",
+    PyQuaternion {
+        x: f32 = 0.0,
+        y: f32 = 0.0,
+        z: f32 = 0.0,
+        w: f32 = 1.0
+    }
+);
+math_type!(
+    "Plane",
+    PyPlane {
+        normal: PyVector3 = PyVector3::default(),
+        d: f32 = 0.0
+    }
+);
+math_type!(
+    "Rect2",
+    PyRect2 {
+        position: PyVector2 = PyVector2::default(),
+        size: PyVector2 = PyVector2::default(),
+    }
+);
+math_type!(
+    "Rect2i",
+    PyRect2i {
+        position: PyVector2i = PyVector2i::default(),
+        size: PyVector2i = PyVector2i::default(),
+    }
+);
+math_type!(
+    "AABB",
+    PyAabb {
+        position: PyVector3 = PyVector3::default(),
+        size: PyVector3 = PyVector3::default(),
+    }
+);
+math_type!(
+    /// `x`, `y` and `z` are the columns, like in GDScript. `*` takes a Basis or a Vector3.
+    "Basis", PyBasis {
+        x: PyVector3 = PyVector3 { x: 1.0, y: 0.0, z: 0.0 },
+        y: PyVector3 = PyVector3 { x: 0.0, y: 1.0, z: 0.0 },
+        z: PyVector3 = PyVector3 { x: 0.0, y: 0.0, z: 1.0 },
+    } with(AsNumber) {
+        #[pymethod]
+        fn determinant(&self) -> f64 {
+            vector_dot(self.x, self.y.cross_with(self.z)) as f64
+        }
+    }
+);
+math_type!(
+    /// `*` takes a Transform2D or a Vector2.
+    "Transform2D", PyTransform2D {
+        x: PyVector2 = PyVector2 { x: 1.0, y: 0.0 },
+        y: PyVector2 = PyVector2 { x: 0.0, y: 1.0 },
+        origin: PyVector2 = PyVector2::default(),
+    } with(AsNumber) {
+        /// Applies only the rotation, scale and skew, not the origin.
+        #[pymethod]
+        fn basis_xform(&self, v: PyRef<PyVector2>) -> PyVector2 {
+            self.transform_basis(**v)
+        }
+
+        #[pymethod]
+        fn determinant(&self) -> f64 {
+            self.basis_determinant() as f64
+        }
+
+        #[pymethod]
+        fn affine_inverse(&self, vm: &VirtualMachine) -> PyResult<Self> {
+            let det = self.basis_determinant();
+            if det == 0.0 {
+                return Err(vm.new_value_error("Transform2D is not invertible".to_owned()));
+            }
+            let idet = 1.0 / det;
+            let basis = Self {
+                x: PyVector2 {
+                    x: self.y.y * idet,
+                    y: -self.x.y * idet,
+                },
+                y: PyVector2 {
+                    x: -self.y.x * idet,
+                    y: self.x.x * idet,
+                },
+                origin: PyVector2::default(),
+            };
+            Ok(Self {
+                origin: basis.transform_basis(self.origin.map(|c| -c)),
+                ..basis
+            })
+        }
+    }
+);
+math_type!(
+    /// `*` takes a Transform3D or a Vector3.
+    "Transform3D", PyTransform3D {
+        basis: PyBasis = PyBasis::default(),
+        origin: PyVector3 = PyVector3::default(),
+    } with(AsNumber) {}
+);
+math_type!(
+    /// `x`, `y`, `z` and `w` are the columns, like in GDScript.
+    "Projection", PyProjection {
+        x: PyVector4 = PyVector4 { x: 1.0, y: 0.0, z: 0.0, w: 0.0 },
+        y: PyVector4 = PyVector4 { x: 0.0, y: 1.0, z: 0.0, w: 0.0 },
+        z: PyVector4 = PyVector4 { x: 0.0, y: 0.0, z: 1.0, w: 0.0 },
+        w: PyVector4 = PyVector4 { x: 0.0, y: 0.0, z: 0.0, w: 1.0 },
+    }
+);
+
+impl PyVector3 {
+    fn cross_with(self, with: Self) -> Self {
+        Self {
+            x: self.y * with.z - self.z * with.y,
+            y: self.z * with.x - self.x * with.z,
+            z: self.x * with.y - self.y * with.x,
+        }
+    }
+}
+
+impl PyBasis {
+    fn transform(self, v: PyVector3) -> PyVector3 {
+        PyVector3 {
+            x: self.x.x * v.x + self.y.x * v.y + self.z.x * v.z,
+            y: self.x.y * v.x + self.y.y * v.y + self.z.y * v.z,
+            z: self.x.z * v.x + self.y.z * v.y + self.z.z * v.z,
+        }
+    }
+
+    fn compose(self, other: Self) -> Self {
+        Self {
+            x: self.transform(other.x),
+            y: self.transform(other.y),
+            z: self.transform(other.z),
+        }
+    }
+}
+
+impl PyTransform2D {
+    fn transform_basis(self, v: PyVector2) -> PyVector2 {
+        PyVector2 {
+            x: self.x.x * v.x + self.y.x * v.y,
+            y: self.x.y * v.x + self.y.y * v.y,
+        }
+    }
+
+    fn transform(self, v: PyVector2) -> PyVector2 {
+        self.transform_basis(v).zip(self.origin, |a, b| a + b)
+    }
+
+    fn compose(self, other: Self) -> Self {
+        Self {
+            x: self.transform_basis(other.x),
+            y: self.transform_basis(other.y),
+            origin: self.transform(other.origin),
+        }
+    }
+
+    fn basis_determinant(self) -> f32 {
+        self.x.x * self.y.y - self.x.y * self.y.x
+    }
+}
+
+impl PyTransform3D {
+    fn transform(self, v: PyVector3) -> PyVector3 {
+        self.basis.transform(v).zip(self.origin, |a, b| a + b)
+    }
+
+    fn compose(self, other: Self) -> Self {
+        Self {
+            basis: self.basis.compose(other.basis),
+            origin: self.transform(other.origin),
+        }
+    }
+}
+
+/// `a * b` for a transform-like `T` on the left. `product` gives None for operands `T` cannot multiply.
+fn multiply<T: PyPayload + Copy>(
+    a: &PyObject,
+    b: &PyObject,
+    vm: &VirtualMachine,
+    product: impl Fn(T, &PyObject) -> Option<PyObjectRef>,
+) -> PyResult {
+    Ok(a.downcast_ref::<T>()
+        .and_then(|a| product(**a, b))
+        .unwrap_or_else(|| vm.ctx.not_implemented()))
+}
+
+impl AsNumber for PyBasis {
+    fn as_number() -> &'static PyNumberMethods {
+        static AS_NUMBER: PyNumberMethods = PyNumberMethods {
+            multiply: Some(|a, b, vm| {
+                multiply::<PyBasis>(a, b, vm, |basis, b| {
+                    if let Some(v) = b.downcast_ref::<PyVector3>() {
+                        Some(basis.transform(**v).to_py(vm))
+                    } else {
+                        b.downcast_ref::<PyBasis>()
+                            .map(|other| basis.compose(**other).to_py(vm))
+                    }
+                })
+            }),
+            ..PyNumberMethods::NOT_IMPLEMENTED
+        };
+        &AS_NUMBER
+    }
+}
+
+impl AsNumber for PyTransform2D {
+    fn as_number() -> &'static PyNumberMethods {
+        static AS_NUMBER: PyNumberMethods = PyNumberMethods {
+            multiply: Some(|a, b, vm| {
+                multiply::<PyTransform2D>(a, b, vm, |transform, b| {
+                    if let Some(v) = b.downcast_ref::<PyVector2>() {
+                        Some(transform.transform(**v).to_py(vm))
+                    } else {
+                        b.downcast_ref::<PyTransform2D>()
+                            .map(|other| transform.compose(**other).to_py(vm))
+                    }
+                })
+            }),
+            ..PyNumberMethods::NOT_IMPLEMENTED
+        };
+        &AS_NUMBER
+    }
+}
+
+impl AsNumber for PyTransform3D {
+    fn as_number() -> &'static PyNumberMethods {
+        static AS_NUMBER: PyNumberMethods = PyNumberMethods {
+            multiply: Some(|a, b, vm| {
+                multiply::<PyTransform3D>(a, b, vm, |transform, b| {
+                    if let Some(v) = b.downcast_ref::<PyVector3>() {
+                        Some(transform.transform(**v).to_py(vm))
+                    } else {
+                        b.downcast_ref::<PyTransform3D>()
+                            .map(|other| transform.compose(**other).to_py(vm))
+                    }
+                })
+            }),
+            ..PyNumberMethods::NOT_IMPLEMENTED
+        };
+        &AS_NUMBER
+    }
+}
+
+/// Godot's value types, see `godot.py` for the rest of the Variant types.
+#[pymodule]
+pub mod godot_math {
+    use super::*;
+    use rustpython_vm::class::PyClassImpl;
+
+    #[pyattr(name = "Vector2")]
+    fn vector2(vm: &VirtualMachine) -> PyTypeRef {
+        PyVector2::make_class(&vm.ctx)
+    }
+
+    #[pyattr(name = "Vector2i")]
+    fn vector2i(vm: &VirtualMachine) -> PyTypeRef {
+        PyVector2i::make_class(&vm.ctx)
+    }
+
+    #[pyattr(name = "Vector3")]
+    fn vector3(vm: &VirtualMachine) -> PyTypeRef {
+        PyVector3::make_class(&vm.ctx)
+    }
+
+    #[pyattr(name = "Vector3i")]
+    fn vector3i(vm: &VirtualMachine) -> PyTypeRef {
+        PyVector3i::make_class(&vm.ctx)
+    }
+
+    #[pyattr(name = "Vector4")]
+    fn vector4(vm: &VirtualMachine) -> PyTypeRef {
+        PyVector4::make_class(&vm.ctx)
+    }
+
+    #[pyattr(name = "Vector4i")]
+    fn vector4i(vm: &VirtualMachine) -> PyTypeRef {
+        PyVector4i::make_class(&vm.ctx)
+    }
+
+    #[pyattr(name = "Color")]
+    fn color(vm: &VirtualMachine) -> PyTypeRef {
+        PyColor::make_class(&vm.ctx)
+    }
+
+    #[pyattr(name = "Quaternion")]
+    fn quaternion(vm: &VirtualMachine) -> PyTypeRef {
+        PyQuaternion::make_class(&vm.ctx)
+    }
+
+    #[pyattr(name = "Plane")]
+    fn plane(vm: &VirtualMachine) -> PyTypeRef {
+        PyPlane::make_class(&vm.ctx)
+    }
+
+    #[pyattr(name = "Rect2")]
+    fn rect2(vm: &VirtualMachine) -> PyTypeRef {
+        PyRect2::make_class(&vm.ctx)
+    }
+
+    #[pyattr(name = "Rect2i")]
+    fn rect2i(vm: &VirtualMachine) -> PyTypeRef {
+        PyRect2i::make_class(&vm.ctx)
+    }
+
+    #[pyattr(name = "AABB")]
+    fn aabb(vm: &VirtualMachine) -> PyTypeRef {
+        PyAabb::make_class(&vm.ctx)
+    }
+
+    #[pyattr(name = "Basis")]
+    fn basis(vm: &VirtualMachine) -> PyTypeRef {
+        PyBasis::make_class(&vm.ctx)
+    }
+
+    #[pyattr(name = "Transform2D")]
+    fn transform2d(vm: &VirtualMachine) -> PyTypeRef {
+        PyTransform2D::make_class(&vm.ctx)
+    }
+
+    #[pyattr(name = "Transform3D")]
+    fn transform3d(vm: &VirtualMachine) -> PyTypeRef {
+        PyTransform3D::make_class(&vm.ctx)
+    }
+
+    #[pyattr(name = "Projection")]
+    fn projection(vm: &VirtualMachine) -> PyTypeRef {
+        PyProjection::make_class(&vm.ctx)
+    }
+}
@@ -0,0 +1,138 @@
+use rustpython_vm::{PyObjectRef, PyResult, TryFromObject, VirtualMachine};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemberKind {
+    Function,
+    Class,
+    Module,
+    Value,
+}
+
+impl MemberKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MemberKind::Function => "function",
+            MemberKind::Class => "class",
+            MemberKind::Module => "module",
+            MemberKind::Value => "value",
+        }
+    }
+
+    fn parse(kind: &str) -> Self {
+        match kind {
+            "function" => MemberKind::Function,
+            "class" => MemberKind::Class,
+            "module" => MemberKind::Module,
+            _ => MemberKind::Value,
+        }
+    }
+}
+
+/// A public global of a loaded module.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModuleMember {
+    pub name: String,
+    pub kind: MemberKind,
+    /// The Python type name, e.g. `int` or `function`
+    pub type_name: String,
+}
+
+impl ModuleMember {
+    /// Reads the list returned by `introspection.list_members`.
+    pub fn from_py_list(vm: &VirtualMachine, members: PyObjectRef) -> PyResult<Vec<Self>> {
+        let members = Vec::<(String, String, String)>::try_from_object(vm, members)?;
+        Ok(members
+            .into_iter()
+            .map(|(name, kind, type_name)| Self {
+                name,
+                kind: MemberKind::parse(&kind),
+                type_name,
+            })
+            .collect())
+    }
+}
+
+/// Matches `inspect.Parameter.kind`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParameterKind {
+    PositionalOnly,
+    PositionalOrKeyword,
+    /// `*args`
+    VarPositional,
+    KeywordOnly,
+    /// `**kwargs`
+    VarKeyword,
+}
+
+impl ParameterKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ParameterKind::PositionalOnly => "positional_only",
+            ParameterKind::PositionalOrKeyword => "positional_or_keyword",
+            ParameterKind::VarPositional => "var_positional",
+            ParameterKind::KeywordOnly => "keyword_only",
+            ParameterKind::VarKeyword => "var_keyword",
+        }
+    }
+
+    fn parse(kind: &str) -> Self {
+        match kind {
+            "positional_only" => ParameterKind::PositionalOnly,
+            "var_positional" => ParameterKind::VarPositional,
+            "keyword_only" => ParameterKind::KeywordOnly,
+            "var_keyword" => ParameterKind::VarKeyword,
+            _ => ParameterKind::PositionalOrKeyword,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Parameter {
+    pub name: String,
+    pub kind: ParameterKind,
+    /// The default value, left as a Python object so each host converts it its own way
+    pub default: Option<PyObjectRef>,
+    /// Annotations are formatted as source text, e.g. `list[int]`
+    pub annotation: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FunctionSignature {
+    pub name: String,
+    pub parameters: Vec<Parameter>,
+    pub return_annotation: Option<String>,
+    pub doc: Option<String>,
+}
+
+type PyParameter = (String, String, bool, PyObjectRef, Option<String>);
+
+impl FunctionSignature {
+    /// Reads the tuple returned by `introspection.get_signature`.
+    pub fn from_py_tuple(vm: &VirtualMachine, signature: PyObjectRef) -> PyResult<Self> {
+        let (name, parameters, return_annotation, doc) =
+            <(String, Vec<PyObjectRef>, Option<String>, Option<String>)>::try_from_object(
+                vm, signature,
+            )?;
+
+        let parameters = parameters
+            .into_iter()
+            .map(|parameter| {
+                let (name, kind, has_default, default, annotation) =
+                    PyParameter::try_from_object(vm, parameter)?;
+                Ok(Parameter {
+                    name,
+                    kind: ParameterKind::parse(&kind),
+                    default: if has_default { Some(default) } else { None },
+                    annotation,
+                })
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+
+        Ok(Self {
+            name,
+            parameters,
+            return_annotation,
+            doc,
+        })
+    }
+}
@@ -0,0 +1,30 @@
+use rustpython_vm::{PyObjectRef, PyResult, TryFromObject, VirtualMachine};
+
+/// What changed when a module was reloaded. Functions are named by their
+/// dotted path inside the module, e.g. `move` or `Player.jump`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReloadDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<String>,
+    /// Globals listed in `__persist__` that kept their value from before the reload
+    pub persisted: Vec<String>,
+    /// Modules that imported functions or classes from the reloaded module
+    pub updated_modules: Vec<String>,
+}
+
+impl ReloadDiff {
+    /// Reads the dict returned by `module_reloader.reload`.
+    pub fn from_py_dict(vm: &VirtualMachine, diff: &PyObjectRef) -> PyResult<Self> {
+        let names = |key: &str| -> PyResult<Vec<String>> {
+            Vec::<String>::try_from_object(vm, diff.get_item(key, vm)?)
+        };
+        Ok(Self {
+            added: names("added")?,
+            removed: names("removed")?,
+            changed: names("changed")?,
+            persisted: names("persisted")?,
+            updated_modules: names("updated_modules")?,
+        })
+    }
+}
@@ -0,0 +1,93 @@
+use std::{
+    collections::HashMap,
+    io::{Cursor, Read},
+};
+
+use super::{package_loader::PackageFile, python_error::PythonError};
+
+/// Somewhere Python `import` can find modules on demand.
+///
+/// Sources are asked again every time a module is executed, so
+/// `importlib.reload` picks up changed source.
+pub trait ModuleSource {
+    /// Returns the module registered under the dotted `module_name`, if this source has it.
+    fn find_module(&self, module_name: &str) -> Option<PackageFile>;
+}
+
+/// Modules kept in memory, keyed by dotted name.
+#[derive(Debug, Default)]
+pub struct InMemorySource {
+    modules: HashMap<String, PackageFile>,
+}
+
+impl InMemorySource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_files(files: Vec<PackageFile>) -> Self {
+        let mut source = Self::new();
+        files.into_iter().for_each(|file| source.insert(file));
+        source
+    }
+
+    pub fn insert(&mut self, file: PackageFile) {
+        self.modules.insert(file.module_name.clone(), file);
+    }
+
+    pub fn remove(&mut self, module_name: &str) -> Option<PackageFile> {
+        self.modules.remove(module_name)
+    }
+}
+
+impl ModuleSource for InMemorySource {
+    fn find_module(&self, module_name: &str) -> Option<PackageFile> {
+        self.modules.get(module_name).cloned()
+    }
+}
+
+/// Every `.py` file of a zip archive. Paths inside the archive map to module
+/// names, so `game/ai/__init__.py` provides the `game.ai` package.
+#[derive(Debug)]
+pub struct ZipSource {
+    modules: InMemorySource,
+}
+
+impl ZipSource {
+    /// Reads the archive up front. `archive_name` prefixes `__file__`, e.g. `mods.zip/game/ai.py`.
+    pub fn new(bytes: Vec<u8>, archive_name: &str) -> Result<Self, PythonError> {
+        let zip_error = |e: zip::result::ZipError| {
+            PythonError::host("ValueError", format!("Invalid zip archive: {}", e))
+        };
+        let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).map_err(zip_error)?;
+
+        let mut modules = InMemorySource::new();
+        for i in 0..archive.len() {
+            let mut entry = archive.by_index(i).map_err(zip_error)?;
+            if !entry.is_file() || !entry.name().ends_with(".py") {
+                continue;
+            }
+
+            let path = entry.name().to_owned();
+            let mut source = String::new();
+            entry.read_to_string(&mut source).map_err(|e| {
+                PythonError::host(
+                    "UnicodeDecodeError",
+                    format!("Could not read {:?} from the archive: {}", path, e),
+                )
+            })?;
+
+            if let Some(file) = PackageFile::from_relative_path("", archive_name, &path, source) {
+                modules.insert(file);
+            }
+        }
+
+        Ok(Self { modules })
+    }
+}
+
+impl ModuleSource for ZipSource {
+    fn find_module(&self, module_name: &str) -> Option<PackageFile> {
+        self.modules.find_module(module_name)
+    }
+}
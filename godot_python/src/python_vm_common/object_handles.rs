@@ -0,0 +1,91 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use rustpython_vm::PyObjectRef;
+
+pub type HandleId = u64;
+
+#[derive(Default)]
+struct Registry {
+    next_id: HandleId,
+    /// Handle -> (object, number of host references)
+    entries: HashMap<HandleId, (PyObjectRef, usize)>,
+    /// Object identity -> handle, so the same object always gets the same handle
+    ids: HashMap<usize, HandleId>,
+}
+
+/// Python objects the host holds on to. Each host reference keeps the object
+/// alive until it is released; clones share the same registry.
+#[derive(Clone, Default)]
+pub struct ObjectHandles {
+    registry: Rc<RefCell<Registry>>,
+}
+
+impl ObjectHandles {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a host reference to `object` and returns its handle.
+    pub fn register(&self, object: PyObjectRef) -> HandleId {
+        let mut registry = self.registry.borrow_mut();
+        if let Some(&handle) = registry.ids.get(&object.get_id()) {
+            registry.entries.get_mut(&handle).unwrap().1 += 1;
+            return handle;
+        }
+
+        registry.next_id += 1;
+        let handle = registry.next_id;
+        registry.ids.insert(object.get_id(), handle);
+        registry.entries.insert(handle, (object, 1));
+        handle
+    }
+
+    pub fn get(&self, handle: HandleId) -> Option<PyObjectRef> {
+        self.registry
+            .borrow()
+            .entries
+            .get(&handle)
+            .map(|(object, _)| object.clone())
+    }
+
+    /// Drops one host reference. The object is let go once none are left.
+    /// Returns false if the handle was already fully released.
+    pub fn release(&self, handle: HandleId) -> bool {
+        let released = {
+            let mut registry = self.registry.borrow_mut();
+            let entry = match registry.entries.get_mut(&handle) {
+                Some(entry) => entry,
+                None => return false,
+            };
+            entry.1 -= 1;
+            if entry.1 > 0 {
+                return true;
+            }
+            let (object, _) = registry.entries.remove(&handle).unwrap();
+            registry.ids.remove(&object.get_id());
+            object
+        };
+        // Dropped after the borrow ends, in case a `__del__` registers a handle
+        drop(released);
+        true
+    }
+
+    /// Number of objects currently held for the host.
+    pub fn len(&self) -> usize {
+        self.registry.borrow().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// True if both refer to the same registry, i.e. the same VM.
+    pub fn ptr_eq(&self, other: &ObjectHandles) -> bool {
+        Rc::ptr_eq(&self.registry, &other.registry)
+    }
+
+    /// Identifies the registry, for hosts that can only compare plain values.
+    pub fn registry_id(&self) -> usize {
+        Rc::as_ptr(&self.registry) as usize
+    }
+}
@@ -0,0 +1,282 @@
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+
+use super::execution_budget::now_msec;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+impl OutputStream {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "stdout" => Some(Self::Stdout),
+            "stderr" => Some(Self::Stderr),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Stdout => "stdout",
+            Self::Stderr => "stderr",
+        }
+    }
+}
+
+/// A piece of text Python wrote to `sys.stdout` or `sys.stderr`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputChunk {
+    pub stream: OutputStream,
+    /// The text as Python wrote it
+    pub text: String,
+    /// `text` with the VM's `OutputMarkup` applied, the same as `text` without one
+    pub formatted: String,
+    /// `__name__` of the module whose code wrote it, `None` for eval and session code
+    pub source: Option<String>,
+    /// Milliseconds since the Unix epoch
+    pub timestamp_msec: f64,
+}
+
+/// Everything one call wrote, see `CommonPythonVM::with_captured_output`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CapturedOutput {
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// Decorates output before it reaches the host, e.g. for a rich text label.
+pub trait OutputMarkup {
+    fn apply(&self, chunk: &OutputChunk) -> String;
+}
+
+/// Wraps stderr output in a BBCode color tag, for Godot's `RichTextLabel`.
+pub struct BBCodeMarkup {
+    pub stderr_color: String,
+}
+
+impl Default for BBCodeMarkup {
+    fn default() -> Self {
+        Self {
+            stderr_color: "red".to_owned(),
+        }
+    }
+}
+
+impl OutputMarkup for BBCodeMarkup {
+    fn apply(&self, chunk: &OutputChunk) -> String {
+        match chunk.stream {
+            OutputStream::Stdout => chunk.text.clone(),
+            OutputStream::Stderr => {
+                format!("[color={}]{}[/color]", self.stderr_color, chunk.text)
+            }
+        }
+    }
+}
+
+/// When buffered output is handed to the host. Every eval and call flushes at its end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BufferMode {
+    /// Each write is a chunk
+    Unbuffered,
+    /// Complete lines are passed on as soon as they are written
+    #[default]
+    Line,
+    /// Output is held until `flush` or until `FULL_BUFFER_SIZE` is reached
+    Full,
+}
+
+impl BufferMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "unbuffered" => Some(Self::Unbuffered),
+            "line" => Some(Self::Line),
+            "full" => Some(Self::Full),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Unbuffered => "unbuffered",
+            Self::Line => "line",
+            Self::Full => "full",
+        }
+    }
+}
+
+/// Bytes a fully buffered stream holds before it flushes by itself.
+pub const FULL_BUFFER_SIZE: usize = 8192;
+
+#[derive(Default)]
+struct StreamState {
+    /// An `Rc` so `emit` can call it without holding the borrow, the sink may replace itself
+    sink: RefCell<Option<Rc<dyn Fn(OutputChunk)>>>,
+    mode: Cell<BufferMode>,
+    pending: RefCell<String>,
+    /// Source of the first write still pending
+    source: RefCell<Option<String>>,
+}
+
+/// Where `sys.stdout` and `sys.stderr` end up. Shared with the `RustStream`s
+/// behind them, so callbacks can be swapped without replacing the streams.
+#[derive(Default)]
+pub struct OutputSinks {
+    stdout: StreamState,
+    stderr: StreamState,
+    markup: RefCell<Option<Box<dyn OutputMarkup>>>,
+    /// Sees every chunk of both streams before the sink does
+    observer: RefCell<Option<Rc<dyn Fn(&OutputChunk)>>>,
+    /// Innermost last, takes the output in place of the sinks while a call runs
+    captures: RefCell<Vec<CapturedOutput>>,
+}
+
+impl OutputSinks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn state(&self, stream: OutputStream) -> &StreamState {
+        match stream {
+            OutputStream::Stdout => &self.stdout,
+            OutputStream::Stderr => &self.stderr,
+        }
+    }
+
+    /// `None` sends the stream to the Godot or browser console again.
+    pub fn set_sink(&self, stream: OutputStream, sink: Option<Box<dyn Fn(OutputChunk)>>) {
+        self.state(stream).sink.replace(sink.map(Rc::from));
+    }
+
+    pub fn set_markup(&self, markup: Option<Box<dyn OutputMarkup>>) {
+        self.markup.replace(markup);
+    }
+
+    pub fn set_observer(&self, observer: Option<Box<dyn Fn(&OutputChunk)>>) {
+        self.observer.replace(observer.map(Rc::from));
+    }
+
+    /// Flushes what is pending first, so the new mode applies to later writes only.
+    pub fn set_buffer_mode(&self, stream: OutputStream, mode: BufferMode) {
+        self.flush(stream);
+        self.state(stream).mode.set(mode);
+    }
+
+    pub fn buffer_mode(&self, stream: OutputStream) -> BufferMode {
+        self.state(stream).mode.get()
+    }
+
+    /// Buffers `text` and passes on what the stream's `BufferMode` says is ready.
+    pub fn write(&self, stream: OutputStream, text: &str, source: Option<String>) {
+        let state = self.state(stream);
+        let ready = {
+            let mut pending = state.pending.borrow_mut();
+            if pending.is_empty() {
+                state.source.replace(source);
+            }
+            pending.push_str(text);
+
+            match state.mode.get() {
+                BufferMode::Unbuffered => Some(std::mem::take(&mut *pending)),
+                BufferMode::Line => pending.rfind('\n').map(|end| {
+                    let rest = pending.split_off(end + 1);
+                    std::mem::replace(&mut *pending, rest)
+                }),
+                BufferMode::Full if pending.len() >= FULL_BUFFER_SIZE => {
+                    Some(std::mem::take(&mut *pending))
+                }
+                BufferMode::Full => None,
+            }
+        };
+
+        if let Some(ready) = ready {
+            // What is left over came from the same write
+            let source = state.source.borrow().clone();
+            self.emit(stream, ready, source);
+        }
+    }
+
+    pub fn flush(&self, stream: OutputStream) {
+        let state = self.state(stream);
+        let pending = std::mem::take(&mut *state.pending.borrow_mut());
+        if !pending.is_empty() {
+            let source = state.source.take();
+            self.emit(stream, pending, source);
+        }
+    }
+
+    pub fn flush_all(&self) {
+        self.flush(OutputStream::Stdout);
+        self.flush(OutputStream::Stderr);
+    }
+
+    /// Diverts output from the sinks until `end_capture`. What was written before
+    /// is flushed first, so it is not attributed to the capture.
+    pub fn begin_capture(&self) {
+        self.flush_all();
+        self.captures.borrow_mut().push(CapturedOutput::default());
+    }
+
+    /// Flushes and returns what was written since the matching `begin_capture`.
+    pub fn end_capture(&self) -> CapturedOutput {
+        self.flush_all();
+        self.captures.borrow_mut().pop().unwrap_or_default()
+    }
+
+    /// Adds the text to the innermost capture. Otherwise builds the chunk and hands it to
+    /// the observer and the stream's sink, or prints it to the Godot or browser console
+    /// without a sink.
+    fn emit(&self, stream: OutputStream, text: String, source: Option<String>) {
+        if let Some(capture) = self.captures.borrow_mut().last_mut() {
+            match stream {
+                OutputStream::Stdout => capture.stdout.push_str(&text),
+                OutputStream::Stderr => capture.stderr.push_str(&text),
+            }
+            return;
+        }
+
+        let mut chunk = OutputChunk {
+            stream,
+            formatted: text.clone(),
+            text,
+            source,
+            timestamp_msec: now_msec(),
+        };
+        if let Some(markup) = &*self.markup.borrow() {
+            chunk.formatted = markup.apply(&chunk);
+        }
+
+        // Cloned out of the cells, callbacks may set other ones while they run
+        let observer = self.observer.borrow().clone();
+        if let Some(observer) = observer {
+            observer(&chunk);
+        }
+        let sink = self.state(stream).sink.borrow().clone();
+        match sink {
+            Some(sink) => sink(chunk),
+            None => print_to_console(stream, &chunk.formatted),
+        }
+    }
+}
+
+/// Prints to the Godot or browser console, for output no host callback takes.
+pub(crate) fn print_to_console(stream: OutputStream, text: &str) {
+    cfg_if::cfg_if! {
+        if #[cfg(not(target_arch = "wasm32"))] {
+            match stream {
+                OutputStream::Stdout => godot::log::godot_print!("{}", text),
+                OutputStream::Stderr => godot::log::godot_error!("{}", text),
+            }
+        }
+        else if #[cfg(target_arch = "wasm32")] {
+            match stream {
+                OutputStream::Stdout => web_sys::console::log_1(&text.into()),
+                OutputStream::Stderr => web_sys::console::error_1(&text.into()),
+            }
+        }
+    }
+}
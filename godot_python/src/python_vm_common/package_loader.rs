@@ -0,0 +1,96 @@
+/// One Python file of a package, registered under its dotted module name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PackageFile {
+    pub module_name: String,
+    /// Used for `__file__` and tracebacks
+    pub path: String,
+    pub source: String,
+    /// True for `__init__.py` files
+    pub is_package: bool,
+}
+
+impl PackageFile {
+    /// Builds the entry for a file inside the package directory `root_path`.
+    /// `relative_path` uses `/` separators, e.g. `ai/__init__.py`.
+    /// An empty `package_name` maps paths straight to top-level modules.
+    /// Returns `None` for files that are not Python sources.
+    pub fn from_relative_path(
+        package_name: &str,
+        root_path: &str,
+        relative_path: &str,
+        source: String,
+    ) -> Option<Self> {
+        let module_path = relative_path.strip_suffix(".py")?;
+
+        let mut parts: Vec<&str> = module_path.split('/').filter(|p| !p.is_empty()).collect();
+        let is_package = parts.last() == Some(&"__init__");
+        if is_package {
+            parts.pop();
+        }
+
+        let mut module_name = package_name.to_owned();
+        for part in parts {
+            if !module_name.is_empty() {
+                module_name.push('.');
+            }
+            module_name.push_str(part);
+        }
+        if module_name.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            module_name,
+            path: format!("{}/{}", root_path.trim_end_matches('/'), relative_path),
+            source,
+            is_package,
+        })
+    }
+
+    /// An empty package, used for directories without an `__init__.py`.
+    pub fn empty_package(module_name: String, path: String) -> Self {
+        Self {
+            module_name,
+            path,
+            source: String::new(),
+            is_package: true,
+        }
+    }
+}
+
+fn parent_directory(path: &str) -> &str {
+    path.rsplit_once('/').map_or("", |(directory, _)| directory)
+}
+
+/// Adds empty packages for every parent module that has no `__init__.py`,
+/// so each file can be imported by its dotted name.
+pub fn with_missing_packages(mut files: Vec<PackageFile>) -> Vec<PackageFile> {
+    let mut missing = Vec::new();
+    for file in &files {
+        // Parent packages are the directories above the file, so their `__file__`
+        // keeps the root the real files have
+        let mut directory = parent_directory(&file.path);
+        if file.is_package {
+            directory = parent_directory(directory);
+        }
+        let mut parent = file.module_name.as_str();
+        while let Some((prefix, _)) = parent.rsplit_once('.') {
+            parent = prefix;
+            let known = files
+                .iter()
+                .chain(missing.iter())
+                .any(|f: &PackageFile| f.is_package && f.module_name == parent);
+            if !known {
+                let path = match directory {
+                    "" => "__init__.py".to_owned(),
+                    directory => format!("{}/__init__.py", directory),
+                };
+                missing.push(PackageFile::empty_package(parent.to_owned(), path));
+            }
+            directory = parent_directory(directory);
+        }
+    }
+
+    files.extend(missing);
+    files
+}
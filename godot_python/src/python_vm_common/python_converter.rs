@@ -1,15 +1,133 @@
-use rustpython_vm::{builtins::PyBaseException, PyRef, TryFromObject, VirtualMachine};
-
-pub fn unwrap_error(vm: &VirtualMachine, error: PyRef<PyBaseException>) -> String {
-    let mut s = String::new();
-    error.args().into_iter().for_each({
-        |v| {
-            if let Ok(str_obj) = String::try_from_object(vm, v.clone()) {
-                s.push_str(&format!("{:?}\n", str_obj));
-            } else {
-                s.push_str(&format!("{:?}\n", v));
+use rustpython_vm::{
+    builtins::{
+        PyBaseException, PyBoundMethod, PyBuiltinFunction, PyBuiltinMethod, PyByteArray, PyBytes,
+        PyDict, PyFloat, PyFrozenSet, PyFunction, PyInt, PyList, PySet, PyStr, PyTuple,
+    },
+    AsObject, PyObjectRef, PyRef, TryFromObject, VirtualMachine,
+};
+
+use super::python_error::PythonError;
+
+pub fn unwrap_error(vm: &VirtualMachine, error: PyRef<PyBaseException>) -> PythonError {
+    PythonError::from_exception(vm, &error)
+}
+
+/// What a Python value is to the host converters, decided by its exact type so
+/// nothing is coerced on the way (`True` is not `1`, `1.0` is not `1`):
+///
+/// | Python                       | `PyValue`  | Godot                       |
+/// |------------------------------|------------|-----------------------------|
+/// | `None`                       | `None`     | nil                         |
+/// | `bool`                       | `Bool`     | bool                        |
+/// | `int` within 64 bits         | `Int`      | int                         |
+/// | larger `int`                 | `BigInt`   | String of its decimal digits |
+/// | `float`                      | `Float`    | float                       |
+/// | `str`                        | `Str`      | String                      |
+/// | `bytes`, `bytearray`         | `Bytes`    | PackedByteArray             |
+/// | `list`, `tuple`, `set`, `frozenset` | `Sequence` | Array                |
+/// | `dict`                       | `Dict`     | Dictionary                  |
+/// | anything else                | `Other`    | the converter's fallback    |
+///
+/// Subclasses count as their base type, the Godot converter checks the `godot`
+/// types (`StringName` is a `str`) before it gets here.
+///
+/// Host values come back through `into_py_object`, so a `Sequence` is a `list`:
+/// tuples, sets and frozensets come back as lists, and big ints as the String of
+/// their digits from Godot.
+#[derive(Debug)]
+pub enum PyValue {
+    None,
+    Bool(bool),
+    Int(i64),
+    BigInt(String),
+    Float(f64),
+    Str(String),
+    Bytes(Vec<u8>),
+    /// In iteration order, which is arbitrary for sets
+    Sequence(Vec<PyObjectRef>),
+    Dict(Vec<(PyObjectRef, PyObjectRef)>),
+    Other(PyObjectRef),
+}
+
+impl PyValue {
+    /// The Python value the host converters build for `self`, the inverse of
+    /// `classify_py_value` but for `Sequence`, which always becomes a `list`.
+    pub fn into_py_object(self, vm: &VirtualMachine) -> PyObjectRef {
+        match self {
+            PyValue::None => vm.ctx.none(),
+            PyValue::Bool(boolean) => vm.ctx.new_bool(boolean).into(),
+            PyValue::Int(int) => vm.ctx.new_int(int).into(),
+            PyValue::BigInt(digits) => vm
+                .ctx
+                .types
+                .int_type
+                .as_object()
+                .call((digits,), vm)
+                .expect("the digits of an int to parse"),
+            PyValue::Float(float) => vm.ctx.new_float(float).into(),
+            PyValue::Str(string) => vm.ctx.new_str(string).into(),
+            PyValue::Bytes(bytes) => vm.ctx.new_bytes(bytes).into(),
+            PyValue::Sequence(items) => vm.ctx.new_list(items).into(),
+            PyValue::Dict(items) => {
+                let dict = vm.ctx.new_dict();
+                for (key, value) in items {
+                    // Only unhashable keys fail, which no host dictionary key becomes
+                    let _ = dict.set_item(&*key, value, vm);
+                }
+                dict.into()
             }
+            PyValue::Other(value) => value,
         }
-    });
-    s
+    }
+}
+
+pub fn classify_py_value(vm: &VirtualMachine, value: &PyObjectRef) -> PyValue {
+    if vm.is_none(value) {
+        PyValue::None
+    } else if value.class().is(vm.ctx.types.bool_type) {
+        PyValue::Bool(value.is(&vm.ctx.true_value))
+    } else if let Some(int) = value.downcast_ref::<PyInt>() {
+        match i64::try_from_object(vm, value.clone()) {
+            Ok(int) => PyValue::Int(int),
+            Err(_) => PyValue::BigInt(int.as_bigint().to_string()),
+        }
+    } else if let Some(float) = value.downcast_ref::<PyFloat>() {
+        PyValue::Float(float.to_f64())
+    } else if let Some(string) = value.downcast_ref::<PyStr>() {
+        PyValue::Str(string.as_str().to_owned())
+    } else if let Some(bytes) = value.downcast_ref::<PyBytes>() {
+        PyValue::Bytes(bytes.as_bytes().to_vec())
+    } else if let Some(bytearray) = value.downcast_ref::<PyByteArray>() {
+        PyValue::Bytes(bytearray.borrow_buf().to_vec())
+    } else if let Some(list) = value.downcast_ref::<PyList>() {
+        PyValue::Sequence(list.borrow_vec().to_vec())
+    } else if let Some(tuple) = value.downcast_ref::<PyTuple>() {
+        PyValue::Sequence(tuple.as_slice().to_vec())
+    } else if let Some(set) = value.downcast_ref::<PySet>() {
+        PyValue::Sequence(set.elements())
+    } else if let Some(frozenset) = value.downcast_ref::<PyFrozenSet>() {
+        PyValue::Sequence(frozenset.elements())
+    } else if let Some(dict) = value.downcast_ref::<PyDict>() {
+        PyValue::Dict(dict.into_iter().collect())
+    } else {
+        PyValue::Other(value.clone())
+    }
+}
+
+/// The name of a function, lambda, bound method or builtin, which the host converters
+/// turn into something the host can call (a Godot Callable, a JS function).
+/// Classes and other callable objects are None, they stay handles.
+pub fn py_function_name(vm: &VirtualMachine, value: &PyObjectRef) -> Option<String> {
+    let is_function = value.payload_is::<PyFunction>()
+        || value.payload_is::<PyBoundMethod>()
+        || value.payload_is::<PyBuiltinFunction>()
+        || value.payload_is::<PyBuiltinMethod>();
+    if !is_function {
+        return None;
+    }
+    let name = value
+        .get_attr("__name__", vm)
+        .ok()
+        .and_then(|name| String::try_from_object(vm, name).ok());
+    Some(name.unwrap_or_else(|| "python_function".to_owned()))
 }
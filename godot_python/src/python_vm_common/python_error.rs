@@ -0,0 +1,205 @@
+use std::fmt;
+
+use rustpython_vm::{
+    builtins::{PyBaseException, PyTraceback},
+    AsObject, PyObject, PyObjectRef, PyRef, TryFromObject, VirtualMachine,
+};
+
+// Exception chains can be cyclic, so stop following __cause__ / __context__ after this many links
+const MAX_CHAIN_DEPTH: usize = 16;
+
+/// A Python exception copied out of the interpreter, so hosts can display it
+/// without holding on to Python objects.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PythonError {
+    pub type_name: String,
+    pub module: String,
+    pub message: String,
+    /// Frames ordered like a Python traceback, most recent call last
+    pub traceback: Vec<TracebackFrame>,
+    /// The exception set by `raise ... from ...`
+    pub cause: Option<Box<PythonError>>,
+    /// The exception being handled when this one was raised
+    pub context: Option<Box<PythonError>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TracebackFrame {
+    pub file: String,
+    pub line: usize,
+    /// One-indexed, when the interpreter recorded it
+    pub column: Option<usize>,
+    pub function: String,
+}
+
+impl PythonError {
+    /// Creates an error raised by the host rather than by Python code.
+    pub fn host(type_name: &str, message: String) -> Self {
+        Self {
+            type_name: type_name.to_owned(),
+            module: "builtins".to_owned(),
+            message,
+            traceback: Vec::new(),
+            cause: None,
+            context: None,
+        }
+    }
+
+    pub fn from_exception(vm: &VirtualMachine, error: &PyRef<PyBaseException>) -> Self {
+        Self::from_exception_at_depth(vm, error, 0)
+    }
+
+    fn from_exception_at_depth(
+        vm: &VirtualMachine,
+        error: &PyRef<PyBaseException>,
+        depth: usize,
+    ) -> Self {
+        let class = error.class();
+        let type_name = String::from(&*class.name());
+        let module = get_string_attr(vm, class.as_object(), "__module__")
+            .unwrap_or_else(|| "builtins".to_owned());
+        let message = match error.as_object().str(vm) {
+            Ok(s) => s.as_str().to_owned(),
+            Err(_) => String::new(),
+        };
+
+        let mut traceback = Vec::new();
+        let mut tb = error.as_object().get_attr("__traceback__", vm).ok();
+        while let Some(tb_obj) = tb.filter(|t| !vm.is_none(t)) {
+            if let Some(frame) = traceback_frame(vm, &tb_obj) {
+                traceback.push(frame);
+            }
+            tb = tb_obj.get_attr("tb_next", vm).ok();
+        }
+
+        // Syntax errors are raised by the compiler, so their location lives on the exception
+        if error
+            .class()
+            .fast_issubclass(vm.ctx.exceptions.syntax_error)
+        {
+            if let Some(line) = get_usize_attr(vm, error.as_object(), "lineno") {
+                traceback.push(TracebackFrame {
+                    file: get_string_attr(vm, error.as_object(), "filename")
+                        .unwrap_or_else(|| "<unknown>".to_owned()),
+                    line,
+                    column: get_usize_attr(vm, error.as_object(), "offset"),
+                    function: "<module>".to_owned(),
+                });
+            }
+        }
+
+        let (cause, context) = if depth < MAX_CHAIN_DEPTH {
+            let cause = get_exception_attr(vm, error, "__cause__");
+            let context = get_exception_attr(vm, error, "__context__");
+            (
+                cause.map(|e| Box::new(Self::from_exception_at_depth(vm, &e, depth + 1))),
+                context.map(|e| Box::new(Self::from_exception_at_depth(vm, &e, depth + 1))),
+            )
+        } else {
+            (None, None)
+        };
+
+        Self {
+            type_name,
+            module,
+            message,
+            traceback,
+            cause,
+            context,
+        }
+    }
+
+    /// The exception name as Python prints it, e.g. `ValueError` or `mymodule.MyError`.
+    pub fn qualified_type_name(&self) -> String {
+        if self.module == "builtins" || self.module.is_empty() {
+            self.type_name.clone()
+        } else {
+            format!("{}.{}", self.module, self.type_name)
+        }
+    }
+
+    fn fmt_single(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.traceback.is_empty() {
+            writeln!(f, "Traceback (most recent call last):")?;
+            for frame in &self.traceback {
+                writeln!(
+                    f,
+                    "  File \"{}\", line {}, in {}",
+                    frame.file, frame.line, frame.function
+                )?;
+            }
+        }
+        if self.message.is_empty() {
+            write!(f, "{}", self.qualified_type_name())
+        } else {
+            write!(f, "{}: {}", self.qualified_type_name(), self.message)
+        }
+    }
+}
+
+impl fmt::Display for PythonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(cause) = &self.cause {
+            writeln!(f, "{}\n", cause)?;
+            writeln!(
+                f,
+                "The above exception was the direct cause of the following exception:\n"
+            )?;
+        } else if let Some(context) = &self.context {
+            writeln!(f, "{}\n", context)?;
+            writeln!(
+                f,
+                "During handling of the above exception, another exception occurred:\n"
+            )?;
+        }
+        self.fmt_single(f)
+    }
+}
+
+fn traceback_frame(vm: &VirtualMachine, tb: &PyObjectRef) -> Option<TracebackFrame> {
+    let line = get_usize_attr(vm, tb, "tb_lineno")?;
+    let code = tb
+        .get_attr("tb_frame", vm)
+        .and_then(|frame| frame.get_attr("f_code", vm))
+        .ok()?;
+
+    // The traceback stores the instruction index after the failing one
+    let column = tb.downcast_ref::<PyTraceback>().and_then(|tb| {
+        let index = (tb.lasti as usize).saturating_sub(1);
+        tb.frame
+            .code
+            .code
+            .locations
+            .get(index)
+            .map(|location| location.column.get())
+    });
+
+    Some(TracebackFrame {
+        file: get_string_attr(vm, &code, "co_filename").unwrap_or_default(),
+        line,
+        column,
+        function: get_string_attr(vm, &code, "co_name").unwrap_or_default(),
+    })
+}
+
+fn get_exception_attr(
+    vm: &VirtualMachine,
+    error: &PyRef<PyBaseException>,
+    name: &'static str,
+) -> Option<PyRef<PyBaseException>> {
+    let value = error.as_object().get_attr(name, vm).ok()?;
+    if vm.is_none(&value) {
+        return None;
+    }
+    value.downcast::<PyBaseException>().ok()
+}
+
+fn get_string_attr(vm: &VirtualMachine, obj: &PyObject, name: &'static str) -> Option<String> {
+    let value = obj.get_attr(name, vm).ok()?;
+    String::try_from_object(vm, value).ok()
+}
+
+fn get_usize_attr(vm: &VirtualMachine, obj: &PyObject, name: &'static str) -> Option<usize> {
+    let value = obj.get_attr(name, vm).ok()?;
+    usize::try_from_object(vm, value).ok()
+}
@@ -9,105 +9,92 @@ pub mod rust_stdout {
 
     use std::fmt;
     use std::fmt::Formatter;
+    use std::rc::Rc;
 
     use super::*;
-    #[cfg(not(target_arch = "wasm32"))]
-    use godot::log::{godot_error, godot_print};
-    use rustpython_vm::{pyclass, PyPayload};
-    #[cfg(target_arch = "wasm32")]
-    use web_sys::{self, console};
+    use crate::python_vm_common::output::{
+        print_to_console, BufferMode, OutputSinks, OutputStream,
+    };
+    use rustpython_vm::{builtins::PyStrRef, pyclass, PyPayload, TryFromObject};
 
+    /// `sys.stdout` or `sys.stderr`, buffering into the VM's `OutputSinks`.
     #[pyattr]
-    #[pyclass(module = "rust_stdout", name = "RustStdout")]
-    #[derive(Debug, PyPayload)]
-    pub struct RustStdout {
-        fns: RustStdoutFns,
+    #[pyclass(module = "rust_stdout", name = "RustStream")]
+    #[derive(PyPayload)]
+    pub struct RustStream {
+        stream: OutputStream,
+        sinks: Rc<OutputSinks>,
     }
 
     #[pyclass]
-    impl RustStdout {
-        pub fn new() -> Self {
-            Self {
-                fns: RustStdoutFns::new(),
-            }
+    impl RustStream {
+        pub fn new(stream: OutputStream, sinks: Rc<OutputSinks>) -> Self {
+            Self { stream, sinks }
         }
 
-        pub fn set_stdout_fn(&mut self, f: Box<dyn Fn(String)>) {
-            self.fns.set_stdout_fn(f);
+        /// `__name__` of the module running the code that writes, print() has no frame.
+        fn caller_module(vm: &VirtualMachine) -> Option<String> {
+            let frame = vm.current_frame()?;
+            let name = frame.globals.get_item_opt("__name__", vm).ok()??;
+            String::try_from_object(vm, name).ok()
         }
 
-        pub fn set_stderr_fn(&mut self, f: Box<dyn Fn(String)>) {
-            self.fns.set_stderr_fn(f);
+        #[pymethod]
+        fn write(&self, text: PyStrRef, vm: &VirtualMachine) -> usize {
+            self.sinks
+                .write(self.stream, text.as_str(), Self::caller_module(vm));
+            text.char_len()
         }
 
         #[pymethod]
-        fn rs_print(&self, args: String) {
-            if let Some(f) = &self.fns.stdout_fn {
-                f(args);
-            } else {
-                println!("{}", args);
-            }
+        fn flush(&self) {
+            self.sinks.flush(self.stream);
         }
 
         #[pymethod]
-        fn rs_print_err(&self, args: String) {
-            if let Some(f) = &self.fns.stderr_fn {
-                f(args);
-            } else {
-                println!("{}", args);
-            }
+        fn writable(&self) -> bool {
+            true
         }
-    }
 
-    #[pyfunction]
-    fn rs_print(args: String, _vm: &VirtualMachine) {
-        cfg_if::cfg_if! {
-            if #[cfg(not(target_arch = "wasm32"))] {
-                godot_print!("{}", args);
-            }
-            else if #[cfg(target_arch = "wasm32")] {
-                console::log_1(&args.as_str().into());
-            }
+        #[pymethod]
+        fn isatty(&self) -> bool {
+            false
         }
-    }
 
-    #[pyfunction]
-    fn rs_print_err(args: String, _vm: &VirtualMachine) {
-        cfg_if::cfg_if! {
-            if #[cfg(not(target_arch = "wasm32"))] {
-                godot_error!("{}", args);
-            }
-            else if #[cfg(target_arch = "wasm32")] {
-                console::error_1(&args.as_str().into());
-            }
+        #[pygetset]
+        fn encoding(&self) -> String {
+            "utf-8".to_owned()
         }
-    }
 
-    pub struct RustStdoutFns {
-        pub stdout_fn: Option<Box<dyn Fn(String)>>,
-        pub stderr_fn: Option<Box<dyn Fn(String)>>,
-    }
-
-    impl RustStdoutFns {
-        pub fn new() -> Self {
-            Self {
-                stdout_fn: None,
-                stderr_fn: None,
-            }
+        #[pygetset]
+        fn name(&self) -> String {
+            format!("<{}>", self.stream.as_str())
         }
 
-        pub fn set_stdout_fn(&mut self, f: Box<dyn Fn(String)>) {
-            self.stdout_fn = Some(f);
+        #[pygetset]
+        fn line_buffering(&self) -> bool {
+            self.sinks.buffer_mode(self.stream) == BufferMode::Line
         }
 
-        pub fn set_stderr_fn(&mut self, f: Box<dyn Fn(String)>) {
-            self.stderr_fn = Some(f);
+        #[pygetset]
+        fn closed(&self) -> bool {
+            false
         }
     }
 
-    impl fmt::Debug for RustStdoutFns {
+    impl fmt::Debug for RustStream {
         fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-            write!(f, "RustStdoutFns")
+            write!(f, "RustStream({})", self.stream.as_str())
         }
     }
+
+    #[pyfunction]
+    fn rs_print(args: String, _vm: &VirtualMachine) {
+        print_to_console(OutputStream::Stdout, &args);
+    }
+
+    #[pyfunction]
+    fn rs_print_err(args: String, _vm: &VirtualMachine) {
+        print_to_console(OutputStream::Stderr, &args);
+    }
 }
@@ -0,0 +1,77 @@
+/// Restrictions applied to user code, chosen when the VM is created.
+///
+/// User code is everything compiled through `eval`, sessions and `load_module`.
+/// Standard library modules imported by allowed modules are not restricted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SandboxPolicy {
+    /// Top-level modules user code may import, stdlib or native. `None` allows all of them.
+    /// Modules loaded through `load_module` and the host's `godot` module can always be imported,
+    /// but `godot` only exposes the engine (`godot.Object`, `godot.OS`...) if `godot_engine` is allowed.
+    /// The same check applies to modules reached through other modules, e.g. `typing.sys`.
+    /// The VM's own helper modules, like `sandbox`, are never importable while a policy is active.
+    pub allowed_modules: Option<Vec<String>>,
+    /// Builtins that raise `NameError` when user code uses them, e.g. `open` or `exec`.
+    /// Removing `__import__` disables imports altogether.
+    pub removed_builtins: Vec<String>,
+    /// When false, attributes like `__class__` or `__globals__` are rejected at compile time,
+    /// and `globals`, `locals` and `vars` are removed.
+    pub allow_dunder_access: bool,
+}
+
+pub const RESTRICTED_ALLOWED_MODULES: &[&str] = &[
+    "abc",
+    "asyncio",
+    "bisect",
+    "collections",
+    "copy",
+    "dataclasses",
+    "enum",
+    "functools",
+    "heapq",
+    "itertools",
+    "json",
+    "math",
+    "random",
+    "re",
+    "statistics",
+    "string",
+    "typing",
+];
+
+pub const RESTRICTED_REMOVED_BUILTINS: &[&str] = &["open", "exec", "eval", "compile", "breakpoint"];
+
+impl SandboxPolicy {
+    /// No restrictions, the behaviour of `CommonPythonVM::init`.
+    pub fn unrestricted() -> Self {
+        Self {
+            allowed_modules: None,
+            removed_builtins: Vec::new(),
+            allow_dunder_access: true,
+        }
+    }
+
+    /// A policy for running player code: a small set of pure stdlib modules, no file
+    /// access or dynamic code, no dunder attributes and no private attributes of modules.
+    /// It only restricts what Python code can reach, it doesn't isolate the process.
+    pub fn restricted() -> Self {
+        Self {
+            allowed_modules: Some(
+                RESTRICTED_ALLOWED_MODULES
+                    .iter()
+                    .map(|m| m.to_string())
+                    .collect(),
+            ),
+            removed_builtins: RESTRICTED_REMOVED_BUILTINS
+                .iter()
+                .map(|b| b.to_string())
+                .collect(),
+            allow_dunder_access: false,
+        }
+    }
+}
+
+impl Default for SandboxPolicy {
+    fn default() -> Self {
+        Self::unrestricted()
+    }
+}
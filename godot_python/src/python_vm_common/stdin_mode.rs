@@ -0,0 +1,21 @@
+/// How `input()` waits when no line is ready, see `CommonPythonVM::setup_stdin`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StdinMode {
+    /// `input()` returns the next pushed line, or raises `EOFError` if there is none
+    #[default]
+    Blocking,
+    /// `input()` returns an awaitable; a generator or coroutine that yields or
+    /// awaits it is suspended until the next line is pushed. Outside of a coroutine
+    /// the host runs, `input()` raises `RuntimeError`
+    Suspending,
+}
+
+impl StdinMode {
+    pub fn from_suspending(suspending: bool) -> Self {
+        if suspending {
+            Self::Suspending
+        } else {
+            Self::Blocking
+        }
+    }
+}
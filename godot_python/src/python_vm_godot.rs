@@ -1,62 +1,507 @@
 mod godot_converter;
+mod godot_engine;
+mod godot_output;
+mod package_files;
+mod python_coroutine;
+mod python_object;
+mod python_result;
+mod python_task;
+
+use std::cell::RefCell;
 
 use godot::prelude::*;
 use godot_converter::{
-    convert_py_to_variant_common, convert_variant_arr_to_args, convert_variant_dict_to_kwargs,
+    convert_dict_to_sandbox_policy, convert_members_to_array, convert_python_error_to_dict,
+    convert_reload_diff_to_dict, convert_signature_to_dict, convert_variant_arr_to_args,
+    convert_variant_dict_to_kwargs,
 };
-use rustpython_vm::function::FuncArgs;
+use godot_engine::{create_godot_engine, VariantBridge};
+use godot_output::{convert_variant_to_markup, convert_variant_to_output_sink};
+use package_files::{collect_package_files, GodotResSource};
+use python_coroutine::PythonCoroutine;
+use python_result::PythonResult;
+use python_task::PythonTask;
+use rustpython_vm::{function::FuncArgs, PyObjectRef, VirtualMachine};
 
-use crate::python_vm_common::CommonPythonVM;
+use crate::python_vm_common::{
+    execution_budget::ExecutionBudget,
+    module_source::{InMemorySource, ZipSource},
+    output::{BBCodeMarkup, BufferMode, CapturedOutput, OutputStream},
+    package_loader::PackageFile,
+    python_error::PythonError,
+    sandbox_policy::SandboxPolicy,
+    stdin_mode::StdinMode,
+    CommonPythonVM,
+};
 
 #[derive(GodotClass)]
 #[class(base=Node)]
 pub struct GodotPythonVM {
     node: Base<Node>,
     common_vm: CommonPythonVM,
+    /// Tasks of `async def` calls that did not complete yet, checked by `poll`
+    tasks: RefCell<Vec<Gd<PythonTask>>>,
 }
 
 #[godot_api]
 impl INode for GodotPythonVM {
     fn init(node: Base<Node>) -> Self {
-        let common_vm = CommonPythonVM::init();
+        let common_vm = Self::init_common_vm(SandboxPolicy::unrestricted());
+
+        Self {
+            node,
+            common_vm,
+            tasks: RefCell::new(Vec::new()),
+        }
+    }
+
+    fn ready(&mut self) {
+        self.connect_output_signal();
+        self.add_engine_module();
+    }
+}
+
+impl GodotPythonVM {
+    /// Stderr is shown red in a `RichTextLabel` until `set_output_markup` says otherwise.
+    fn init_common_vm(policy: SandboxPolicy) -> CommonPythonVM {
+        let mut common_vm = CommonPythonVM::init_with_policy(policy);
+        common_vm.set_output_markup(Some(Box::new(BBCodeMarkup::default())));
+        common_vm
+    }
 
-        Self { node, common_vm }
+    pub(crate) fn common_vm(&self) -> &CommonPythonVM {
+        &self.common_vm
+    }
+
+    fn set_output_target(&mut self, stream: OutputStream, target: Variant) {
+        match convert_variant_to_output_sink(&target) {
+            Ok(sink) => self.common_vm.set_output_sink(stream, sink),
+            Err(error) => godot_error!("{}", error),
+        }
+    }
+
+    /// Lets Python code reach the engine through `godot`, see `godot_engine`.
+    fn add_engine_module(&self) {
+        let bridge = self.variant_bridge();
+        self.common_vm
+            .add_host_module(|vm| create_godot_engine(vm, bridge).into());
+    }
+
+    /// Emits `output` for every chunk, next to whatever `setup_stdout` and `setup_stderr` installed.
+    fn connect_output_signal(&mut self) {
+        let node = self.to_gd().upcast::<Node>();
+        self.common_vm.set_output_observer(move |chunk| {
+            let mut node = node.clone();
+            if node.is_instance_valid() {
+                node.emit_signal(
+                    "output",
+                    &[
+                        Variant::from(chunk.formatted.as_str()),
+                        Variant::from(chunk.stream.as_str()),
+                    ],
+                );
+            }
+        });
+    }
+
+    /// Wraps `r` in a `PythonResult`, emitting `python_error` if Python raised.
+    pub(crate) fn to_result(&self, r: Result<Variant, PythonError>) -> Gd<PythonResult> {
+        self.to_result_with_output(r, &CapturedOutput::default())
+    }
+
+    fn to_result_with_output(
+        &self,
+        r: Result<Variant, PythonError>,
+        output: &CapturedOutput,
+    ) -> Gd<PythonResult> {
+        if let Err(error) = &r {
+            emit_python_error(&mut self.to_gd().upcast(), error);
+        }
+        PythonResult::with_output(r, output)
+    }
+
+    /// Like `to_result`, and emits `module_loaded` or `module_load_failed` for `name`.
+    fn to_module_result(&self, name: String, r: Result<Variant, PythonError>) -> Gd<PythonResult> {
+        let mut node = self.to_gd().upcast::<Node>();
+        match &r {
+            Ok(_) => {
+                node.emit_signal("module_loaded", &[Variant::from(name)]);
+            }
+            Err(error) => {
+                node.emit_signal(
+                    "module_load_failed",
+                    &[
+                        Variant::from(name),
+                        Variant::from(convert_python_error_to_dict(error)),
+                    ],
+                );
+                emit_python_error(&mut node, error);
+            }
+        }
+        PythonResult::new(r)
+    }
+
+    fn variant_bridge(&self) -> VariantBridge {
+        VariantBridge::new(self.to_gd(), self.common_vm.handles().clone())
+    }
+
+    /// Converts a Python value, wrapping values without a Variant equivalent in a `PythonObject`.
+    /// Functions and bound methods become a Callable calling such a `PythonObject`.
+    pub(crate) fn to_variant(&self, value: PyObjectRef) -> Variant {
+        let bridge = self.variant_bridge();
+        self.common_vm
+            .interpreter
+            .enter(|vm| bridge.to_variant(vm, value))
+    }
+
+    /// Converts a Variant, unwrapping `PythonObject`s into the object they hold
+    /// and wrapping other objects in a `godot.Object`.
+    pub(crate) fn to_py_object(&self, value: Variant) -> PyObjectRef {
+        let bridge = self.variant_bridge();
+        self.common_vm
+            .interpreter
+            .enter(|vm| bridge.to_py_object(vm, value))
+    }
+
+    /// Like `to_variant`, but wraps the task of an `async def` call in a `PythonTask` `poll` completes.
+    fn to_variant_or_task(&self, value: PyObjectRef) -> Variant {
+        if !self.common_vm.is_task(&value) {
+            return self.to_variant(value);
+        }
+        let handle = self.common_vm.register_handle(value);
+        let task = PythonTask::new(self.to_gd(), self.common_vm.handles().clone(), handle);
+        self.tasks.borrow_mut().push(task.clone());
+        Variant::from(task)
+    }
+
+    /// Fails with a TypeError when a kwargs key is not a String or StringName.
+    pub(crate) fn to_func_args(
+        &self,
+        args: VariantArray,
+        kwargs: Dictionary,
+    ) -> Result<FuncArgs, PythonError> {
+        let bridge = self.variant_bridge();
+        let resolve_object = |vm: &VirtualMachine, object: &Variant| bridge.resolve(vm, object);
+        Ok(FuncArgs::new(
+            convert_variant_arr_to_args(&self.common_vm, args, &resolve_object),
+            convert_variant_dict_to_kwargs(&self.common_vm, kwargs, &resolve_object)?,
+        ))
     }
 }
 
 #[godot_api]
 impl GodotPythonVM {
+    /// Emitted with the error dict whenever a call through the VM, one of its
+    /// `PythonObject`s or `PythonCoroutine`s, or a `PythonTask` raised.
+    #[signal]
+    fn python_error(error: Dictionary);
+
+    /// Emitted by `load_module`, `reload_module` and `load_package` on success.
+    #[signal]
+    fn module_loaded(name: String);
+
+    /// Emitted by `load_module`, `reload_module` and `load_package` when the code
+    /// raised, before `python_error`.
+    #[signal]
+    fn module_load_failed(name: String, error: Dictionary);
+
+    /// Emitted for every chunk written to `sys.stdout` or `sys.stderr` once the node
+    /// is in the tree, with the output markup applied. `stream` is "stdout" or "stderr".
+    /// Output of the `_captured` calls is not emitted.
+    #[signal]
+    fn output(text: String, stream: String);
+
+    /// Recreates the interpreter with a sandbox policy, see `convert_dict_to_sandbox_policy`.
+    /// Call it before `setup_stdout` and `load_module`, nothing from the old interpreter is kept.
+    /// Running `PythonTask`s complete with a `CancelledError` on the next `poll`.
+    #[func]
+    fn configure_sandbox(&mut self, policy: Dictionary) {
+        self.common_vm = Self::init_common_vm(convert_dict_to_sandbox_policy(&policy));
+        for mut task in self.tasks.borrow().iter().cloned() {
+            let cancelled =
+                PythonError::host("CancelledError", "The PythonVM was reset".to_owned());
+            task.bind_mut()
+                .set_result(PythonResult::new(Err(cancelled)));
+        }
+        if self.base().is_inside_tree() {
+            self.connect_output_signal();
+            self.add_engine_module();
+        }
+    }
+
+    /// Sends `sys.stdout` to `target`, replacing what was set before:
+    /// - a Callable, e.g. a lambda, gets the formatted text of each chunk
+    /// - a Node gets `{stream, text, formatted, source, timestamp_msec}` for each chunk
+    ///   if it has an `append_output_chunk(chunk)` method, otherwise `append_output(text)`
+    ///   gets the formatted text
+    /// - nil disconnects it, the output goes to the Godot console again
+    #[func]
+    fn setup_stdout(&mut self, target: Variant) {
+        self.set_output_target(OutputStream::Stdout, target);
+    }
+
+    /// Sends `sys.stderr` to `target`, like `setup_stdout`.
+    #[func]
+    fn setup_stderr(&mut self, target: Variant) {
+        self.set_output_target(OutputStream::Stderr, target);
+    }
+
+    /// How output is formatted: `"bbcode"` (the default) colors stderr red, nil leaves
+    /// text as is, and a Callable gets each chunk dict and returns the text to show.
+    #[func]
+    fn set_output_markup(&mut self, markup: Variant) {
+        match convert_variant_to_markup(&markup) {
+            Ok(markup) => self.common_vm.set_output_markup(markup),
+            Err(error) => godot_error!("{}", error),
+        }
+    }
+
+    /// When `stream` (`"stdout"` or `"stderr"`) hands output over: `"line"` (the
+    /// default), `"unbuffered"` or `"full"`. Every eval and call flushes when it ends.
+    #[func]
+    fn set_output_buffering(&mut self, stream: String, mode: String) {
+        match (
+            OutputStream::from_name(&stream),
+            BufferMode::from_name(&mode),
+        ) {
+            (Some(stream), Some(mode)) => self.common_vm.set_output_buffering(stream, mode),
+            _ => godot_error!("Unknown output buffering: {} {}", stream, mode),
+        }
+    }
+
+    /// Routes `input()` and `sys.stdin` to the host, see `CommonPythonVM::setup_stdin`.
+    /// `request_line(prompt)` is called when Python needs a line that was not pushed
+    /// yet, and may return one. When it returns nil, `input()` raises `EOFError`,
+    /// or with `suspending` returns an awaitable that waits for `push_stdin`.
+    /// A nil `request_line` disconnects the previous one, then only pushed lines are read.
     #[func]
-    fn setup_stdout(&mut self, godot_out: Gd<Node>) {
-        let callable = godot_out.callable("append_output");
+    fn setup_stdin(&mut self, request_line: Variant, suspending: bool) {
+        let mode = StdinMode::from_suspending(suspending);
+        if request_line.is_nil() {
+            self.common_vm.clear_stdin(mode);
+            return;
+        }
+        let request_line = match request_line.try_to::<Callable>() {
+            Ok(callable) => callable,
+            Err(_) => {
+                godot_error!("Cannot read stdin from {}", request_line);
+                return;
+            }
+        };
 
-        let clo = move |s: String| {
-            let mut arr = VariantArray::new();
-            arr.push(Variant::from(s));
-            callable.callv(arr);
+        let clo = move |prompt: String| {
+            request_line
+                .call(&[Variant::from(prompt)])
+                .try_to::<String>()
+                .ok()
         };
+        self.common_vm.setup_stdin(clo, mode);
+    }
+
+    /// Queues a line for `input()`, e.g. when the player submits the in-game terminal.
+    #[func]
+    fn push_stdin(&self, line: String) {
+        self.common_vm.push_stdin(line);
+    }
+
+    /// Limits every later call. Values of zero or less disable that limit.
+    #[func]
+    fn set_execution_budget(&mut self, max_instructions: i64, timeout_msec: i64) {
+        self.common_vm
+            .set_execution_budget(ExecutionBudget::from_limits(max_instructions, timeout_msec));
+    }
 
-        self.common_vm.setup_stdout(clo);
+    #[func]
+    fn eval_with_budget(
+        &self,
+        code: String,
+        max_instructions: i64,
+        timeout_msec: i64,
+    ) -> Gd<PythonResult> {
+        let budget = ExecutionBudget::from_limits(max_instructions, timeout_msec);
+        self.common_vm
+            .with_execution_budget(budget, |_| self.eval(code))
     }
 
     #[func]
-    fn eval(&self, code: String) -> Variant {
+    fn call_python_function_with_budget(
+        &self,
+        module_name: String,
+        function_name: String,
+        args: VariantArray,
+        kwargs: Dictionary,
+        max_instructions: i64,
+        timeout_msec: i64,
+    ) -> Gd<PythonResult> {
+        let budget = ExecutionBudget::from_limits(max_instructions, timeout_msec);
+        self.common_vm.with_execution_budget(budget, |_| {
+            self.call_python_function(module_name, function_name, args, kwargs)
+        })
+    }
+
+    #[func]
+    fn eval(&self, code: String) -> Gd<PythonResult> {
         let r = self.common_vm.eval(code);
+        self.to_result(r.map(|value| self.to_variant(value)))
+    }
 
-        match r {
-            Ok(value) => convert_py_to_variant_common(&self.common_vm, value),
-            Err(error) => Variant::from(format!("Error: {:?}", error)),
-        }
+    /// Like `eval`, but fills the result's `stdout` and `stderr` with what the code
+    /// printed. That output is not passed to `setup_stdout` or `setup_stderr`.
+    #[func]
+    fn eval_captured(&self, code: String) -> Gd<PythonResult> {
+        let (r, output) = self
+            .common_vm
+            .with_captured_output(|common_vm| common_vm.eval(code));
+        self.to_result_with_output(r.map(|value| self.to_variant(value)), &output)
+    }
+
+    #[func]
+    fn create_session(&mut self, session_name: String) {
+        self.common_vm.create_session(session_name);
+    }
+
+    #[func]
+    fn eval_in_session(&self, session_name: String, code: String) -> Gd<PythonResult> {
+        let r = self.common_vm.eval_in_session(session_name, code);
+        self.to_result(r.map(|value| self.to_variant(value)))
+    }
+
+    #[func]
+    fn get_session_globals(&self, session_name: String) -> Gd<PythonResult> {
+        let r = self.common_vm.get_session_globals(session_name);
+        self.to_result(r.map(|value| self.to_variant(value)))
+    }
+
+    #[func]
+    fn clear_session(&mut self, session_name: String) -> bool {
+        self.common_vm.clear_session(session_name)
+    }
+
+    #[func]
+    fn drop_session(&mut self, session_name: String) -> bool {
+        self.common_vm.drop_session(session_name)
     }
 
     #[func]
-    fn load_module(&mut self, module_name: String, module_code: String) -> Variant {
+    fn list_sessions(&self) -> PackedStringArray {
+        self.common_vm
+            .list_sessions()
+            .iter()
+            .map(|name| GString::from(name.as_str()))
+            .collect()
+    }
+
+    /// The result's value is nil. Emits `module_loaded` or `module_load_failed`.
+    /// Takes `&self` like `call_python_function`, so the module's code and the signal
+    /// handlers may run Python through the VM again, e.g. to call the module's functions.
+    #[func]
+    fn load_module(&self, module_name: String, module_code: String) -> Gd<PythonResult> {
         let r = self.common_vm.load_module(module_name.clone(), module_code);
+        self.to_module_result(module_name, r.map(|_| Variant::nil()))
+    }
+
+    /// Re-executes a loaded module, see `CommonPythonVM::reload_module`.
+    /// The result's value is `{added, removed, changed, persisted, updated_modules}`.
+    /// Emits `module_loaded` or `module_load_failed` like `load_module`.
+    #[func]
+    fn reload_module(&self, module_name: String, module_code: String) -> Gd<PythonResult> {
+        let r = self
+            .common_vm
+            .reload_module(module_name.clone(), module_code);
+        self.to_module_result(
+            module_name,
+            r.map(|diff| Variant::from(convert_reload_diff_to_dict(&diff))),
+        )
+    }
 
-        match r {
-            Ok(_) => Variant::from("Success"),
-            Err(error) => Variant::from(format!("Error: {:?}", error)),
+    /// Loads every `.py` file below a `res://` or `user://` directory as a package
+    /// named after the directory. `__init__.py` files become packages.
+    /// Emits `module_loaded` or `module_load_failed` with the package name, or with
+    /// `path` when the directory could not be read.
+    #[func]
+    fn load_package(&self, path: String) -> Gd<PythonResult> {
+        let (name, r) = match collect_package_files(&path) {
+            Ok((package_name, files)) => (
+                package_name.clone(),
+                self.common_vm.load_package(package_name, files),
+            ),
+            Err(error) => (path, Err(error)),
+        };
+        self.to_module_result(name, r.map(|_| Variant::nil()))
+    }
+
+    /// Lets `import` find modules below a `res://` or `user://` directory on demand.
+    /// Sources added later are searched first, adding one under an existing name replaces it.
+    #[func]
+    fn add_module_source_res(&mut self, name: String, root_path: String) {
+        self.common_vm
+            .add_module_source(name, Box::new(GodotResSource::new(&root_path)));
+    }
+
+    /// Lets `import` find the `.py` files of a zip archive, e.g. one read with `FileAccess`.
+    #[func]
+    fn add_module_source_zip(
+        &mut self,
+        name: String,
+        archive: PackedByteArray,
+    ) -> Gd<PythonResult> {
+        let r = ZipSource::new(archive.to_vec(), &name).map(|source| {
+            self.common_vm.add_module_source(name, Box::new(source));
+            Variant::nil()
+        });
+        if let Err(error) = &r {
+            emit_python_error(&mut self.base_mut(), error);
         }
+        PythonResult::new(r)
+    }
+
+    /// Lets `import` find modules kept in memory, keyed by relative path,
+    /// e.g. `{"game/__init__.py": "...", "game/util.py": "..."}`.
+    #[func]
+    fn add_module_source_dict(&mut self, name: String, files: Dictionary) {
+        let files = files
+            .iter_shared()
+            .filter_map(|(path, source)| {
+                PackageFile::from_relative_path("", &name, &path.to_string(), source.to_string())
+            })
+            .collect();
+        self.common_vm
+            .add_module_source(name, Box::new(InMemorySource::from_files(files)));
+    }
+
+    #[func]
+    fn remove_module_source(&mut self, name: String) -> bool {
+        self.common_vm.remove_module_source(&name)
+    }
+
+    /// Names of the modules loaded with `load_module` or `load_package`.
+    #[func]
+    fn list_modules(&self) -> PackedStringArray {
+        self.common_vm
+            .list_modules()
+            .iter()
+            .map(|name| GString::from(name.as_str()))
+            .collect()
+    }
+
+    /// The result's value is `[{name, kind, type}]` for the public globals of a module.
+    /// `kind` is one of "function", "class", "module" or "value".
+    #[func]
+    fn list_members(&self, module_name: String) -> Gd<PythonResult> {
+        let r = self.common_vm.list_members(module_name);
+        self.to_result(r.map(|members| Variant::from(convert_members_to_array(&members))))
+    }
+
+    /// The result's value is `{name, parameters, return_annotation, doc}`.
+    /// Each parameter is `{name, kind, has_default, default, annotation}`.
+    #[func]
+    fn get_signature(&self, module_name: String, function_name: String) -> Gd<PythonResult> {
+        let r = self.common_vm.get_signature(module_name, function_name);
+        self.to_result(
+            r.map(|signature| {
+                Variant::from(convert_signature_to_dict(&self.common_vm, &signature))
+            }),
+        )
     }
 
     #[func]
@@ -66,18 +511,104 @@ impl GodotPythonVM {
         function_name: String,
         args: VariantArray,
         kwargs: Dictionary,
-    ) -> Variant {
-        let f_args = FuncArgs::new(
-            convert_variant_arr_to_args(&self.common_vm, args),
-            convert_variant_dict_to_kwargs(&self.common_vm, kwargs),
-        );
+    ) -> Gd<PythonResult> {
+        let f_args = match self.to_func_args(args, kwargs) {
+            Ok(f_args) => f_args,
+            Err(error) => return self.to_result(Err(error)),
+        };
         let r = self
             .common_vm
             .call_python_function(module_name, function_name, f_args);
+        self.to_result(r.map(|value| self.to_variant_or_task(value)))
+    }
+
+    /// Like `call_python_function`, but fills the result's `stdout` and `stderr` with
+    /// what the call printed. For `async def` functions the value is the `PythonTask`;
+    /// their body only runs when `poll` steps the task, so none of it is captured.
+    #[func]
+    fn call_python_function_captured(
+        &self,
+        module_name: String,
+        function_name: String,
+        args: VariantArray,
+        kwargs: Dictionary,
+    ) -> Gd<PythonResult> {
+        let f_args = match self.to_func_args(args, kwargs) {
+            Ok(f_args) => f_args,
+            Err(error) => return self.to_result(Err(error)),
+        };
+        let (r, output) = self.common_vm.with_captured_output(|common_vm| {
+            common_vm.call_python_function(module_name, function_name, f_args)
+        });
+        self.to_result_with_output(r.map(|value| self.to_variant_or_task(value)), &output)
+    }
+
+    /// Advances Python's event loop by `delta` seconds of game time, call it from `_process`.
+    /// `asyncio.sleep` waits for game time, and `PythonTask`s that finish emit `completed`.
+    /// The result's value is nil.
+    #[func]
+    fn poll(&self, delta: f64) -> Gd<PythonResult> {
+        if let Err(error) = self.common_vm.poll(delta) {
+            return self.to_result(Err(error));
+        }
 
-        match r {
-            Ok(value) => convert_py_to_variant_common(&self.common_vm, value),
-            Err(error) => Variant::from(format!("Error: {:?}", error)),
+        // Taken out so `completed` handlers can start new tasks
+        let tasks = self.tasks.take();
+        let mut pending = Vec::new();
+        for mut task in tasks {
+            let (handle, cancelled) = {
+                let task = task.bind();
+                (task.handle(), task.result())
+            };
+            // Set by `configure_sandbox`, the handle belongs to the old VM
+            let result = match cancelled {
+                Some(result) => result,
+                None => match self.common_vm.task_result(handle) {
+                    None => {
+                        pending.push(task);
+                        continue;
+                    }
+                    Some(r) => self.to_result(r.map(|value| self.to_variant(value))),
+                },
+            };
+            task.bind_mut().set_result(result.clone());
+            task.upcast::<RefCounted>()
+                .emit_signal("completed", &[Variant::from(result)]);
         }
+        self.tasks.borrow_mut().splice(0..0, pending);
+        self.to_result(Ok(Variant::nil()))
+    }
+
+    /// Calls a generator function or `async def`, the result's value is the
+    /// `PythonCoroutine` to resume it with.
+    #[func]
+    fn start_coroutine(
+        &self,
+        module_name: String,
+        function_name: String,
+        args: VariantArray,
+        kwargs: Dictionary,
+    ) -> Gd<PythonResult> {
+        let f_args = match self.to_func_args(args, kwargs) {
+            Ok(f_args) => f_args,
+            Err(error) => return self.to_result(Err(error)),
+        };
+        let r = self
+            .common_vm
+            .start_coroutine(module_name, function_name, f_args);
+        self.to_result(r.map(|handle| {
+            Variant::from(PythonCoroutine::new(
+                self.to_gd(),
+                self.common_vm.handles().clone(),
+                handle,
+            ))
+        }))
     }
 }
+
+fn emit_python_error(node: &mut Gd<Node>, error: &PythonError) {
+    node.emit_signal(
+        "python_error",
+        &[Variant::from(convert_python_error_to_dict(error))],
+    );
+}
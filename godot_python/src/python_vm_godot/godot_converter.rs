@@ -1,12 +1,26 @@
 use godot::prelude::*;
 use indexmap::IndexMap;
 use rustpython_vm::{
-    builtins::{PyDict, PyList},
-    function::KwArgs,
-    PyObjectRef, TryFromObject, VirtualMachine,
+    builtins::PyList,
+    function::{FuncArgs, KwArgs},
+    PyObjectRef, PyPayload, PyResult, TryFromObject, VirtualMachine,
 };
 
-use crate::python_vm_common::CommonPythonVM;
+use super::godot_engine::{PyGodotObject, PyGodotSignal};
+use crate::python_vm_common::{
+    godot_math::{
+        PyAabb, PyBasis, PyColor, PyPlane, PyProjection, PyQuaternion, PyRect2, PyRect2i,
+        PyTransform2D, PyTransform3D, PyVector2, PyVector2i, PyVector3, PyVector3i, PyVector4,
+        PyVector4i,
+    },
+    introspection::{FunctionSignature, ModuleMember},
+    module_reloader::ReloadDiff,
+    output::OutputChunk,
+    python_converter::{classify_py_value, PyValue},
+    python_error::PythonError,
+    sandbox_policy::SandboxPolicy,
+    CommonPythonVM,
+};
 
 pub fn convert_py_to_variant_common(common_vm: &CommonPythonVM, value: PyObjectRef) -> Variant {
     common_vm
@@ -20,104 +34,897 @@ pub fn convert_py_to_variant_common(common_vm: &CommonPythonVM, value: PyObjectR
 //         .enter(|vm| convert_variant_to_py_object(vm, value))
 // }
 
+/// Values without a Variant equivalent become nil, see `convert_py_object_to_variant_with`.
 pub fn convert_py_object_to_variant(vm: &VirtualMachine, value: PyObjectRef) -> Variant {
-    // Handle success case
-    // godot_print!("Success: {:?}", value);
-    if let Ok(int_obj) = i64::try_from_object(&vm, value.clone()) {
-        Variant::from(int_obj)
-    } else if let Ok(float_obj) = f64::try_from_object(&vm, value.clone()) {
-        Variant::from(float_obj)
-    } else if let Ok(str_obj) = String::try_from_object(&vm, value.clone()) {
-        Variant::from(str_obj)
-    } else if let Ok(bool_obj) = bool::try_from_object(&vm, value.clone()) {
-        Variant::from(bool_obj)
-    } else if let Some(list) = value.downcast_ref::<PyList>() {
-        let mut arr = VariantArray::new();
-        list.borrow_vec().iter().for_each(|item| {
-            arr.push(convert_py_object_to_variant(vm, item.clone()));
-        });
-        Variant::from(arr)
-        // for item in list.elements() {
-        //     js_arr.push(&convery_py_to_js(vm, item.clone()));
-        // }
-    } else if let Some(dict) = value.downcast_ref::<PyDict>() {
-        let mut obj = Dictionary::new();
-        dict.into_iter().for_each(|(key, val)| {
-            obj.insert(
-                convert_py_object_to_variant(vm, key.clone()),
-                convert_py_object_to_variant(vm, val.clone()),
-            )
-            .expect("property to be settable");
-        });
-        Variant::from(obj)
-    } else {
-        Variant::nil()
+    convert_py_object_to_variant_with(vm, value, &|_| Variant::nil())
+}
+
+/// Like `convert_py_object_to_variant`, but values without a Variant equivalent
+/// (instances, functions, generators...) go through `fallback`, e.g. to wrap them in a handle.
+/// Follows the type table of `PyValue`, after the `godot` types.
+pub fn convert_py_object_to_variant_with(
+    vm: &VirtualMachine,
+    value: PyObjectRef,
+    fallback: &dyn Fn(PyObjectRef) -> Variant,
+) -> Variant {
+    if let Some(variant) = convert_godot_type_to_variant(vm, &value) {
+        return variant;
+    }
+    match classify_py_value(vm, &value) {
+        PyValue::None => Variant::nil(),
+        PyValue::Bool(boolean) => Variant::from(boolean),
+        PyValue::Int(int) => Variant::from(int),
+        PyValue::BigInt(digits) => Variant::from(digits),
+        PyValue::Float(float) => Variant::from(float),
+        PyValue::Str(string) => Variant::from(string),
+        PyValue::Bytes(bytes) => Variant::from(PackedByteArray::from(bytes.as_slice())),
+        PyValue::Sequence(items) => {
+            let mut arr = VariantArray::new();
+            items.into_iter().for_each(|item| {
+                arr.push(convert_py_object_to_variant_with(vm, item, fallback));
+            });
+            Variant::from(arr)
+        }
+        PyValue::Dict(items) => {
+            let mut obj = Dictionary::new();
+            items.into_iter().for_each(|(key, val)| {
+                obj.insert(
+                    convert_py_object_to_variant_with(vm, key, fallback),
+                    convert_py_object_to_variant_with(vm, val, fallback),
+                );
+            });
+            Variant::from(obj)
+        }
+        PyValue::Other(value) => fallback(value),
     }
+}
 
-    // let arr = VariantArray::new();
+/// Godot's math types from `godot.math`, the engine's objects and signals, and the other
+/// Variant types `godot.py` defines.
+/// These go first, `StringName` is a `str` and the packed arrays are lists.
+fn convert_godot_type_to_variant(vm: &VirtualMachine, value: &PyObjectRef) -> Option<Variant> {
+    macro_rules! math_types {
+        ($($py:ty => $godot:ty),* $(,)?) => {
+            $(
+                if let Some(math) = value.downcast_ref::<$py>() {
+                    return Some(Variant::from(<$godot>::from(**math)));
+                }
+            )*
+        };
+    }
+    math_types!(
+        PyVector2 => Vector2,
+        PyVector2i => Vector2i,
+        PyVector3 => Vector3,
+        PyVector3i => Vector3i,
+        PyVector4 => Vector4,
+        PyVector4i => Vector4i,
+        PyColor => Color,
+        PyQuaternion => Quaternion,
+        PyPlane => Plane,
+        PyRect2 => Rect2,
+        PyRect2i => Rect2i,
+        PyAabb => Aabb,
+        PyBasis => Basis,
+        PyTransform2D => Transform2D,
+        PyTransform3D => Transform3D,
+        PyProjection => Projection,
+    );
+    if let Some(object) = value.downcast_ref::<PyGodotObject>() {
+        return Some(object.object().map_or_else(Variant::nil, Variant::from));
+    }
+    if let Some(signal) = value.downcast_ref::<PyGodotSignal>() {
+        return Some(signal.signal().map_or_else(Variant::nil, Variant::from));
+    }
+    let class = value.class();
+    let module = class.as_object().get_attr("__module__", vm).ok()?;
+    if String::try_from_object(vm, module).ok()? != "godot" {
+        return None;
+    }
+    let class_name = class.name().to_string();
+    let items = value
+        .downcast_ref::<PyList>()
+        .map(|list| list.borrow_vec().to_vec())
+        .unwrap_or_default();
+    let number = |item: &PyObjectRef| f64::try_from_object(vm, item.clone()).ok();
+    let integer = |item: &PyObjectRef| i64::try_from_object(vm, item.clone()).ok();
+    match class_name.as_str() {
+        "StringName" => Some(Variant::from(StringName::from(
+            String::try_from_object(vm, value.clone()).ok()?.as_str(),
+        ))),
+        "NodePath" => Some(Variant::from(NodePath::from(
+            String::try_from_object(vm, value.clone()).ok()?.as_str(),
+        ))),
+        "RID" => Some(Variant::from(Rid::new(
+            u64::try_from_object(vm, value.clone()).ok()?,
+        ))),
+        "PackedInt32Array" => items
+            .iter()
+            .map(|item| integer(item).and_then(|i| i32::try_from(i).ok()))
+            .collect::<Option<PackedInt32Array>>()
+            .map(Variant::from),
+        "PackedInt64Array" => items
+            .iter()
+            .map(integer)
+            .collect::<Option<PackedInt64Array>>()
+            .map(Variant::from),
+        "PackedFloat32Array" => items
+            .iter()
+            .map(|item| number(item).map(|f| f as f32))
+            .collect::<Option<PackedFloat32Array>>()
+            .map(Variant::from),
+        "PackedFloat64Array" => items
+            .iter()
+            .map(number)
+            .collect::<Option<PackedFloat64Array>>()
+            .map(Variant::from),
+        "PackedStringArray" => items
+            .iter()
+            .map(|item| {
+                String::try_from_object(vm, item.clone())
+                    .ok()
+                    .map(|s| GString::from(s.as_str()))
+            })
+            .collect::<Option<PackedStringArray>>()
+            .map(Variant::from),
+        "PackedVector2Array" => items
+            .iter()
+            .map(|item| item.downcast_ref::<PyVector2>().map(|v| Vector2::from(**v)))
+            .collect::<Option<PackedVector2Array>>()
+            .map(Variant::from),
+        "PackedVector3Array" => items
+            .iter()
+            .map(|item| item.downcast_ref::<PyVector3>().map(|v| Vector3::from(**v)))
+            .collect::<Option<PackedVector3Array>>()
+            .map(Variant::from),
+        "PackedVector4Array" => items
+            .iter()
+            .map(|item| item.downcast_ref::<PyVector4>().map(|v| Vector4::from(**v)))
+            .collect::<Option<PackedVector4Array>>()
+            .map(Variant::from),
+        "PackedColorArray" => items
+            .iter()
+            .map(|item| item.downcast_ref::<PyColor>().map(|c| Color::from(**c)))
+            .collect::<Option<PackedColorArray>>()
+            .map(Variant::from),
+        _ => None,
+    }
+}
+
+pub fn convert_python_error_to_dict(error: &PythonError) -> Dictionary {
+    let mut traceback = VariantArray::new();
+    error.traceback.iter().for_each(|frame| {
+        let mut frame_dict = Dictionary::new();
+        frame_dict.insert("file", frame.file.clone());
+        frame_dict.insert("line", frame.line as i64);
+        frame_dict.insert(
+            "column",
+            match frame.column {
+                Some(column) => Variant::from(column as i64),
+                None => Variant::nil(),
+            },
+        );
+        frame_dict.insert("function", frame.function.clone());
+        traceback.push(Variant::from(frame_dict));
+    });
+
+    let mut dict = Dictionary::new();
+    dict.insert("type", error.type_name.clone());
+    dict.insert("module", error.module.clone());
+    dict.insert("message", error.message.clone());
+    dict.insert("traceback", traceback);
+    dict.insert(
+        "cause",
+        match &error.cause {
+            Some(cause) => Variant::from(convert_python_error_to_dict(cause)),
+            None => Variant::nil(),
+        },
+    );
+    dict.insert(
+        "context",
+        match &error.context {
+            Some(context) => Variant::from(convert_python_error_to_dict(context)),
+            None => Variant::nil(),
+        },
+    );
+    dict.insert("formatted", error.to_string());
+    dict
 }
 
-pub fn convert_variant_to_py_object(virt: &VirtualMachine, value: Variant) -> PyObjectRef {
+pub fn convert_reload_diff_to_dict(diff: &ReloadDiff) -> Dictionary {
+    let to_array = |names: &Vec<String>| -> PackedStringArray {
+        names
+            .iter()
+            .map(|name| GString::from(name.as_str()))
+            .collect()
+    };
+
+    let mut dict = Dictionary::new();
+    dict.insert("added", to_array(&diff.added));
+    dict.insert("removed", to_array(&diff.removed));
+    dict.insert("changed", to_array(&diff.changed));
+    dict.insert("persisted", to_array(&diff.persisted));
+    dict.insert("updated_modules", to_array(&diff.updated_modules));
+    dict
+}
+
+/// `{stream, text, formatted, source, timestamp_msec}`, `source` is nil for eval and session code.
+pub fn convert_output_chunk_to_dict(chunk: &OutputChunk) -> Dictionary {
+    let mut dict = Dictionary::new();
+    dict.insert("stream", chunk.stream.as_str());
+    dict.insert("text", chunk.text.clone());
+    dict.insert("formatted", chunk.formatted.clone());
+    dict.insert(
+        "source",
+        match &chunk.source {
+            Some(source) => Variant::from(source.clone()),
+            None => Variant::nil(),
+        },
+    );
+    dict.insert("timestamp_msec", chunk.timestamp_msec);
+    dict
+}
+
+pub fn convert_members_to_array(members: &[ModuleMember]) -> VariantArray {
+    members
+        .iter()
+        .map(|member| {
+            let mut dict = Dictionary::new();
+            dict.insert("name", member.name.clone());
+            dict.insert("kind", member.kind.as_str());
+            dict.insert("type", member.type_name.clone());
+            Variant::from(dict)
+        })
+        .collect()
+}
+
+/// Defaults are converted like return values, annotations and the docstring are strings or nil.
+pub fn convert_signature_to_dict(
+    common_vm: &CommonPythonVM,
+    signature: &FunctionSignature,
+) -> Dictionary {
+    let optional_string = |value: &Option<String>| match value {
+        Some(value) => Variant::from(value.clone()),
+        None => Variant::nil(),
+    };
+
+    let mut parameters = VariantArray::new();
+    signature.parameters.iter().for_each(|parameter| {
+        let mut dict = Dictionary::new();
+        dict.insert("name", parameter.name.clone());
+        dict.insert("kind", parameter.kind.as_str());
+        dict.insert("has_default", parameter.default.is_some());
+        dict.insert(
+            "default",
+            match &parameter.default {
+                Some(default) => convert_py_to_variant_common(common_vm, default.clone()),
+                None => Variant::nil(),
+            },
+        );
+        dict.insert("annotation", optional_string(&parameter.annotation));
+        parameters.push(Variant::from(dict));
+    });
+
+    let mut dict = Dictionary::new();
+    dict.insert("name", signature.name.clone());
+    dict.insert("parameters", parameters);
+    dict.insert(
+        "return_annotation",
+        optional_string(&signature.return_annotation),
+    );
+    dict.insert("doc", optional_string(&signature.doc));
+    dict
+}
+
+/// Reads a policy from `{preset, allowed_modules, removed_builtins, allow_dunder_access}`.
+/// Missing keys keep the preset's value; the preset is "unrestricted" unless set to "restricted".
+pub fn convert_dict_to_sandbox_policy(dict: &Dictionary) -> SandboxPolicy {
+    let mut policy = match dict.get("preset") {
+        Some(preset) if preset.to_string() == "restricted" => SandboxPolicy::restricted(),
+        _ => SandboxPolicy::unrestricted(),
+    };
+    if let Some(allowed_modules) = dict.get("allowed_modules") {
+        policy.allowed_modules = if allowed_modules.is_nil() {
+            None
+        } else {
+            Some(convert_variant_to_string_vec(&allowed_modules))
+        };
+    }
+    if let Some(removed_builtins) = dict.get("removed_builtins") {
+        policy.removed_builtins = convert_variant_to_string_vec(&removed_builtins);
+    }
+    if let Some(allow_dunder_access) = dict.get("allow_dunder_access") {
+        policy.allow_dunder_access = allow_dunder_access
+            .try_to::<bool>()
+            .unwrap_or(policy.allow_dunder_access);
+    }
+    policy
+}
+
+fn convert_variant_to_string_vec(value: &Variant) -> Vec<String> {
     match value.get_type() {
-        VariantType::NIL => virt.ctx.none(),
-        VariantType::BOOL => virt.ctx.new_bool(bool::from_variant(&value)).into(),
-        VariantType::INT => virt.ctx.new_int(i32::from_variant(&value)).into(),
-        VariantType::FLOAT => virt.ctx.new_float(f64::from_variant(&value)).into(),
-        VariantType::STRING => virt.ctx.new_str(String::from_variant(&value)).into(),
+        VariantType::PACKED_STRING_ARRAY => PackedStringArray::from_variant(value)
+            .as_slice()
+            .iter()
+            .map(|s| s.to_string())
+            .collect(),
+        VariantType::ARRAY => VariantArray::from_variant(value)
+            .iter_shared()
+            .map(|v| v.to_string())
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Objects are offered to `resolve_object`, e.g. to unwrap handles back into
+/// the Python object they hold or to wrap them in a `godot.Object`.
+/// Anything it does not resolve becomes None. Callables are offered too, those it does
+/// not resolve become functions converting plainly, see `convert_callable_to_py_function`.
+pub fn convert_variant_to_py_object(
+    virt: &VirtualMachine,
+    value: Variant,
+    resolve_object: &dyn Fn(&VirtualMachine, &Variant) -> Option<PyObjectRef>,
+) -> PyObjectRef {
+    match value.get_type() {
+        VariantType::NIL => PyValue::None.into_py_object(virt),
+        VariantType::BOOL => PyValue::Bool(bool::from_variant(&value)).into_py_object(virt),
+        VariantType::INT => PyValue::Int(i64::from_variant(&value)).into_py_object(virt),
+        VariantType::FLOAT => PyValue::Float(f64::from_variant(&value)).into_py_object(virt),
+        VariantType::STRING => PyValue::Str(String::from_variant(&value)).into_py_object(virt),
         VariantType::ARRAY => {
             let arr = VariantArray::from_variant(&value);
             let mut elements = Vec::new();
             for i in 0..arr.len() {
-                elements.push(convert_variant_to_py_object(virt, arr.get(i).unwrap()));
+                elements.push(convert_variant_to_py_object(
+                    virt,
+                    arr.get(i).unwrap(),
+                    resolve_object,
+                ));
             }
-            let list = virt.ctx.new_list(elements);
-            list.into()
+            PyValue::Sequence(elements).into_py_object(virt)
         }
         VariantType::DICTIONARY => {
             let dict = Dictionary::from_variant(&value);
-            let keys = dict.keys_array();
-            let py_dict = virt.ctx.new_dict();
-            for i in 0..keys.len() {
-                let v_key = keys.get(i).unwrap();
-                let key = String::from_variant(&v_key);
-                let _ = py_dict.set_item(
-                    &key.clone(),
-                    convert_variant_to_py_object(virt, dict.get(key).unwrap()),
-                    virt,
+            let items = dict
+                .iter_shared()
+                .map(|(key, value)| {
+                    (
+                        convert_variant_to_py_key(virt, key, resolve_object),
+                        convert_variant_to_py_object(virt, value, resolve_object),
+                    )
+                })
+                .collect();
+            PyValue::Dict(items).into_py_object(virt)
+        }
+        VariantType::OBJECT => resolve_object(virt, &value).unwrap_or_else(|| virt.ctx.none()),
+        VariantType::CALLABLE => resolve_object(virt, &value).unwrap_or_else(|| {
+            convert_callable_to_py_function(
+                virt,
+                Callable::from_variant(&value),
+                |vm, arg| convert_py_object_to_variant(vm, arg),
+                |vm, result| convert_variant_to_py_object(vm, result, &|_, _| None),
+            )
+        }),
+        VariantType::VECTOR2 => new_math(virt, PyVector2::from(Vector2::from_variant(&value))),
+        VariantType::VECTOR2I => new_math(virt, PyVector2i::from(Vector2i::from_variant(&value))),
+        VariantType::VECTOR3 => new_math(virt, PyVector3::from(Vector3::from_variant(&value))),
+        VariantType::VECTOR3I => new_math(virt, PyVector3i::from(Vector3i::from_variant(&value))),
+        VariantType::VECTOR4 => new_math(virt, PyVector4::from(Vector4::from_variant(&value))),
+        VariantType::VECTOR4I => new_math(virt, PyVector4i::from(Vector4i::from_variant(&value))),
+        VariantType::COLOR => new_math(virt, PyColor::from(Color::from_variant(&value))),
+        VariantType::QUATERNION => {
+            new_math(virt, PyQuaternion::from(Quaternion::from_variant(&value)))
+        }
+        VariantType::PLANE => new_math(virt, PyPlane::from(Plane::from_variant(&value))),
+        VariantType::RECT2 => new_math(virt, PyRect2::from(Rect2::from_variant(&value))),
+        VariantType::RECT2I => new_math(virt, PyRect2i::from(Rect2i::from_variant(&value))),
+        VariantType::AABB => new_math(virt, PyAabb::from(Aabb::from_variant(&value))),
+        VariantType::BASIS => new_math(virt, PyBasis::from(Basis::from_variant(&value))),
+        VariantType::TRANSFORM2D => {
+            new_math(virt, PyTransform2D::from(Transform2D::from_variant(&value)))
+        }
+        VariantType::TRANSFORM3D => {
+            new_math(virt, PyTransform3D::from(Transform3D::from_variant(&value)))
+        }
+        VariantType::PROJECTION => {
+            new_math(virt, PyProjection::from(Projection::from_variant(&value)))
+        }
+        VariantType::STRING_NAME => new_godot_type(
+            virt,
+            "StringName",
+            virt.ctx
+                .new_str(StringName::from_variant(&value).to_string())
+                .into(),
+        ),
+        VariantType::NODE_PATH => new_godot_type(
+            virt,
+            "NodePath",
+            virt.ctx
+                .new_str(NodePath::from_variant(&value).to_string())
+                .into(),
+        ),
+        VariantType::RID => new_godot_type(
+            virt,
+            "RID",
+            virt.ctx.new_int(Rid::from_variant(&value).to_u64()).into(),
+        ),
+        VariantType::PACKED_BYTE_ARRAY => {
+            PyValue::Bytes(PackedByteArray::from_variant(&value).to_vec()).into_py_object(virt)
+        }
+        VariantType::PACKED_INT32_ARRAY => new_packed_array(
+            virt,
+            "PackedInt32Array",
+            PackedInt32Array::from_variant(&value)
+                .as_slice()
+                .iter()
+                .map(|i| virt.ctx.new_int(*i).into())
+                .collect(),
+        ),
+        VariantType::PACKED_INT64_ARRAY => new_packed_array(
+            virt,
+            "PackedInt64Array",
+            PackedInt64Array::from_variant(&value)
+                .as_slice()
+                .iter()
+                .map(|i| virt.ctx.new_int(*i).into())
+                .collect(),
+        ),
+        VariantType::PACKED_FLOAT32_ARRAY => new_packed_array(
+            virt,
+            "PackedFloat32Array",
+            PackedFloat32Array::from_variant(&value)
+                .as_slice()
+                .iter()
+                .map(|f| virt.ctx.new_float(*f as f64).into())
+                .collect(),
+        ),
+        VariantType::PACKED_FLOAT64_ARRAY => new_packed_array(
+            virt,
+            "PackedFloat64Array",
+            PackedFloat64Array::from_variant(&value)
+                .as_slice()
+                .iter()
+                .map(|f| virt.ctx.new_float(*f).into())
+                .collect(),
+        ),
+        VariantType::PACKED_STRING_ARRAY => new_packed_array(
+            virt,
+            "PackedStringArray",
+            PackedStringArray::from_variant(&value)
+                .as_slice()
+                .iter()
+                .map(|s| virt.ctx.new_str(s.to_string()).into())
+                .collect(),
+        ),
+        VariantType::PACKED_VECTOR2_ARRAY => new_packed_array(
+            virt,
+            "PackedVector2Array",
+            PackedVector2Array::from_variant(&value)
+                .as_slice()
+                .iter()
+                .map(|v| new_math(virt, PyVector2::from(*v)))
+                .collect(),
+        ),
+        VariantType::PACKED_VECTOR3_ARRAY => new_packed_array(
+            virt,
+            "PackedVector3Array",
+            PackedVector3Array::from_variant(&value)
+                .as_slice()
+                .iter()
+                .map(|v| new_math(virt, PyVector3::from(*v)))
+                .collect(),
+        ),
+        VariantType::PACKED_VECTOR4_ARRAY => new_packed_array(
+            virt,
+            "PackedVector4Array",
+            PackedVector4Array::from_variant(&value)
+                .as_slice()
+                .iter()
+                .map(|v| new_math(virt, PyVector4::from(*v)))
+                .collect(),
+        ),
+        VariantType::PACKED_COLOR_ARRAY => new_packed_array(
+            virt,
+            "PackedColorArray",
+            PackedColorArray::from_variant(&value)
+                .as_slice()
+                .iter()
+                .map(|c| new_math(virt, PyColor::from(*c)))
+                .collect(),
+        ),
+        _ => virt.ctx.none(),
+    }
+}
+
+/// A Python function calling `callable`, its arguments go through `to_variant` and its
+/// result through `to_py_object`. Callables take no keyword arguments.
+pub(crate) fn convert_callable_to_py_function(
+    vm: &VirtualMachine,
+    callable: Callable,
+    to_variant: impl Fn(&VirtualMachine, PyObjectRef) -> Variant + 'static,
+    to_py_object: impl Fn(&VirtualMachine, Variant) -> PyObjectRef + 'static,
+) -> PyObjectRef {
+    let name = callable
+        .method_name()
+        .map(|name| name.to_string())
+        .unwrap_or_else(|| "callable".to_owned());
+    vm.new_function(
+        vm.ctx.intern_str(name).as_str(),
+        move |args: FuncArgs, vm: &VirtualMachine| -> PyResult {
+            if !args.kwargs.is_empty() {
+                return Err(
+                    vm.new_type_error("Godot Callables take no keyword arguments".to_owned())
                 );
-                // elements.push((convert_variant_to_py_object(virt, value),));
             }
-            py_dict.into()
+            if !callable.is_valid() {
+                return Err(
+                    vm.new_runtime_error(format!("The Callable {} is not valid anymore", callable))
+                );
+            }
+            let args = args
+                .args
+                .into_iter()
+                .map(|arg| to_variant(vm, arg))
+                .collect::<VariantArray>();
+            let result = callable.callv(args);
+            Ok(to_py_object(vm, result))
+        },
+    )
+    .into()
+}
+
+/// Like `convert_variant_to_py_object`, but hashable to be a dict key. Arrays and
+/// packed arrays become tuples, Dictionaries tuples of `(key, value)` tuples.
+fn convert_variant_to_py_key(
+    virt: &VirtualMachine,
+    value: Variant,
+    resolve_object: &dyn Fn(&VirtualMachine, &Variant) -> Option<PyObjectRef>,
+) -> PyObjectRef {
+    let to_key = |item: Variant| convert_variant_to_py_key(virt, item, resolve_object);
+    match value.get_type() {
+        VariantType::ARRAY => virt
+            .ctx
+            .new_tuple(
+                VariantArray::from_variant(&value)
+                    .iter_shared()
+                    .map(to_key)
+                    .collect(),
+            )
+            .into(),
+        VariantType::DICTIONARY => virt
+            .ctx
+            .new_tuple(
+                Dictionary::from_variant(&value)
+                    .iter_shared()
+                    .map(|(key, value)| {
+                        let pair = vec![to_key(key), to_key(value)];
+                        virt.ctx.new_tuple(pair).into()
+                    })
+                    .collect(),
+            )
+            .into(),
+        _ => {
+            let key = convert_variant_to_py_object(virt, value, resolve_object);
+            // The packed arrays, their items are numbers, strings and math types
+            match key.downcast_ref::<PyList>() {
+                Some(list) => virt.ctx.new_tuple(list.borrow_vec().to_vec()).into(),
+                None => key,
+            }
+        }
+    }
+}
+
+fn new_math<T: PyPayload>(vm: &VirtualMachine, value: T) -> PyObjectRef {
+    value.into_ref(&vm.ctx).into()
+}
+
+/// An instance of one of the Variant types `godot.py` defines, e.g. `StringName`.
+/// Falls back to the plain `value` if `godot` cannot be imported.
+fn new_godot_type(vm: &VirtualMachine, class_name: &str, value: PyObjectRef) -> PyObjectRef {
+    vm.import("godot", None, 0)
+        .and_then(|godot| godot.get_attr(class_name, vm))
+        .and_then(|class| class.call((value.clone(),), vm))
+        .unwrap_or(value)
+}
+
+fn new_packed_array(vm: &VirtualMachine, class_name: &str, items: Vec<PyObjectRef>) -> PyObjectRef {
+    new_godot_type(vm, class_name, vm.ctx.new_list(items).into())
+}
+
+impl From<Vector2> for PyVector2 {
+    fn from(v: Vector2) -> Self {
+        Self { x: v.x, y: v.y }
+    }
+}
+
+impl From<PyVector2> for Vector2 {
+    fn from(v: PyVector2) -> Self {
+        Vector2::new(v.x, v.y)
+    }
+}
+
+impl From<Vector2i> for PyVector2i {
+    fn from(v: Vector2i) -> Self {
+        Self { x: v.x, y: v.y }
+    }
+}
+
+impl From<PyVector2i> for Vector2i {
+    fn from(v: PyVector2i) -> Self {
+        Vector2i::new(v.x, v.y)
+    }
+}
+
+impl From<Vector3> for PyVector3 {
+    fn from(v: Vector3) -> Self {
+        Self {
+            x: v.x,
+            y: v.y,
+            z: v.z,
+        }
+    }
+}
+
+impl From<PyVector3> for Vector3 {
+    fn from(v: PyVector3) -> Self {
+        Vector3::new(v.x, v.y, v.z)
+    }
+}
+
+impl From<Vector3i> for PyVector3i {
+    fn from(v: Vector3i) -> Self {
+        Self {
+            x: v.x,
+            y: v.y,
+            z: v.z,
         }
-        _ => virt.ctx.none(),
+    }
+}
+
+impl From<PyVector3i> for Vector3i {
+    fn from(v: PyVector3i) -> Self {
+        Vector3i::new(v.x, v.y, v.z)
+    }
+}
+
+impl From<Vector4> for PyVector4 {
+    fn from(v: Vector4) -> Self {
+        Self {
+            x: v.x,
+            y: v.y,
+            z: v.z,
+            w: v.w,
+        }
+    }
+}
+
+impl From<PyVector4> for Vector4 {
+    fn from(v: PyVector4) -> Self {
+        Vector4::new(v.x, v.y, v.z, v.w)
+    }
+}
+
+impl From<Vector4i> for PyVector4i {
+    fn from(v: Vector4i) -> Self {
+        Self {
+            x: v.x,
+            y: v.y,
+            z: v.z,
+            w: v.w,
+        }
+    }
+}
+
+impl From<PyVector4i> for Vector4i {
+    fn from(v: PyVector4i) -> Self {
+        Vector4i::new(v.x, v.y, v.z, v.w)
+    }
+}
+
+impl From<Color> for PyColor {
+    fn from(c: Color) -> Self {
+        Self {
+            r: c.r,
+            g: c.g,
+            b: c.b,
+            a: c.a,
+        }
+    }
+}
+
+impl From<PyColor> for Color {
+    fn from(c: PyColor) -> Self {
+        Color::from_rgba(c.r, c.g, c.b, c.a)
+    }
+}
+
+impl From<Quaternion> for PyQuaternion {
+    fn from(q: Quaternion) -> Self {
+        Self {
+            x: q.x,
+            y: q.y,
+            z: q.z,
+            w: q.w,
+        }
+    }
+}
+
+impl From<PyQuaternion> for Quaternion {
+    fn from(q: PyQuaternion) -> Self {
+        Quaternion::new(q.x, q.y, q.z, q.w)
+    }
+}
+
+impl From<Plane> for PyPlane {
+    fn from(p: Plane) -> Self {
+        Self {
+            normal: p.normal.into(),
+            d: p.d,
+        }
+    }
+}
+
+impl From<PyPlane> for Plane {
+    fn from(p: PyPlane) -> Self {
+        // Not `Plane::new`, which insists on a unit normal
+        Plane {
+            normal: p.normal.into(),
+            d: p.d,
+        }
+    }
+}
+
+impl From<Rect2> for PyRect2 {
+    fn from(r: Rect2) -> Self {
+        Self {
+            position: r.position.into(),
+            size: r.size.into(),
+        }
+    }
+}
+
+impl From<PyRect2> for Rect2 {
+    fn from(r: PyRect2) -> Self {
+        Rect2::new(r.position.into(), r.size.into())
+    }
+}
+
+impl From<Rect2i> for PyRect2i {
+    fn from(r: Rect2i) -> Self {
+        Self {
+            position: r.position.into(),
+            size: r.size.into(),
+        }
+    }
+}
+
+impl From<PyRect2i> for Rect2i {
+    fn from(r: PyRect2i) -> Self {
+        Rect2i::new(r.position.into(), r.size.into())
+    }
+}
+
+impl From<Aabb> for PyAabb {
+    fn from(a: Aabb) -> Self {
+        Self {
+            position: a.position.into(),
+            size: a.size.into(),
+        }
+    }
+}
+
+impl From<PyAabb> for Aabb {
+    fn from(a: PyAabb) -> Self {
+        Aabb::new(a.position.into(), a.size.into())
+    }
+}
+
+impl From<Basis> for PyBasis {
+    fn from(b: Basis) -> Self {
+        Self {
+            x: b.col_a().into(),
+            y: b.col_b().into(),
+            z: b.col_c().into(),
+        }
+    }
+}
+
+impl From<PyBasis> for Basis {
+    fn from(b: PyBasis) -> Self {
+        Basis::from_cols(b.x.into(), b.y.into(), b.z.into())
+    }
+}
+
+impl From<Transform2D> for PyTransform2D {
+    fn from(t: Transform2D) -> Self {
+        Self {
+            x: t.a.into(),
+            y: t.b.into(),
+            origin: t.origin.into(),
+        }
+    }
+}
+
+impl From<PyTransform2D> for Transform2D {
+    fn from(t: PyTransform2D) -> Self {
+        Transform2D::from_cols(t.x.into(), t.y.into(), t.origin.into())
+    }
+}
+
+impl From<Transform3D> for PyTransform3D {
+    fn from(t: Transform3D) -> Self {
+        Self {
+            basis: t.basis.into(),
+            origin: t.origin.into(),
+        }
+    }
+}
+
+impl From<PyTransform3D> for Transform3D {
+    fn from(t: PyTransform3D) -> Self {
+        Transform3D::new(t.basis.into(), t.origin.into())
+    }
+}
+
+impl From<Projection> for PyProjection {
+    fn from(p: Projection) -> Self {
+        let [x, y, z, w] = p.cols;
+        Self {
+            x: x.into(),
+            y: y.into(),
+            z: z.into(),
+            w: w.into(),
+        }
+    }
+}
+
+impl From<PyProjection> for Projection {
+    fn from(p: PyProjection) -> Self {
+        Projection::from_cols(p.x.into(), p.y.into(), p.z.into(), p.w.into())
     }
 }
 
 pub fn convert_variant_arr_to_args(
     common_vm: &CommonPythonVM,
     arr: VariantArray,
+    resolve_object: &dyn Fn(&VirtualMachine, &Variant) -> Option<PyObjectRef>,
 ) -> Vec<PyObjectRef> {
     common_vm.interpreter.enter(|vm| {
         let mut elements = Vec::new();
         for i in 0..arr.len() {
-            elements.push(convert_variant_to_py_object(vm, arr.get(i).unwrap()));
+            elements.push(convert_variant_to_py_object(
+                vm,
+                arr.get(i).unwrap(),
+                resolve_object,
+            ));
         }
         elements
     })
 }
 
-pub fn convert_variant_dict_to_kwargs(common_vm: &CommonPythonVM, dict: Dictionary) -> KwArgs {
+/// Only String and StringName keys can be keyword names, others are a TypeError.
+pub fn convert_variant_dict_to_kwargs(
+    common_vm: &CommonPythonVM,
+    dict: Dictionary,
+    resolve_object: &dyn Fn(&VirtualMachine, &Variant) -> Option<PyObjectRef>,
+) -> Result<KwArgs, PythonError> {
     common_vm.interpreter.enter(|vm| {
-        let keys = dict.keys_array();
         let mut map = IndexMap::new();
-        for i in 0..keys.len() {
-            let v_key = keys.get(i).unwrap();
-            let key = String::from_variant(&v_key);
-            let _ = map.insert(
-                key.clone(),
-                convert_variant_to_py_object(vm, dict.get(key).unwrap()),
+        for (key, value) in dict.iter_shared() {
+            let name = match key.get_type() {
+                VariantType::STRING | VariantType::STRING_NAME => key.to_string(),
+                _ => {
+                    return Err(PythonError::host(
+                        "TypeError",
+                        format!("keywords must be strings, got {:?} {}", key.get_type(), key),
+                    ))
+                }
+            };
+            map.insert(
+                name,
+                convert_variant_to_py_object(vm, value, resolve_object),
             );
         }
-        KwArgs::new(map)
+        Ok(KwArgs::new(map))
     })
 }
@@ -0,0 +1,378 @@
+use std::fmt;
+
+use godot::{
+    classes::{ClassDb, Engine},
+    prelude::*,
+};
+use rustpython_vm::{
+    builtins::{PyModule, PyStr},
+    common::hash::PyHash,
+    function::{FuncArgs, PyComparisonValue, PySetterValue},
+    pyclass, pymodule,
+    types::{Comparable, GetAttr, Hashable, PyComparisonOp, Representable, SetAttr},
+    AsObject, Py, PyObject, PyObjectRef, PyPayload, PyRef, PyResult, VirtualMachine,
+};
+
+use super::{
+    godot_converter::{
+        convert_callable_to_py_function, convert_py_object_to_variant_with,
+        convert_variant_to_py_object,
+    },
+    python_object::PythonObject,
+    GodotPythonVM,
+};
+use crate::python_vm_common::{object_handles::ObjectHandles, python_converter::py_function_name};
+
+/// The engine singletons `godot` exposes by name, `get_singleton` reaches the others.
+const SINGLETONS: [&str; 4] = ["Input", "Engine", "OS", "ResourceLoader"];
+
+/// Converts values the way the VM owning `handles` does, also for values Python code
+/// passes to and gets from the engine: Python values without a Variant equivalent become
+/// `PythonObject`s (functions a Callable), Godot objects become `godot.Object`s.
+#[derive(Clone)]
+pub(crate) struct VariantBridge {
+    owner: Gd<GodotPythonVM>,
+    handles: ObjectHandles,
+}
+
+impl fmt::Debug for VariantBridge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("VariantBridge")
+    }
+}
+
+impl VariantBridge {
+    pub(crate) fn new(owner: Gd<GodotPythonVM>, handles: ObjectHandles) -> Self {
+        Self { owner, handles }
+    }
+
+    pub(crate) fn to_variant(&self, vm: &VirtualMachine, value: PyObjectRef) -> Variant {
+        convert_py_object_to_variant_with(vm, value, &|object| {
+            let function_name = py_function_name(vm, &object);
+            let handle = self.handles.register(object);
+            let object = PythonObject::new(self.owner.clone(), self.handles.clone(), handle);
+            match function_name {
+                Some(name) => Variant::from(PythonObject::into_callable(vm, object, &name)),
+                None => Variant::from(object),
+            }
+        })
+    }
+
+    pub(crate) fn to_py_object(&self, vm: &VirtualMachine, value: Variant) -> PyObjectRef {
+        convert_variant_to_py_object(vm, value, &|vm, object| self.resolve(vm, object))
+    }
+
+    /// `PythonObject`s of the VM, and the Callables made of its functions, unwrap into the
+    /// object they hold, other objects become `godot.Object`.
+    /// Other Callables become functions converting like engine methods do.
+    pub(crate) fn resolve(&self, vm: &VirtualMachine, value: &Variant) -> Option<PyObjectRef> {
+        if let Some(object) = PythonObject::resolve(value, &self.handles) {
+            return Some(object);
+        }
+        if let Ok(callable) = value.try_to::<Callable>() {
+            let (to_variant, to_py_object) = (self.clone(), self.clone());
+            return Some(convert_callable_to_py_function(
+                vm,
+                callable,
+                move |vm, arg| to_variant.to_variant(vm, arg),
+                move |vm, result| to_py_object.to_py_object(vm, result),
+            ));
+        }
+        let object = value.try_to::<Gd<Object>>().ok()?;
+        Some(
+            PyGodotObject::new(object, self.clone())
+                .into_ref(&vm.ctx)
+                .into(),
+        )
+    }
+}
+
+/// `object`, or a ReferenceError once it was freed.
+fn live_object(object: &Gd<Object>, vm: &VirtualMachine) -> PyResult<Gd<Object>> {
+    if object.is_instance_valid() {
+        Ok(object.clone())
+    } else {
+        Err(vm.new_exception_msg(
+            vm.ctx.exceptions.reference_error.to_owned(),
+            "The Godot object was freed".to_owned(),
+        ))
+    }
+}
+
+/// Script variables count too, ClassDB only knows the native ones.
+fn has_property(object: &Gd<Object>, name: &str) -> bool {
+    object.get_property_list().iter_shared().any(|property| {
+        property
+            .get("name")
+            .map_or(false, |property_name| property_name.to_string() == name)
+    })
+}
+
+/// A Godot object. Properties, methods, signals and integer constants resolve by name
+/// through the object and ClassDB, e.g. `node.get_node("Player").position = Vector2(1, 2)`.
+/// Anything but `is_instance_valid()` raises ReferenceError once the object was freed.
+#[pyclass(module = "godot", name = "Object")]
+#[derive(Debug, PyPayload)]
+pub struct PyGodotObject {
+    object: Gd<Object>,
+    instance_id: InstanceId,
+    bridge: VariantBridge,
+}
+
+impl PyGodotObject {
+    pub(crate) fn new(object: Gd<Object>, bridge: VariantBridge) -> Self {
+        Self {
+            instance_id: object.instance_id(),
+            object,
+            bridge,
+        }
+    }
+
+    /// The object, unless it was freed.
+    pub(crate) fn object(&self) -> Option<Gd<Object>> {
+        self.object.is_instance_valid().then(|| self.object.clone())
+    }
+
+    /// A Python function calling the Godot method `name` on the object.
+    fn method(&self, vm: &VirtualMachine, name: &str) -> PyObjectRef {
+        let object = self.object.clone();
+        let bridge = self.bridge.clone();
+        let method = StringName::from(name);
+        vm.new_function(
+            vm.ctx.intern_str(name).as_str(),
+            move |args: FuncArgs, vm: &VirtualMachine| -> PyResult {
+                if !args.kwargs.is_empty() {
+                    return Err(
+                        vm.new_type_error("Godot methods take no keyword arguments".to_owned())
+                    );
+                }
+                let mut object = live_object(&object, vm)?;
+                let args = args
+                    .args
+                    .into_iter()
+                    .map(|arg| bridge.to_variant(vm, arg))
+                    .collect::<VariantArray>();
+                let result = object.callv(&method, args);
+                Ok(bridge.to_py_object(vm, result))
+            },
+        )
+        .into()
+    }
+}
+
+#[pyclass(with(GetAttr, SetAttr, Comparable, Hashable, Representable))]
+impl PyGodotObject {
+    /// False once the object was freed, e.g. after `queue_free()`.
+    #[pymethod]
+    fn is_instance_valid(&self) -> bool {
+        self.object.is_instance_valid()
+    }
+}
+
+impl GetAttr for PyGodotObject {
+    fn getattro(zelf: &Py<Self>, name: &Py<PyStr>, vm: &VirtualMachine) -> PyResult {
+        if let Some(attr) = zelf.as_object().generic_getattr_opt(name, None, vm)? {
+            return Ok(attr);
+        }
+        let object = live_object(&zelf.object, vm)?;
+        let name = name.as_str();
+        if has_property(&object, name) {
+            return Ok(zelf.bridge.to_py_object(vm, object.get(name)));
+        }
+        if object.has_method(name) {
+            return Ok(zelf.method(vm, name));
+        }
+        if object.has_signal(name) {
+            let signal = PyGodotSignal {
+                object,
+                name: StringName::from(name),
+                bridge: zelf.bridge.clone(),
+            };
+            return Ok(signal.into_ref(&vm.ctx).into());
+        }
+        let class = StringName::from(object.get_class());
+        let class_db = ClassDb::singleton();
+        if class_db.class_has_integer_constant(&class, name) {
+            let constant = class_db.class_get_integer_constant(&class, name);
+            return Ok(vm.ctx.new_int(constant).into());
+        }
+        Err(vm.new_attribute_error(format!("'{}' object has no attribute '{}'", class, name)))
+    }
+}
+
+impl SetAttr for PyGodotObject {
+    fn setattro(
+        zelf: &Py<Self>,
+        name: &Py<PyStr>,
+        value: PySetterValue,
+        vm: &VirtualMachine,
+    ) -> PyResult<()> {
+        let mut object = live_object(&zelf.object, vm)?;
+        let value = match value {
+            PySetterValue::Assign(value) => value,
+            PySetterValue::Delete => {
+                return Err(vm.new_type_error("Godot properties can't be deleted".to_owned()))
+            }
+        };
+        if !has_property(&object, name.as_str()) {
+            return Err(vm.new_attribute_error(format!(
+                "'{}' object has no property '{}'",
+                object.get_class(),
+                name
+            )));
+        }
+        object.set(name.as_str(), &zelf.bridge.to_variant(vm, value));
+        Ok(())
+    }
+}
+
+impl Comparable for PyGodotObject {
+    fn cmp(
+        zelf: &Py<Self>,
+        other: &PyObject,
+        op: PyComparisonOp,
+        _vm: &VirtualMachine,
+    ) -> PyResult<PyComparisonValue> {
+        op.eq_only(|| match other.downcast_ref::<Self>() {
+            Some(other) => Ok(PyComparisonValue::Implemented(
+                zelf.instance_id == other.instance_id,
+            )),
+            None => Ok(PyComparisonValue::NotImplemented),
+        })
+    }
+}
+
+impl Hashable for PyGodotObject {
+    fn hash(zelf: &Py<Self>, _vm: &VirtualMachine) -> PyResult<PyHash> {
+        Ok(zelf.instance_id.to_i64() as PyHash)
+    }
+}
+
+impl Representable for PyGodotObject {
+    fn repr_str(zelf: &Py<Self>, _vm: &VirtualMachine) -> PyResult<String> {
+        Ok(match zelf.object() {
+            Some(object) => format!("<godot.Object {}>", object),
+            None => format!("<godot.Object #{} (freed)>", zelf.instance_id.to_i64()),
+        })
+    }
+}
+
+/// A signal of a Godot object, e.g. `button.pressed.connect(on_pressed)`.
+/// Connected Python functions stay alive until they are disconnected.
+#[pyclass(module = "godot", name = "Signal")]
+#[derive(Debug, PyPayload)]
+pub struct PyGodotSignal {
+    object: Gd<Object>,
+    name: StringName,
+    bridge: VariantBridge,
+}
+
+impl PyGodotSignal {
+    /// The signal, unless its object was freed.
+    pub(crate) fn signal(&self) -> Option<Signal> {
+        self.object
+            .is_instance_valid()
+            .then(|| Signal::from_object_signal(&self.object, &self.name))
+    }
+
+    fn to_callable(&self, vm: &VirtualMachine, callable: PyObjectRef) -> PyResult<Callable> {
+        let type_name = callable.class().name().to_string();
+        self.bridge
+            .to_variant(vm, callable)
+            .try_to::<Callable>()
+            .map_err(|_| vm.new_type_error(format!("Expected a function, got {}", type_name)))
+    }
+}
+
+#[pyclass(with(Representable))]
+impl PyGodotSignal {
+    #[pymethod]
+    fn connect(&self, callable: PyObjectRef, vm: &VirtualMachine) -> PyResult<()> {
+        let callable = self.to_callable(vm, callable)?;
+        live_object(&self.object, vm)?.connect(&self.name, &callable);
+        Ok(())
+    }
+
+    #[pymethod]
+    fn disconnect(&self, callable: PyObjectRef, vm: &VirtualMachine) -> PyResult<()> {
+        let callable = self.to_callable(vm, callable)?;
+        live_object(&self.object, vm)?.disconnect(&self.name, &callable);
+        Ok(())
+    }
+
+    #[pymethod]
+    fn is_connected(&self, callable: PyObjectRef, vm: &VirtualMachine) -> PyResult<bool> {
+        let callable = self.to_callable(vm, callable)?;
+        Ok(live_object(&self.object, vm)?.is_connected(&self.name, &callable))
+    }
+
+    #[pymethod]
+    fn emit(&self, args: FuncArgs, vm: &VirtualMachine) -> PyResult<()> {
+        let args = args
+            .args
+            .into_iter()
+            .map(|arg| self.bridge.to_variant(vm, arg))
+            .collect::<Vec<_>>();
+        live_object(&self.object, vm)?.emit_signal(&self.name, &args);
+        Ok(())
+    }
+}
+
+impl Representable for PyGodotSignal {
+    fn repr_str(zelf: &Py<Self>, _vm: &VirtualMachine) -> PyResult<String> {
+        let object = match zelf.object.is_instance_valid() {
+            true => zelf.object.to_string(),
+            false => "(freed)".to_owned(),
+        };
+        Ok(format!("Signal({}, {:?})", object, zelf.name.to_string()))
+    }
+}
+
+/// The `godot_engine` module, which `godot` re-exports: the `Object` and `Signal` types,
+/// the engine singletons and `get_singleton(name)`.
+pub fn create_godot_engine(vm: &VirtualMachine, bridge: VariantBridge) -> PyRef<PyModule> {
+    let module = godot_engine::make_module(vm);
+    let engine = Engine::singleton();
+    for name in SINGLETONS {
+        if let Some(singleton) = engine.get_singleton(name) {
+            let singleton = PyGodotObject::new(singleton, bridge.clone());
+            module
+                .as_object()
+                .set_attr(name, singleton.into_ref(&vm.ctx), vm)
+                .expect("godot_engine to accept the singletons");
+        }
+    }
+
+    let get_singleton = vm.new_function(
+        "get_singleton",
+        move |name: String, vm: &VirtualMachine| -> PyResult {
+            match Engine::singleton().get_singleton(name.as_str()) {
+                Some(singleton) => Ok(PyGodotObject::new(singleton, bridge.clone())
+                    .into_ref(&vm.ctx)
+                    .into()),
+                None => Err(vm.new_value_error(format!("No engine singleton {:?}", name))),
+            }
+        },
+    );
+    module
+        .as_object()
+        .set_attr("get_singleton", get_singleton, vm)
+        .expect("godot_engine to accept get_singleton");
+    module
+}
+
+#[pymodule]
+pub mod godot_engine {
+    use super::*;
+    use rustpython_vm::{builtins::PyTypeRef, class::PyClassImpl};
+
+    #[pyattr(name = "Object")]
+    fn object(vm: &VirtualMachine) -> PyTypeRef {
+        PyGodotObject::make_class(&vm.ctx)
+    }
+
+    #[pyattr(name = "Signal")]
+    fn signal(vm: &VirtualMachine) -> PyTypeRef {
+        PyGodotSignal::make_class(&vm.ctx)
+    }
+}
@@ -0,0 +1,76 @@
+use godot::prelude::*;
+
+use super::godot_converter::convert_output_chunk_to_dict;
+use crate::python_vm_common::output::{BBCodeMarkup, OutputChunk, OutputMarkup};
+
+/// Sends each chunk to `append_output_chunk(chunk)` when `node` has that method,
+/// otherwise only the formatted text to `append_output(text)`.
+pub fn node_output_sink(node: Gd<Node>) -> impl Fn(OutputChunk) {
+    let wants_chunks = node.has_method("append_output_chunk");
+    let callable = if wants_chunks {
+        node.callable("append_output_chunk")
+    } else {
+        node.callable("append_output")
+    };
+
+    move |chunk: OutputChunk| {
+        if wants_chunks {
+            callable.call(&[Variant::from(convert_output_chunk_to_dict(&chunk))]);
+        } else {
+            callable.call(&[Variant::from(chunk.formatted)]);
+        }
+    }
+}
+
+/// Calls `callable(text)` with the formatted text of each chunk.
+pub fn callable_output_sink(callable: Callable) -> impl Fn(OutputChunk) {
+    move |chunk: OutputChunk| {
+        callable.call(&[Variant::from(chunk.formatted)]);
+    }
+}
+
+/// A Callable for `callable_output_sink`, a Node for `node_output_sink`, or nil for none.
+pub fn convert_variant_to_output_sink(
+    target: &Variant,
+) -> Result<Option<Box<dyn Fn(OutputChunk)>>, String> {
+    if target.is_nil() {
+        return Ok(None);
+    }
+    if let Ok(callable) = target.try_to::<Callable>() {
+        return Ok(Some(Box::new(callable_output_sink(callable))));
+    }
+    match target.try_to::<Gd<Node>>() {
+        Ok(node) => Ok(Some(Box::new(node_output_sink(node)))),
+        Err(_) => Err(format!("Cannot send output to {}", target)),
+    }
+}
+
+/// Markup computed in GDScript: the Callable gets the chunk dict and returns the text to show.
+pub struct CallableMarkup(pub Callable);
+
+impl OutputMarkup for CallableMarkup {
+    fn apply(&self, chunk: &OutputChunk) -> String {
+        let mut arr = VariantArray::new();
+        arr.push(Variant::from(convert_output_chunk_to_dict(chunk)));
+        self.0
+            .callv(arr)
+            .try_to::<String>()
+            .unwrap_or_else(|_| chunk.text.clone())
+    }
+}
+
+/// nil for no markup, `"bbcode"` for `BBCodeMarkup`, or a Callable for `CallableMarkup`.
+pub fn convert_variant_to_markup(
+    markup: &Variant,
+) -> Result<Option<Box<dyn OutputMarkup>>, String> {
+    if markup.is_nil() {
+        return Ok(None);
+    }
+    if let Ok(callable) = markup.try_to::<Callable>() {
+        return Ok(Some(Box::new(CallableMarkup(callable))));
+    }
+    match markup.to_string().as_str() {
+        "bbcode" => Ok(Some(Box::new(BBCodeMarkup::default()))),
+        other => Err(format!("Unknown output markup {:?}", other)),
+    }
+}
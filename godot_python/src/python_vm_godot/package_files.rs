@@ -0,0 +1,133 @@
+use godot::{
+    classes::{DirAccess, FileAccess},
+    prelude::*,
+};
+
+use crate::python_vm_common::{
+    module_source::ModuleSource, package_loader::PackageFile, python_error::PythonError,
+};
+
+/// Finds modules below a `res://` or `user://` directory when they are imported,
+/// so `importlib.reload` picks up edited files.
+pub struct GodotResSource {
+    root_path: String,
+}
+
+impl GodotResSource {
+    pub fn new(root_path: &str) -> Self {
+        Self {
+            root_path: root_path.trim_end_matches('/').to_owned(),
+        }
+    }
+}
+
+impl ModuleSource for GodotResSource {
+    fn find_module(&self, module_name: &str) -> Option<PackageFile> {
+        let relative_dir = module_name.replace('.', "/");
+        for relative_path in [
+            format!("{}/__init__.py", relative_dir),
+            format!("{}.py", relative_dir),
+        ] {
+            let full_path = GString::from(join_path(&self.root_path, &relative_path).as_str());
+            if FileAccess::file_exists(&full_path) {
+                let source = FileAccess::get_file_as_string(&full_path);
+                return PackageFile::from_relative_path(
+                    "",
+                    &self.root_path,
+                    &relative_path,
+                    source.to_string(),
+                );
+            }
+        }
+
+        // Directories without an `__init__.py` still work as packages
+        let dir_path = join_path(&self.root_path, &relative_dir);
+        if DirAccess::dir_exists_absolute(&GString::from(dir_path.as_str())) {
+            return Some(PackageFile::empty_package(
+                module_name.to_owned(),
+                format!("{}/__init__.py", dir_path),
+            ));
+        }
+        None
+    }
+}
+
+/// Walks a `res://` or `user://` directory and returns the package name (the
+/// directory name) with every `.py` file below it.
+///
+/// Exported projects only contain `.py` files if the export filters include them.
+pub fn collect_package_files(root_path: &str) -> Result<(String, Vec<PackageFile>), PythonError> {
+    let root_path = root_path.trim_end_matches('/');
+    let package_name = match root_path.rsplit('/').next() {
+        Some(name) if !name.is_empty() && !name.ends_with(':') => name.to_owned(),
+        _ => {
+            return Err(PythonError::host(
+                "ValueError",
+                format!("Not a package directory: {:?}", root_path),
+            ));
+        }
+    };
+
+    let mut files = Vec::new();
+    collect_dir(root_path, "", &package_name, &mut files)?;
+    Ok((package_name, files))
+}
+
+fn collect_dir(
+    root_path: &str,
+    relative_dir: &str,
+    package_name: &str,
+    files: &mut Vec<PackageFile>,
+) -> Result<(), PythonError> {
+    let dir_path = join_path(root_path, relative_dir);
+    let mut dir = match DirAccess::open(&GString::from(dir_path.as_str())) {
+        Some(dir) => dir,
+        None => {
+            return Err(PythonError::host(
+                "FileNotFoundError",
+                format!("Could not open directory: {:?}", dir_path),
+            ));
+        }
+    };
+
+    for file_name in dir.get_files().as_slice() {
+        let relative_path = join_path(relative_dir, &file_name.to_string());
+        if !relative_path.ends_with(".py") {
+            continue;
+        }
+
+        let full_path = join_path(root_path, &relative_path);
+        let source = FileAccess::get_file_as_string(&GString::from(full_path.as_str()));
+        if let Some(file) = PackageFile::from_relative_path(
+            package_name,
+            root_path,
+            &relative_path,
+            source.to_string(),
+        ) {
+            files.push(file);
+        }
+    }
+
+    for dir_name in dir.get_directories().as_slice() {
+        let dir_name = dir_name.to_string();
+        if dir_name.starts_with('.') || dir_name == "__pycache__" {
+            continue;
+        }
+        collect_dir(
+            root_path,
+            &join_path(relative_dir, &dir_name),
+            package_name,
+            files,
+        )?;
+    }
+
+    Ok(())
+}
+
+fn join_path(dir: &str, name: &str) -> String {
+    if dir.is_empty() {
+        name.to_owned()
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
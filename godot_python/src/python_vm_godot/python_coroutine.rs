@@ -0,0 +1,267 @@
+use godot::{classes::object::ConnectFlags, prelude::*};
+
+use super::{python_result::PythonResult, GodotPythonVM};
+use crate::python_vm_common::{
+    coroutine::CoroutineStep,
+    object_handles::{HandleId, ObjectHandles},
+    python_error::PythonError,
+};
+
+/// A Python generator or `async def` coroutine the host runs one step at a time,
+/// e.g. with a `resume` per `_process`. Created by `GodotPythonVM.start_coroutine`.
+///
+/// Yielding (or awaiting) `godot.wait_for_signal(signal, node_path)` suspends it
+/// until that signal fires; `node_path` is relative to the signal source, which is
+/// the PythonVM node unless `set_signal_source` is called. In suspending stdin
+/// mode, yielding (or awaiting) `input()` suspends it until a line is pushed.
+#[derive(GodotClass)]
+#[class(base=RefCounted, no_init)]
+pub struct PythonCoroutine {
+    base: Base<RefCounted>,
+    vm: Gd<GodotPythonVM>,
+    /// Kept to release the coroutine even if the VM is busy or gone
+    handles: ObjectHandles,
+    handle: HandleId,
+    signal_source: Option<Gd<Node>>,
+    started: bool,
+    done: bool,
+    /// The node, signal and connection of the signal being waited for
+    waiting_for: Option<(Gd<Node>, StringName, Callable)>,
+    waiting_for_input: bool,
+    /// Arguments of the awaited signal, sent on the next resume
+    signal_args: Option<Variant>,
+}
+
+impl PythonCoroutine {
+    pub(crate) fn new(vm: Gd<GodotPythonVM>, handles: ObjectHandles, handle: HandleId) -> Gd<Self> {
+        Gd::from_init_fn(|base| Self {
+            base,
+            vm,
+            handles,
+            handle,
+            signal_source: None,
+            started: false,
+            done: false,
+            waiting_for: None,
+            waiting_for_input: false,
+            signal_args: None,
+        })
+    }
+
+    fn step(&self, value: Variant) -> Result<(CoroutineStep, Variant), PythonError> {
+        if !self.vm.is_instance_valid() {
+            return Err(PythonError::host(
+                "ReferenceError",
+                "The PythonVM was freed".to_owned(),
+            ));
+        }
+        let vm = self.vm.bind();
+        // `configure_sandbox` replaces the VM's objects, whose handles start over
+        if !self.handles.ptr_eq(vm.common_vm().handles()) {
+            return Err(PythonError::host(
+                "ReferenceError",
+                "The coroutine belongs to a PythonVM that was reset".to_owned(),
+            ));
+        }
+        let value = vm.to_py_object(value);
+        let step = vm.common_vm().resume_handle(self.handle, value)?;
+        let value = match &step {
+            CoroutineStep::Yielded(value) | CoroutineStep::Finished(value) => {
+                vm.to_variant(value.clone())
+            }
+            CoroutineStep::WaitForSignal { .. } | CoroutineStep::WaitForInput { .. } => {
+                Variant::nil()
+            }
+        };
+        Ok((step, value))
+    }
+
+    fn wait_for_signal(&mut self, signal: String, node_path: String) -> Result<(), PythonError> {
+        let source = match &self.signal_source {
+            Some(source) if source.is_instance_valid() => source.clone(),
+            Some(_) => {
+                return Err(PythonError::host(
+                    "ReferenceError",
+                    "The signal source was freed".to_owned(),
+                ))
+            }
+            None => self.vm.clone().upcast::<Node>(),
+        };
+        let node = if node_path.is_empty() {
+            Some(source)
+        } else {
+            source.get_node_or_null(NodePath::from(node_path.as_str()))
+        };
+        let mut node = match node {
+            Some(node) => node,
+            None => {
+                return Err(PythonError::host(
+                    "LookupError",
+                    format!("No node at {:?} to wait for {:?}", node_path, signal),
+                ))
+            }
+        };
+        let signal = StringName::from(signal.as_str());
+        if !node.has_signal(&signal) {
+            return Err(PythonError::host(
+                "LookupError",
+                format!("{} has no signal {:?}", node.get_name(), signal.to_string()),
+            ));
+        }
+
+        let mut this = self.to_gd();
+        let callable = Callable::from_local_fn("resume_after_signal", move |args| {
+            this.bind_mut().on_signal(args);
+            Ok(Variant::nil())
+        });
+        node.connect_ex(&signal, &callable)
+            .flags(ConnectFlags::ONE_SHOT.ord() as u32)
+            .done();
+        self.waiting_for = Some((node, signal, callable));
+        Ok(())
+    }
+
+    fn take_input(&self) -> Option<String> {
+        if !self.vm.is_instance_valid() {
+            return None;
+        }
+        self.vm.bind().common_vm().take_stdin_line()
+    }
+
+    fn on_signal(&mut self, args: &[&Variant]) {
+        self.waiting_for = None;
+        self.signal_args = Some(match args {
+            [] => Variant::nil(),
+            [arg] => (*arg).clone(),
+            args => Variant::from(
+                args.iter()
+                    .map(|arg| (*arg).clone())
+                    .collect::<VariantArray>(),
+            ),
+        });
+    }
+
+    fn resume_step(&mut self, value: Variant) -> Result<Variant, PythonError> {
+        if self.done || self.waiting_for.is_some() {
+            return Ok(Variant::nil());
+        }
+        let value = if self.waiting_for_input {
+            match self.take_input() {
+                Some(line) => {
+                    self.waiting_for_input = false;
+                    Variant::from(line)
+                }
+                None => return Ok(Variant::nil()),
+            }
+        } else {
+            match self.signal_args.take() {
+                Some(signal_args) => signal_args,
+                None if !self.started => Variant::nil(),
+                None => value,
+            }
+        };
+        self.started = true;
+
+        match self.step(value) {
+            Ok((CoroutineStep::Yielded(_), value)) => Ok(value),
+            Ok((CoroutineStep::WaitForSignal { signal, node_path }, _)) => {
+                match self.wait_for_signal(signal, node_path) {
+                    Ok(()) => Ok(Variant::nil()),
+                    Err(error) => {
+                        self.finish();
+                        Err(error)
+                    }
+                }
+            }
+            Ok((CoroutineStep::WaitForInput { .. }, _)) => {
+                self.waiting_for_input = true;
+                Ok(Variant::nil())
+            }
+            Ok((CoroutineStep::Finished(_), value)) => {
+                self.finish();
+                self.base_mut().emit_signal("finished", &[value.clone()]);
+                Ok(value)
+            }
+            Err(error) => {
+                self.finish();
+                Err(error)
+            }
+        }
+    }
+
+    fn finish(&mut self) {
+        if let Some((mut node, signal, callable)) = self.waiting_for.take() {
+            if node.is_instance_valid() && node.is_connected(&signal, &callable) {
+                node.disconnect(&signal, &callable);
+            }
+        }
+        self.signal_args = None;
+        self.waiting_for_input = false;
+        if !self.done {
+            self.done = true;
+            self.handles.release(self.handle);
+        }
+    }
+}
+
+#[godot_api]
+impl PythonCoroutine {
+    /// Emitted once the coroutine returns.
+    #[signal]
+    fn finished(return_value: Variant);
+
+    /// Runs the coroutine to its next `yield`, the result's value is the yielded value.
+    ///
+    /// `value` becomes the result of that `yield`; the first resume always sends
+    /// nil, the one after an awaited signal sends the signal arguments instead, and
+    /// the one after `input()` the pushed line.
+    /// The value is nil while waiting for a signal or input, and the return value
+    /// once finished.
+    #[func]
+    fn resume(&mut self, value: Variant) -> Gd<PythonResult> {
+        let r = self.resume_step(value);
+        if self.vm.is_instance_valid() {
+            self.vm.bind().to_result(r)
+        } else {
+            PythonResult::new(r)
+        }
+    }
+
+    /// The node `wait_for_signal` paths are relative to. Defaults to the PythonVM node.
+    #[func]
+    fn set_signal_source(&mut self, source: Gd<Node>) {
+        self.signal_source = Some(source);
+    }
+
+    /// True once it returned, raised or was cancelled.
+    #[func]
+    fn is_finished(&self) -> bool {
+        self.done
+    }
+
+    #[func]
+    fn is_waiting_for_signal(&self) -> bool {
+        self.waiting_for.is_some()
+    }
+
+    /// True while it waits for a line pushed with `push_stdin`.
+    #[func]
+    fn is_waiting_for_input(&self) -> bool {
+        self.waiting_for_input
+    }
+
+    /// Stops the coroutine without running it further, `finished` is not emitted.
+    #[func]
+    fn cancel(&mut self) {
+        self.finish();
+    }
+}
+
+impl Drop for PythonCoroutine {
+    fn drop(&mut self) {
+        if !self.done {
+            self.done = true;
+            self.handles.release(self.handle);
+        }
+    }
+}
@@ -0,0 +1,347 @@
+use std::{
+    cell::RefCell,
+    fmt,
+    hash::{Hash, Hasher},
+};
+
+use godot::{builtin::RustCallable, prelude::*};
+use rustpython_vm::{
+    function::{FuncArgs, KwArgs},
+    PyObjectRef, VirtualMachine,
+};
+
+use super::{python_result::PythonResult, GodotPythonVM};
+use crate::python_vm_common::{
+    object_handles::{HandleId, ObjectHandles},
+    python_error::PythonError,
+};
+
+/// Bound methods are new objects on every attribute access, so they are told apart
+/// by the ids of `__self__` and `__func__`, anything else by its own id.
+fn callable_key(vm: &VirtualMachine, function: &PyObjectRef) -> (usize, usize) {
+    let part = |name: &str| function.get_attr(name, vm).ok().map(|part| part.get_id());
+    match (part("__self__"), part("__func__")) {
+        (Some(zelf), Some(func)) => (zelf, func),
+        _ => (function.get_id(), 0),
+    }
+}
+
+thread_local! {
+    /// The object of the `PythonCallable` a probe of `PythonObject::from_callable` was
+    /// compared with. Godot hands custom Callables to no one but their `==`.
+    static PROBED: RefCell<Option<Gd<PythonObject>>> = const { RefCell::new(None) };
+}
+
+/// The Callable of `PythonObject::into_callable`. Callables of the same function, or of
+/// the same method of the same object, are equal, so `disconnect` finds a connection
+/// made with another conversion of it.
+struct PythonCallable {
+    /// None for the probes of `PythonObject::from_callable`
+    object: Option<Gd<PythonObject>>,
+    name: String,
+    /// The VM's registry id and the `callable_key` of the function
+    key: (usize, usize, usize),
+}
+
+impl PartialEq for PythonCallable {
+    fn eq(&self, other: &Self) -> bool {
+        match (&self.object, &other.object) {
+            (Some(object), None) | (None, Some(object)) => {
+                PROBED.with(|probed| *probed.borrow_mut() = Some(object.clone()));
+                false
+            }
+            _ => self.key == other.key,
+        }
+    }
+}
+
+impl Hash for PythonCallable {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key.hash(state);
+    }
+}
+
+impl fmt::Display for PythonCallable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+impl RustCallable for PythonCallable {
+    fn invoke(&mut self, args: &[&Variant]) -> Result<Variant, ()> {
+        match &self.object {
+            Some(object) => object.bind().call(args),
+            None => Err(()),
+        }
+    }
+}
+
+/// A Python value without a Variant equivalent, e.g. a class instance, a class
+/// or a generator. The object stays alive until this is freed or `release` is called.
+///
+/// Passing it back into Python (as an argument or attribute value) passes the object itself.
+/// `for item in object` iterates it like Python would.
+#[derive(GodotClass)]
+#[class(base=RefCounted, no_init)]
+pub struct PythonObject {
+    base: Base<RefCounted>,
+    vm: Gd<GodotPythonVM>,
+    /// Kept to release the object even if the VM is busy or gone
+    handles: ObjectHandles,
+    handle: HandleId,
+    released: bool,
+    iterator: Option<HandleId>,
+    current_item: Variant,
+}
+
+impl PythonObject {
+    pub(crate) fn new(vm: Gd<GodotPythonVM>, handles: ObjectHandles, handle: HandleId) -> Gd<Self> {
+        Gd::from_init_fn(|base| Self {
+            base,
+            vm,
+            handles,
+            handle,
+            released: false,
+            iterator: None,
+            current_item: Variant::nil(),
+        })
+    }
+
+    /// The Python object behind `value`, if it is a handle of the VM that owns `handles`
+    /// or a Callable of `into_callable` for one.
+    pub(crate) fn resolve(value: &Variant, handles: &ObjectHandles) -> Option<PyObjectRef> {
+        let object = match value.try_to::<Callable>() {
+            Ok(callable) => Self::from_callable(&callable)?,
+            Err(_) => value.try_to::<Gd<PythonObject>>().ok()?,
+        };
+        let object = object.bind();
+        if object.released || !object.handles.ptr_eq(handles) {
+            return None;
+        }
+        handles.get(object.handle)
+    }
+
+    /// A custom Callable calling `object`, so GDScript can `connect` signals to Python code.
+    /// It keeps the object alive, errors reach the VM's `python_error` signal and fail the call.
+    pub(crate) fn into_callable(vm: &VirtualMachine, object: Gd<Self>, name: &str) -> Callable {
+        let key = {
+            let this = object.bind();
+            let (first, second) = this
+                .handles
+                .get(this.handle)
+                .map_or((0, 0), |function| callable_key(vm, &function));
+            (this.handles.registry_id(), first, second)
+        };
+        Callable::from_custom(PythonCallable {
+            object: Some(object),
+            name: name.to_owned(),
+            key,
+        })
+    }
+
+    /// The object a Callable of `into_callable` calls, None for other Callables.
+    fn from_callable(callable: &Callable) -> Option<Gd<Self>> {
+        let probe = Callable::from_custom(PythonCallable {
+            object: None,
+            name: String::new(),
+            key: (0, 0, 0),
+        });
+        PROBED.with(|probed| probed.borrow_mut().take());
+        // Only calls `PythonCallable::eq` if `callable` is one too
+        let _ = probe == *callable;
+        PROBED.with(|probed| probed.borrow_mut().take())
+    }
+
+    fn call(&self, args: &[&Variant]) -> Result<Variant, ()> {
+        let r = self.with_vm(|vm| {
+            let args = args.iter().map(|arg| vm.to_py_object((*arg).clone()));
+            let f_args = FuncArgs::new(args.collect::<Vec<_>>(), KwArgs::default());
+            let value = vm.common_vm().call_handle(self.handle, f_args)?;
+            Ok(vm.to_variant(value))
+        });
+        match r {
+            Ok(value) => Ok(value),
+            Err(error) => {
+                self.to_result(Err(error));
+                Err(())
+            }
+        }
+    }
+
+    fn with_vm<R>(
+        &self,
+        f: impl FnOnce(&GodotPythonVM) -> Result<R, PythonError>,
+    ) -> Result<R, PythonError> {
+        if !self.vm.is_instance_valid() {
+            return Err(PythonError::host(
+                "ReferenceError",
+                "The PythonVM was freed".to_owned(),
+            ));
+        }
+        let vm = self.vm.bind();
+        // `configure_sandbox` replaces the VM's objects, whose handles start over
+        if !self.handles.ptr_eq(vm.common_vm().handles()) {
+            return Err(PythonError::host(
+                "ReferenceError",
+                "The object belongs to a PythonVM that was reset".to_owned(),
+            ));
+        }
+        f(&vm)
+    }
+
+    /// Wraps `r` like the VM does, so errors also reach its `python_error` signal.
+    fn to_result(&self, r: Result<Variant, PythonError>) -> Gd<PythonResult> {
+        if self.vm.is_instance_valid() {
+            self.vm.bind().to_result(r)
+        } else {
+            PythonResult::new(r)
+        }
+    }
+
+    fn release_iterator(&mut self) {
+        if let Some(iterator) = self.iterator.take() {
+            self.handles.release(iterator);
+        }
+        self.current_item = Variant::nil();
+    }
+
+    /// Moves to the next item of the running `for` loop, returns false once it is done.
+    fn advance_iterator(&mut self) -> bool {
+        let iterator = match self.iterator {
+            Some(iterator) => iterator,
+            None => return false,
+        };
+
+        let item = self.with_vm(|vm| {
+            Ok(vm
+                .common_vm()
+                .next_handle_item(iterator)?
+                .map(|item| vm.to_variant(item)))
+        });
+        match item {
+            Ok(Some(item)) => {
+                self.current_item = item;
+                true
+            }
+            Ok(None) => {
+                self.release_iterator();
+                false
+            }
+            Err(error) => {
+                godot_error!("{}", error);
+                self.release_iterator();
+                false
+            }
+        }
+    }
+}
+
+#[godot_api]
+impl IRefCounted for PythonObject {
+    fn to_string(&self) -> GString {
+        let repr = self
+            .with_vm(|vm| vm.common_vm().handle_repr(self.handle))
+            .unwrap_or_else(|error| error.to_string());
+        GString::from(repr.as_str())
+    }
+}
+
+#[godot_api]
+impl PythonObject {
+    #[func]
+    fn get_attr(&self, name: String) -> Gd<PythonResult> {
+        self.to_result(self.with_vm(|vm| {
+            let value = vm.common_vm().get_handle_attr(self.handle, name)?;
+            Ok(vm.to_variant(value))
+        }))
+    }
+
+    /// The result's value is nil.
+    #[func]
+    fn set_attr(&self, name: String, value: Variant) -> Gd<PythonResult> {
+        self.to_result(self.with_vm(|vm| {
+            let value = vm.to_py_object(value);
+            vm.common_vm().set_handle_attr(self.handle, name, value)?;
+            Ok(Variant::nil())
+        }))
+    }
+
+    /// Calls the object itself, e.g. a function or a class.
+    #[func]
+    fn invoke(&self, args: VariantArray, kwargs: Dictionary) -> Gd<PythonResult> {
+        self.to_result(self.with_vm(|vm| {
+            let f_args = vm.to_func_args(args, kwargs)?;
+            let value = vm.common_vm().call_handle(self.handle, f_args)?;
+            Ok(vm.to_variant(value))
+        }))
+    }
+
+    #[func]
+    fn call_method(
+        &self,
+        method_name: String,
+        args: VariantArray,
+        kwargs: Dictionary,
+    ) -> Gd<PythonResult> {
+        self.to_result(self.with_vm(|vm| {
+            let f_args = vm.to_func_args(args, kwargs)?;
+            let value = vm
+                .common_vm()
+                .call_handle_method(self.handle, method_name, f_args)?;
+            Ok(vm.to_variant(value))
+        }))
+    }
+
+    /// The Python type name, e.g. `Player` or `generator`.
+    #[func]
+    fn get_type_name(&self) -> String {
+        self.with_vm(|vm| vm.common_vm().handle_type_name(self.handle))
+            .unwrap_or_default()
+    }
+
+    /// Lets go of the Python object before this handle is freed.
+    #[func]
+    fn release(&mut self) {
+        self.release_iterator();
+        if !self.released {
+            self.released = true;
+            self.handles.release(self.handle);
+        }
+    }
+
+    #[func]
+    fn is_released(&self) -> bool {
+        self.released
+    }
+
+    #[func]
+    fn _iter_init(&mut self, _state: Variant) -> bool {
+        self.release_iterator();
+        match self.with_vm(|vm| vm.common_vm().iter_handle(self.handle)) {
+            Ok(iterator) => {
+                self.iterator = Some(iterator);
+                self.advance_iterator()
+            }
+            Err(error) => {
+                godot_error!("{}", error);
+                false
+            }
+        }
+    }
+
+    #[func]
+    fn _iter_next(&mut self, _state: Variant) -> bool {
+        self.advance_iterator()
+    }
+
+    #[func]
+    fn _iter_get(&self, _state: Variant) -> Variant {
+        self.current_item.clone()
+    }
+}
+
+impl Drop for PythonObject {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
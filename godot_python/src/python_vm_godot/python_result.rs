@@ -0,0 +1,85 @@
+use godot::prelude::*;
+
+use super::godot_converter::convert_python_error_to_dict;
+use crate::python_vm_common::{output::CapturedOutput, python_error::PythonError};
+
+/// What every call into Python returns. `ok` is false when Python raised, then
+/// `error` holds the error dict (see `convert_python_error_to_dict`) and `value`
+/// is nil. A returned string is always a value, whatever it says.
+#[derive(GodotClass)]
+#[class(base=RefCounted, no_init)]
+pub struct PythonResult {
+    base: Base<RefCounted>,
+    #[var(get)]
+    ok: bool,
+    #[var(get)]
+    value: Variant,
+    #[var(get)]
+    error: Variant,
+    /// What the call printed, only filled by the `_captured` calls
+    #[var(get)]
+    stdout: GString,
+    #[var(get)]
+    stderr: GString,
+}
+
+impl PythonResult {
+    pub(crate) fn new(r: Result<Variant, PythonError>) -> Gd<Self> {
+        Self::with_output(r, &CapturedOutput::default())
+    }
+
+    pub(crate) fn with_output(
+        r: Result<Variant, PythonError>,
+        output: &CapturedOutput,
+    ) -> Gd<Self> {
+        let (ok, value, error) = match r {
+            Ok(value) => (true, value, Variant::nil()),
+            Err(error) => (
+                false,
+                Variant::nil(),
+                Variant::from(convert_python_error_to_dict(&error)),
+            ),
+        };
+        Gd::from_init_fn(|base| Self {
+            base,
+            ok,
+            value,
+            error,
+            stdout: GString::from(output.stdout.as_str()),
+            stderr: GString::from(output.stderr.as_str()),
+        })
+    }
+}
+
+#[godot_api]
+impl IRefCounted for PythonResult {
+    fn to_string(&self) -> GString {
+        let text = if self.ok {
+            format!("PythonResult(ok, {})", self.value)
+        } else {
+            let formatted = self
+                .error
+                .try_to::<Dictionary>()
+                .ok()
+                .and_then(|error| error.get("formatted"))
+                .unwrap_or_default();
+            format!("PythonResult(error, {})", formatted)
+        };
+        GString::from(text.as_str())
+    }
+}
+
+#[godot_api]
+impl PythonResult {
+    /// `{ok, value, error, stdout, stderr}`, e.g. to send the result somewhere as JSON.
+    #[func]
+    fn to_dict(&self) -> Dictionary {
+        let mut dict = Dictionary::new();
+        dict.insert("ok", self.ok);
+        dict.insert("value", self.value.clone());
+        dict.insert("error", self.error.clone());
+        dict.insert("stdout", self.stdout.clone());
+        dict.insert("stderr", self.stderr.clone());
+        dict
+    }
+}
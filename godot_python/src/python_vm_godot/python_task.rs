@@ -0,0 +1,93 @@
+use godot::prelude::*;
+use rustpython_vm::function::FuncArgs;
+
+use super::{python_result::PythonResult, GodotPythonVM};
+use crate::python_vm_common::object_handles::{HandleId, ObjectHandles};
+
+/// An `async def` call running on the PythonVM's event loop, returned by
+/// `call_python_function`. It advances when the VM's `poll` is called, so
+/// `await task.completed` in GDScript waits for the Python coroutine to return.
+#[derive(GodotClass)]
+#[class(base=RefCounted, no_init)]
+pub struct PythonTask {
+    base: Base<RefCounted>,
+    vm: Gd<GodotPythonVM>,
+    /// Kept to release the task even if the VM is busy or gone
+    handles: ObjectHandles,
+    handle: HandleId,
+    result: Option<Gd<PythonResult>>,
+}
+
+impl PythonTask {
+    pub(crate) fn new(vm: Gd<GodotPythonVM>, handles: ObjectHandles, handle: HandleId) -> Gd<Self> {
+        Gd::from_init_fn(|base| Self {
+            base,
+            vm,
+            handles,
+            handle,
+            result: None,
+        })
+    }
+
+    pub(crate) fn handle(&self) -> HandleId {
+        self.handle
+    }
+
+    pub(crate) fn result(&self) -> Option<Gd<PythonResult>> {
+        self.result.clone()
+    }
+
+    /// Stores the outcome, the VM emits `completed` after.
+    pub(crate) fn set_result(&mut self, result: Gd<PythonResult>) {
+        self.result = Some(result);
+    }
+}
+
+#[godot_api]
+impl PythonTask {
+    /// Emitted from the VM's `poll` once the coroutine returned, raised, or was
+    /// cancelled (with a `CancelledError`).
+    #[signal]
+    fn completed(result: Gd<PythonResult>);
+
+    #[func]
+    fn is_done(&self) -> bool {
+        self.result.is_some()
+    }
+
+    /// The `PythonResult`, or nil while it is still running.
+    #[func]
+    fn get_result(&self) -> Variant {
+        match &self.result {
+            Some(result) => Variant::from(result.clone()),
+            None => Variant::nil(),
+        }
+    }
+
+    /// Asks the task to stop, it completes with a `CancelledError` on the next poll.
+    #[func]
+    fn cancel(&self) {
+        if self.result.is_some() || !self.vm.is_instance_valid() {
+            return;
+        }
+        let vm = self.vm.bind();
+        // Tasks from before `configure_sandbox` are cancelled already
+        if !self.handles.ptr_eq(vm.common_vm().handles()) {
+            return;
+        }
+        let r = vm.common_vm().call_handle_method(
+            self.handle,
+            "cancel".to_string(),
+            FuncArgs::default(),
+        );
+        if let Err(error) = r {
+            godot_error!("{}", error);
+        }
+    }
+}
+
+impl Drop for PythonTask {
+    fn drop(&mut self) {
+        self.handles.release(self.handle);
+    }
+}
@@ -1,14 +1,32 @@
+mod js_module_source;
+mod js_output;
+mod python_object;
 mod wasm_converter;
 
-use std::panic;
+use std::{cell::RefCell, panic, rc::Rc};
 
-use js_sys::{Array, Object, Reflect, WebAssembly::RuntimeError};
-use rustpython_vm::function::FuncArgs;
+use js_module_source::JsCallbackSource;
+use js_output::{convert_js_to_markup, function_output_sink};
+use js_sys::{Array, Function, Object, Promise, Reflect, WebAssembly::RuntimeError};
+use python_object::{convert_js_to_func_args, convert_py_to_js_with_handles};
+use rustpython_vm::{function::FuncArgs, PyObjectRef};
 use wasm_bindgen::prelude::*;
-use wasm_converter::{convert_js_arr_to_args, convert_js_obj_to_kwargs, convert_py_to_js_common};
+use wasm_converter::{
+    convert_js_obj_to_sandbox_policy, convert_members_to_js, convert_python_error_to_js,
+    convert_reload_diff_to_js, convert_result_to_js, convert_result_with_output_to_js,
+    convert_signature_to_js, object_entries,
+};
 use web_sys::console;
 
-use crate::python_vm_common::CommonPythonVM;
+use crate::python_vm_common::{
+    execution_budget::ExecutionBudget,
+    module_source::ZipSource,
+    object_handles::HandleId,
+    output::{BufferMode, OutputStream},
+    package_loader::PackageFile,
+    stdin_mode::StdinMode,
+    CommonPythonVM,
+};
 
 /// Sets error info on the window object, and prints the backtrace to console
 pub fn panic_hook(info: &panic::PanicInfo) {
@@ -40,7 +58,50 @@ pub fn _setup_console_error() {
 
 #[wasm_bindgen(js_name = WasmPythonVM)]
 pub struct WasmPythonVM {
-    common_vm: CommonPythonVM,
+    /// Shared with the `PythonObject` handles this VM hands out
+    common_vm: Rc<RefCell<CommonPythonVM>>,
+    /// Tasks of `async def` calls that did not complete yet, with their promise's resolve and reject
+    tasks: RefCell<Vec<(HandleId, Function, Function)>>,
+}
+
+impl WasmPythonVM {
+    fn new(common_vm: CommonPythonVM) -> Self {
+        Self {
+            common_vm: Rc::new(RefCell::new(common_vm)),
+            tasks: RefCell::new(Vec::new()),
+        }
+    }
+
+    fn to_js(&self, value: PyObjectRef) -> JsValue {
+        convert_py_to_js_with_handles(&self.common_vm, value)
+    }
+
+    /// Like `to_js`, but turns the task of an `async def` call into a `Promise` `poll` settles.
+    fn to_js_or_task(&self, value: PyObjectRef) -> JsValue {
+        if !self.common_vm.borrow().is_task(&value) {
+            return self.to_js(value);
+        }
+        let handle = self.common_vm.borrow().register_handle(value);
+        let promise = Promise::new(&mut |resolve, reject| {
+            self.tasks.borrow_mut().push((handle, resolve, reject));
+        });
+        promise.into()
+    }
+
+    fn output_function(callable: JsValue) -> Option<Function> {
+        match callable.dyn_into::<Function>() {
+            Ok(f) => Some(f),
+            Err(e) => {
+                console::log_1(&"Error getting function:".into());
+                console::log_1(&e);
+                None
+            }
+        }
+    }
+
+    fn to_func_args(&self, args: Array, kwargs: Object) -> FuncArgs {
+        convert_js_to_func_args(&self.common_vm.borrow(), args, kwargs)
+    }
 }
 
 #[wasm_bindgen(js_class = WasmPythonVM)]
@@ -49,56 +110,327 @@ impl WasmPythonVM {
     pub fn init() -> Self {
         let common_vm = CommonPythonVM::init();
 
-        Self { common_vm }
+        Self::new(common_vm)
+    }
+
+    /// Creates a VM whose user code is restricted by `policy`, see `convert_js_obj_to_sandbox_policy`.
+    #[wasm_bindgen]
+    pub fn with_sandbox(policy: Object) -> Self {
+        let common_vm = CommonPythonVM::init_with_policy(convert_js_obj_to_sandbox_policy(&policy));
+
+        Self::new(common_vm)
     }
 
+    /// Sends `sys.stdout` to `callable(formatted, chunk)`, where chunk is
+    /// `{stream, text, formatted, source, timestamp_msec}`.
     #[wasm_bindgen]
     pub fn setup_stdout(&mut self, callable: JsValue) {
-        // self.godot_stdout = Some(godot_out);
+        if let Some(function) = Self::output_function(callable) {
+            self.common_vm
+                .borrow_mut()
+                .setup_stdout(function_output_sink(function));
+        }
+    }
 
-        let function_r = callable.dyn_into::<js_sys::Function>();
+    /// Sends `sys.stderr` to `callable(formatted, chunk)`, like `setup_stdout`.
+    #[wasm_bindgen]
+    pub fn setup_stderr(&mut self, callable: JsValue) {
+        if let Some(function) = Self::output_function(callable) {
+            self.common_vm
+                .borrow_mut()
+                .setup_stderr(function_output_sink(function));
+        }
+    }
 
-        let function = match function_r {
-            Ok(f) => f,
+    /// How output is formatted: null (the default) leaves text as is, `"bbcode"` colors
+    /// stderr red, and a function gets each chunk object and returns the text to show.
+    #[wasm_bindgen]
+    pub fn set_output_markup(&mut self, markup: JsValue) {
+        match convert_js_to_markup(markup) {
+            Ok(markup) => self.common_vm.borrow_mut().set_output_markup(markup),
             Err(e) => {
-                console::log_1(&"Error getting function:".into());
+                console::log_1(&"Unknown output markup:".into());
                 console::log_1(&e);
-                return;
             }
-        };
+        }
+    }
 
-        let clo = move |s: String| {
-            let r = function.call1(&JsValue::NULL, &s.as_str().into());
-            match r {
-                Ok(_) => {}
-                Err(e) => {
-                    console::log_1(&"Error calling function:".into());
-                    console::log_1(&e);
-                }
+    /// When `stream` (`"stdout"` or `"stderr"`) hands output over: `"line"` (the
+    /// default), `"unbuffered"` or `"full"`. Every eval and call flushes when it ends.
+    #[wasm_bindgen]
+    pub fn set_output_buffering(&mut self, stream: String, mode: String) {
+        match (
+            OutputStream::from_name(&stream),
+            BufferMode::from_name(&mode),
+        ) {
+            (Some(stream), Some(mode)) => self
+                .common_vm
+                .borrow_mut()
+                .set_output_buffering(stream, mode),
+            _ => console::log_1(&format!("Unknown output buffering: {} {}", stream, mode).into()),
+        }
+    }
+
+    /// Routes `input()` and `sys.stdin` to the host, see `CommonPythonVM::setup_stdin`.
+    /// `request_line(prompt)` is called when Python needs a line that was not pushed
+    /// yet, and may return one. When it returns nothing, `input()` raises `EOFError`,
+    /// or with `suspending` returns an awaitable that waits for `push_stdin`.
+    #[wasm_bindgen]
+    pub fn setup_stdin(&mut self, request_line: Function, suspending: bool) {
+        let clo = move |prompt: String| match request_line.call1(&JsValue::NULL, &prompt.into()) {
+            Ok(line) => line.as_string(),
+            Err(e) => {
+                console::log_1(&"Error calling function:".into());
+                console::log_1(&e);
+                None
             }
         };
 
-        self.common_vm.setup_stdout(clo);
+        self.common_vm
+            .borrow_mut()
+            .setup_stdin(clo, StdinMode::from_suspending(suspending));
+    }
+
+    /// Queues a line for `input()`, e.g. when the user submits a terminal prompt.
+    #[wasm_bindgen]
+    pub fn push_stdin(&self, line: String) {
+        self.common_vm.borrow().push_stdin(line);
+    }
+
+    /// Limits every later call. Values of zero or less disable that limit.
+    #[wasm_bindgen]
+    pub fn set_execution_budget(&mut self, max_instructions: f64, timeout_msec: f64) {
+        self.common_vm
+            .borrow_mut()
+            .set_execution_budget(ExecutionBudget::from_limits(
+                max_instructions as i64,
+                timeout_msec as i64,
+            ));
+    }
+
+    #[wasm_bindgen]
+    pub fn eval_with_budget(
+        &self,
+        code: String,
+        max_instructions: f64,
+        timeout_msec: f64,
+    ) -> JsValue {
+        let budget = ExecutionBudget::from_limits(max_instructions as i64, timeout_msec as i64);
+        self.common_vm
+            .borrow()
+            .with_execution_budget(budget, |_| self.eval(code))
+    }
+
+    #[wasm_bindgen]
+    pub fn call_python_function_with_budget(
+        &mut self,
+        module_name: String,
+        function_name: String,
+        args: Array,
+        kwargs: Object,
+        max_instructions: f64,
+        timeout_msec: f64,
+    ) -> JsValue {
+        let budget = ExecutionBudget::from_limits(max_instructions as i64, timeout_msec as i64);
+        let f_args = self.to_func_args(args, kwargs);
+        let r = self
+            .common_vm
+            .borrow()
+            .with_execution_budget(budget, |common_vm| {
+                common_vm.call_python_function(module_name, function_name, f_args)
+            });
+
+        convert_result_to_js(r.map(|value| self.to_js_or_task(value)))
     }
 
     #[wasm_bindgen]
     pub fn eval(&self, code: String) -> JsValue {
-        let r = self.common_vm.eval(code);
+        let r = self.common_vm.borrow().eval(code);
 
-        match r {
-            Ok(value) => convert_py_to_js_common(&self.common_vm, value),
-            Err(error) => JsValue::from(format!("Error: {:?}", error)),
-        }
+        convert_result_to_js(r.map(|value| self.to_js(value)))
+    }
+
+    /// Like `eval`, but fills the result's `stdout` and `stderr` with what the code
+    /// printed. That output is not passed to `setup_stdout` or `setup_stderr`.
+    #[wasm_bindgen]
+    pub fn eval_captured(&self, code: String) -> JsValue {
+        let (r, output) = self
+            .common_vm
+            .borrow()
+            .with_captured_output(|common_vm| common_vm.eval(code));
+        convert_result_with_output_to_js(r.map(|value| self.to_js(value)), &output)
+    }
+
+    #[wasm_bindgen]
+    pub fn create_session(&mut self, session_name: String) {
+        self.common_vm.borrow_mut().create_session(session_name);
+    }
+
+    #[wasm_bindgen]
+    pub fn eval_in_session(&self, session_name: String, code: String) -> JsValue {
+        let r = self.common_vm.borrow().eval_in_session(session_name, code);
+
+        convert_result_to_js(r.map(|value| self.to_js(value)))
+    }
+
+    #[wasm_bindgen]
+    pub fn get_session_globals(&self, session_name: String) -> JsValue {
+        let r = self.common_vm.borrow().get_session_globals(session_name);
+
+        convert_result_to_js(r.map(|value| self.to_js(value)))
     }
 
     #[wasm_bindgen]
-    pub fn load_module(&mut self, module_name: String, module_code: String) -> JsValue {
-        let r = self.common_vm.load_module(module_name.clone(), module_code);
+    pub fn clear_session(&mut self, session_name: String) -> bool {
+        self.common_vm.borrow_mut().clear_session(session_name)
+    }
+
+    #[wasm_bindgen]
+    pub fn drop_session(&mut self, session_name: String) -> bool {
+        self.common_vm.borrow_mut().drop_session(session_name)
+    }
 
-        match r {
-            Ok(_) => JsValue::from("Success"),
-            Err(error) => JsValue::from(format!("Error: {:?}", error)),
+    #[wasm_bindgen]
+    pub fn list_sessions(&self) -> Array {
+        self.common_vm
+            .borrow()
+            .list_sessions()
+            .iter()
+            .map(|name| JsValue::from_str(name))
+            .collect()
+    }
+
+    /// The result's value is undefined.
+    #[wasm_bindgen]
+    pub fn load_module(&self, module_name: String, module_code: String) -> JsValue {
+        let r = self
+            .common_vm
+            .borrow()
+            .load_module(module_name, module_code);
+        convert_result_to_js(r.map(|_| JsValue::UNDEFINED))
+    }
+
+    /// Re-executes a loaded module, see `CommonPythonVM::reload_module`.
+    /// The result's value is `{added, removed, changed, persisted, updated_modules}`.
+    #[wasm_bindgen]
+    pub fn reload_module(&self, module_name: String, module_code: String) -> JsValue {
+        let r = self
+            .common_vm
+            .borrow()
+            .reload_module(module_name, module_code);
+        convert_result_to_js(r.map(|diff| convert_reload_diff_to_js(&diff)))
+    }
+
+    /// Loads a package from an object mapping paths inside the package to sources,
+    /// e.g. `{"__init__.py": "...", "ai/brain.py": "..."}`.
+    #[wasm_bindgen]
+    pub fn load_package(&self, package_name: String, files: Object) -> JsValue {
+        let files = object_entries(&files)
+            .filter_map(|pair| pair.ok())
+            .filter_map(|(path, source)| {
+                PackageFile::from_relative_path(
+                    &package_name,
+                    &package_name,
+                    &path.as_string()?,
+                    source.as_string()?,
+                )
+            })
+            .collect();
+        let r = self.common_vm.borrow().load_package(package_name, files);
+        convert_result_to_js(r.map(|_| JsValue::UNDEFINED))
+    }
+
+    /// Lets `import` ask `callback(module_name)` for modules on demand, see `JsCallbackSource`.
+    /// Sources added later are searched first, adding one under an existing name replaces it.
+    #[wasm_bindgen]
+    pub fn add_module_source_callback(&mut self, name: String, callback: Function) {
+        self.common_vm.borrow_mut().add_module_source(
+            name.clone(),
+            Box::new(JsCallbackSource::new(name, callback)),
+        );
+    }
+
+    /// Lets `import` find the `.py` files of a zip archive, e.g. fetched as a `Uint8Array`.
+    #[wasm_bindgen]
+    pub fn add_module_source_zip(&mut self, name: String, archive: Vec<u8>) -> JsValue {
+        let r = ZipSource::new(archive, &name).map(|source| {
+            self.common_vm
+                .borrow_mut()
+                .add_module_source(name, Box::new(source));
+            JsValue::UNDEFINED
+        });
+        convert_result_to_js(r)
+    }
+
+    #[wasm_bindgen]
+    pub fn remove_module_source(&mut self, name: String) -> bool {
+        self.common_vm.borrow_mut().remove_module_source(&name)
+    }
+
+    /// Names of the modules loaded with `load_module` or `load_package`.
+    #[wasm_bindgen]
+    pub fn list_modules(&self) -> Array {
+        self.common_vm
+            .borrow()
+            .list_modules()
+            .iter()
+            .map(|name| JsValue::from_str(name))
+            .collect()
+    }
+
+    /// The result's value is `[{name, kind, type}]` for the public globals of a module.
+    /// `kind` is one of "function", "class", "module" or "value".
+    #[wasm_bindgen]
+    pub fn list_members(&self, module_name: String) -> JsValue {
+        let r = self.common_vm.borrow().list_members(module_name);
+        convert_result_to_js(r.map(|members| JsValue::from(convert_members_to_js(&members))))
+    }
+
+    /// The result's value is `{name, parameters, return_annotation, doc}`.
+    /// Each parameter is `{name, kind, has_default, default, annotation}`.
+    #[wasm_bindgen]
+    pub fn get_signature(&self, module_name: String, function_name: String) -> JsValue {
+        let r = self
+            .common_vm
+            .borrow()
+            .get_signature(module_name, function_name);
+        convert_result_to_js(
+            r.map(|signature| convert_signature_to_js(&self.common_vm.borrow(), &signature)),
+        )
+    }
+
+    /// Advances Python's event loop by `delta` seconds, e.g. from `requestAnimationFrame`.
+    /// `asyncio.sleep` waits for the time passed here, and the promises of finished
+    /// `async def` calls settle, with the value or the error object. The result's value
+    /// is undefined.
+    #[wasm_bindgen]
+    pub fn poll(&self, delta: f64) -> JsValue {
+        if let Err(error) = self.common_vm.borrow().poll(delta) {
+            return convert_result_to_js(Err(error));
         }
+
+        let tasks = self.tasks.take();
+        let mut pending = Vec::new();
+        for (handle, resolve, reject) in tasks {
+            let result = self.common_vm.borrow().task_result(handle);
+            let settled = match result {
+                None => {
+                    pending.push((handle, resolve, reject));
+                    continue;
+                }
+                Some(Ok(value)) => resolve.call1(&JsValue::NULL, &self.to_js(value)),
+                Some(Err(error)) => {
+                    reject.call1(&JsValue::NULL, &convert_python_error_to_js(&error))
+                }
+            };
+            if let Err(e) = settled {
+                console::log_1(&"Error settling task:".into());
+                console::log_1(&e);
+            }
+            self.common_vm.borrow().release_handle(handle);
+        }
+        self.tasks.borrow_mut().splice(0..0, pending);
+        convert_result_to_js(Ok(JsValue::UNDEFINED))
     }
 
     #[wasm_bindgen]
@@ -109,17 +441,30 @@ impl WasmPythonVM {
         args: Array,
         kwargs: Object,
     ) -> JsValue {
-        let f_args = FuncArgs::new(
-            convert_js_arr_to_args(&self.common_vm, args),
-            convert_js_obj_to_kwargs(&self.common_vm, kwargs),
-        );
+        let f_args = self.to_func_args(args, kwargs);
         let r = self
             .common_vm
+            .borrow()
             .call_python_function(module_name, function_name, f_args);
 
-        match r {
-            Ok(value) => convert_py_to_js_common(&self.common_vm, value),
-            Err(error) => JsValue::from(format!("Error: {:?}", error)),
-        }
+        convert_result_to_js(r.map(|value| self.to_js_or_task(value)))
+    }
+
+    /// Like `call_python_function`, but fills the result's `stdout` and `stderr` with
+    /// what the call printed. For `async def` functions the value is the promise; their
+    /// body only runs when `poll` steps the task, so none of it is captured.
+    #[wasm_bindgen]
+    pub fn call_python_function_captured(
+        &mut self,
+        module_name: String,
+        function_name: String,
+        args: Array,
+        kwargs: Object,
+    ) -> JsValue {
+        let f_args = self.to_func_args(args, kwargs);
+        let (r, output) = self.common_vm.borrow().with_captured_output(|common_vm| {
+            common_vm.call_python_function(module_name, function_name, f_args)
+        });
+        convert_result_with_output_to_js(r.map(|value| self.to_js_or_task(value)), &output)
     }
 }
@@ -0,0 +1,53 @@
+use js_sys::{Function, Reflect};
+use wasm_bindgen::JsValue;
+
+use crate::python_vm_common::{module_source::ModuleSource, package_loader::PackageFile};
+
+/// Asks a JS function for modules when they are imported.
+///
+/// The callback gets the dotted module name and returns either the source as a
+/// string, an object `{source, path, is_package}`, or `null` if it has no such module.
+pub struct JsCallbackSource {
+    name: String,
+    callback: Function,
+}
+
+impl JsCallbackSource {
+    pub fn new(name: String, callback: Function) -> Self {
+        Self { name, callback }
+    }
+}
+
+impl ModuleSource for JsCallbackSource {
+    fn find_module(&self, module_name: &str) -> Option<PackageFile> {
+        let found = self
+            .callback
+            .call1(&JsValue::NULL, &JsValue::from(module_name))
+            .ok()?;
+        if found.is_null() || found.is_undefined() {
+            return None;
+        }
+
+        let default_path = format!("{}/{}.py", self.name, module_name.replace('.', "/"));
+        if let Some(source) = found.as_string() {
+            return Some(PackageFile {
+                module_name: module_name.to_owned(),
+                path: default_path,
+                source,
+                is_package: false,
+            });
+        }
+
+        let field = |key: &str| Reflect::get(&found, &JsValue::from(key)).ok();
+        Some(PackageFile {
+            module_name: module_name.to_owned(),
+            path: field("path")
+                .and_then(|path| path.as_string())
+                .unwrap_or(default_path),
+            source: field("source")?.as_string()?,
+            is_package: field("is_package")
+                .and_then(|is_package| is_package.as_bool())
+                .unwrap_or(false),
+        })
+    }
+}
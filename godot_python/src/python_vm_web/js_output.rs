@@ -0,0 +1,49 @@
+use js_sys::Function;
+use wasm_bindgen::prelude::*;
+use web_sys::console;
+
+use super::wasm_converter::convert_output_chunk_to_js;
+use crate::python_vm_common::output::{BBCodeMarkup, OutputChunk, OutputMarkup};
+
+/// Calls `function(formatted, chunk)` for each chunk, with chunk
+/// `{stream, text, formatted, source, timestamp_msec}`.
+pub fn function_output_sink(function: Function) -> impl Fn(OutputChunk) {
+    move |chunk: OutputChunk| {
+        let r = function.call2(
+            &JsValue::NULL,
+            &chunk.formatted.as_str().into(),
+            &convert_output_chunk_to_js(&chunk),
+        );
+        if let Err(e) = r {
+            console::log_1(&"Error calling function:".into());
+            console::log_1(&e);
+        }
+    }
+}
+
+/// Markup computed in JS: the function gets the chunk object and returns the text to show.
+pub struct FunctionMarkup(pub Function);
+
+impl OutputMarkup for FunctionMarkup {
+    fn apply(&self, chunk: &OutputChunk) -> String {
+        self.0
+            .call1(&JsValue::NULL, &convert_output_chunk_to_js(chunk))
+            .ok()
+            .and_then(|text| text.as_string())
+            .unwrap_or_else(|| chunk.text.clone())
+    }
+}
+
+/// null or undefined for no markup, `"bbcode"` for `BBCodeMarkup`, or a function for `FunctionMarkup`.
+pub fn convert_js_to_markup(markup: JsValue) -> Result<Option<Box<dyn OutputMarkup>>, JsValue> {
+    if markup.is_null() || markup.is_undefined() {
+        return Ok(None);
+    }
+    if let Some(function) = markup.dyn_ref::<Function>() {
+        return Ok(Some(Box::new(FunctionMarkup(function.clone()))));
+    }
+    match markup.as_string().as_deref() {
+        Some("bbcode") => Ok(Some(Box::new(BBCodeMarkup::default()))),
+        _ => Err(markup),
+    }
+}
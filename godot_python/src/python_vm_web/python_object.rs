@@ -0,0 +1,271 @@
+use std::{cell::RefCell, rc::Rc};
+
+use js_sys::{Array, Function, Object, Reflect};
+use rustpython_vm::{function::FuncArgs, PyObjectRef};
+use wasm_bindgen::{prelude::*, JsCast};
+
+use super::wasm_converter::{
+    convert_js_arr_to_args, convert_js_obj_to_kwargs, convert_js_to_py_with, convert_py_to_js_with,
+    convert_python_error_to_js, convert_result_to_js,
+};
+use crate::python_vm_common::{
+    object_handles::{HandleId, ObjectHandles},
+    python_converter::py_function_name,
+    python_error::PythonError,
+    CommonPythonVM,
+};
+
+const HANDLE_KEY: &str = "__python_handle__";
+const REGISTRY_KEY: &str = "__python_registry__";
+
+/// Converts a Python value, wrapping values without a JS equivalent in a `PythonObject`.
+/// Functions and bound methods become a JS function, see `convert_py_function_to_js`.
+pub fn convert_py_to_js_with_handles(
+    common_vm: &Rc<RefCell<CommonPythonVM>>,
+    value: PyObjectRef,
+) -> JsValue {
+    let borrowed = common_vm.borrow();
+    let handles = borrowed.handles();
+    borrowed.interpreter.enter(|vm| {
+        convert_py_to_js_with(vm, value, &|object| {
+            let function_name = py_function_name(vm, &object);
+            let handle = handles.register(object);
+            match function_name {
+                Some(name) => convert_py_function_to_js(common_vm, handles, handle, &name),
+                None => JsValue::from(WasmPythonObject {
+                    common_vm: common_vm.clone(),
+                    handles: handles.clone(),
+                    handle,
+                    released: false,
+                }),
+            }
+        })
+    })
+}
+
+/// Releases the handle of a function handed to JS once the JS garbage collector
+/// frees the closure holding it.
+struct FunctionHandle {
+    handles: ObjectHandles,
+    handle: HandleId,
+}
+
+impl Drop for FunctionHandle {
+    fn drop(&mut self) {
+        self.handles.release(self.handle);
+    }
+}
+
+/// A JS function calling the Python function behind `handle`, throwing the error
+/// object if Python raised. Keyword arguments can't be passed.
+///
+/// The JS garbage collector owns it and releases the handle when it collects it,
+/// which needs the build to enable wasm-bindgen's weak references (see `web_publish.sh`).
+/// It carries the `PythonObject` keys, so passing it back into Python passes the function itself.
+fn convert_py_function_to_js(
+    common_vm: &Rc<RefCell<CommonPythonVM>>,
+    handles: &ObjectHandles,
+    handle: HandleId,
+    name: &str,
+) -> JsValue {
+    let function_handle = FunctionHandle {
+        handles: handles.clone(),
+        handle,
+    };
+    let common_vm = common_vm.clone();
+    let call = Closure::<dyn FnMut(Array) -> Result<JsValue, JsValue>>::new(move |args: Array| {
+        let r = match common_vm.try_borrow() {
+            Ok(borrowed) => {
+                let f_args = convert_js_to_func_args(&borrowed, args, Object::new());
+                borrowed.call_handle(function_handle.handle, f_args)
+            }
+            Err(_) => Err(PythonError::host(
+                "RuntimeError",
+                "The PythonVM is busy".to_owned(),
+            )),
+        };
+        match r {
+            Ok(value) => Ok(convert_py_to_js_with_handles(&common_vm, value)),
+            Err(error) => Err(convert_python_error_to_js(&error)),
+        }
+    });
+
+    // Closures take a fixed number of arguments, this spreads them into an array
+    let function =
+        Function::new_with_args("call", "return function(...args) { return call(args); }")
+            .call1(&JsValue::UNDEFINED, &call.into_js_value())
+            .expect("wrapper to be created");
+    let descriptor = Object::new();
+    let _ = Reflect::set(&descriptor, &"value".into(), &name.into());
+    Object::define_property(function.unchecked_ref(), &"name".into(), &descriptor);
+    let _ = Reflect::set(&function, &HANDLE_KEY.into(), &(handle as f64).into());
+    let _ = Reflect::set(
+        &function,
+        &REGISTRY_KEY.into(),
+        &(handles.registry_id() as f64).into(),
+    );
+    function
+}
+
+/// The Python object behind `value`, if it is a `PythonObject` of the VM that owns `handles`.
+fn resolve_handle(value: &JsValue, handles: &ObjectHandles) -> Option<PyObjectRef> {
+    let registry = Reflect::get(value, &REGISTRY_KEY.into()).ok()?.as_f64()?;
+    if registry as usize != handles.registry_id() {
+        return None;
+    }
+    let handle = Reflect::get(value, &HANDLE_KEY.into()).ok()?.as_f64()?;
+    handles.get(handle as HandleId)
+}
+
+/// Converts call arguments, unwrapping `PythonObject`s into the object they hold.
+pub fn convert_js_to_func_args(
+    common_vm: &CommonPythonVM,
+    args: Array,
+    kwargs: Object,
+) -> FuncArgs {
+    let handles = common_vm.handles();
+    let resolve_object = |value: &JsValue| resolve_handle(value, handles);
+    FuncArgs::new(
+        convert_js_arr_to_args(common_vm, args, &resolve_object),
+        convert_js_obj_to_kwargs(common_vm, kwargs, &resolve_object),
+    )
+}
+
+/// A Python value without a JS equivalent, e.g. a class instance, a class or
+/// a generator. The object stays alive until `release` or `free` is called.
+///
+/// Passing it back into Python (as an argument or attribute value) passes the object itself.
+#[wasm_bindgen(js_name = PythonObject)]
+pub struct WasmPythonObject {
+    common_vm: Rc<RefCell<CommonPythonVM>>,
+    /// Kept to release the object even while the VM is busy
+    handles: ObjectHandles,
+    handle: HandleId,
+    released: bool,
+}
+
+impl WasmPythonObject {
+    fn to_result_js(&self, r: Result<PyObjectRef, PythonError>) -> JsValue {
+        convert_result_to_js(r.map(|value| convert_py_to_js_with_handles(&self.common_vm, value)))
+    }
+}
+
+#[wasm_bindgen(js_class = PythonObject)]
+impl WasmPythonObject {
+    #[wasm_bindgen(getter = __python_handle__)]
+    pub fn python_handle(&self) -> f64 {
+        self.handle as f64
+    }
+
+    #[wasm_bindgen(getter = __python_registry__)]
+    pub fn python_registry(&self) -> f64 {
+        self.handles.registry_id() as f64
+    }
+
+    #[wasm_bindgen]
+    pub fn get_attr(&self, name: String) -> JsValue {
+        let r = self.common_vm.borrow().get_handle_attr(self.handle, name);
+        self.to_result_js(r)
+    }
+
+    /// The result's value is undefined.
+    #[wasm_bindgen]
+    pub fn set_attr(&self, name: String, value: JsValue) -> JsValue {
+        let common_vm = self.common_vm.borrow();
+        let value = common_vm.interpreter.enter(|vm| {
+            convert_js_to_py_with(vm, value, &|object| resolve_handle(object, &self.handles))
+        });
+
+        let r = common_vm.set_handle_attr(self.handle, name, value);
+        convert_result_to_js(r.map(|()| JsValue::UNDEFINED))
+    }
+
+    /// Calls the object itself, e.g. a function or a class.
+    #[wasm_bindgen]
+    pub fn invoke(&self, args: Array, kwargs: Object) -> JsValue {
+        let r = {
+            let common_vm = self.common_vm.borrow();
+            let f_args = convert_js_to_func_args(&common_vm, args, kwargs);
+            common_vm.call_handle(self.handle, f_args)
+        };
+        self.to_result_js(r)
+    }
+
+    #[wasm_bindgen]
+    pub fn call_method(&self, method_name: String, args: Array, kwargs: Object) -> JsValue {
+        let r = {
+            let common_vm = self.common_vm.borrow();
+            let f_args = convert_js_to_func_args(&common_vm, args, kwargs);
+            common_vm.call_handle_method(self.handle, method_name, f_args)
+        };
+        self.to_result_js(r)
+    }
+
+    /// Returns a `PythonObject` for `iter(object)`, whose `next()` follows the JS
+    /// iterator protocol, so `{[Symbol.iterator]: () => object.iter()}` works with `for...of`.
+    #[wasm_bindgen]
+    pub fn iter(&self) -> JsValue {
+        match self.common_vm.borrow().iter_handle(self.handle) {
+            Ok(iterator) => JsValue::from(WasmPythonObject {
+                common_vm: self.common_vm.clone(),
+                handles: self.handles.clone(),
+                handle: iterator,
+                released: false,
+            }),
+            Err(error) => convert_python_error_to_js(&error),
+        }
+    }
+
+    /// Returns `{value, done}`, or an error object.
+    #[wasm_bindgen]
+    pub fn next(&self) -> JsValue {
+        let r = self.common_vm.borrow().next_handle_item(self.handle);
+        let (value, done) = match r {
+            Ok(Some(item)) => (convert_py_to_js_with_handles(&self.common_vm, item), false),
+            Ok(None) => (JsValue::UNDEFINED, true),
+            Err(error) => return convert_python_error_to_js(&error),
+        };
+
+        let js_obj = Object::new();
+        let _ = Reflect::set(&js_obj, &"value".into(), &value);
+        let _ = Reflect::set(&js_obj, &"done".into(), &done.into());
+        JsValue::from(js_obj)
+    }
+
+    /// The Python type name, e.g. `Player` or `generator`.
+    #[wasm_bindgen]
+    pub fn type_name(&self) -> String {
+        self.common_vm
+            .borrow()
+            .handle_type_name(self.handle)
+            .unwrap_or_default()
+    }
+
+    #[wasm_bindgen(js_name = toString)]
+    pub fn repr(&self) -> String {
+        self.common_vm
+            .borrow()
+            .handle_repr(self.handle)
+            .unwrap_or_else(|error| error.to_string())
+    }
+
+    /// Lets go of the Python object. `free()` does the same and also frees this wrapper.
+    #[wasm_bindgen]
+    pub fn release(&mut self) {
+        if !self.released {
+            self.released = true;
+            self.handles.release(self.handle);
+        }
+    }
+
+    #[wasm_bindgen]
+    pub fn is_released(&self) -> bool {
+        self.released
+    }
+}
+
+impl Drop for WasmPythonObject {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
@@ -1,13 +1,20 @@
 use indexmap::IndexMap;
 use js_sys::{Array, ArrayBuffer, BigInt, Object, Reflect, Uint8Array};
 use rustpython_vm::{
-    builtins::{PyBaseExceptionRef, PyDict, PyList},
+    builtins::{PyBaseExceptionRef, PyDict, PyList, PyTuple},
     function::{FuncArgs, KwArgs},
     PyObjectRef, PyResult, TryFromObject, VirtualMachine,
 };
 use wasm_bindgen::{prelude::*, JsCast};
 
-use crate::python_vm_common::CommonPythonVM;
+use crate::python_vm_common::{
+    introspection::{FunctionSignature, ModuleMember},
+    module_reloader::ReloadDiff,
+    output::{CapturedOutput, OutputChunk},
+    python_error::PythonError,
+    sandbox_policy::SandboxPolicy,
+    CommonPythonVM,
+};
 
 pub fn convert_py_to_js_common(common_vm: &CommonPythonVM, value: PyObjectRef) -> JsValue {
     common_vm
@@ -15,7 +22,18 @@ pub fn convert_py_to_js_common(common_vm: &CommonPythonVM, value: PyObjectRef) -
         .enter(|vm| convert_py_to_js(vm, value))
 }
 
+/// Values without a JS equivalent become undefined, see `convert_py_to_js_with`.
 pub fn convert_py_to_js(vm: &VirtualMachine, value: PyObjectRef) -> JsValue {
+    convert_py_to_js_with(vm, value, &|_| JsValue::UNDEFINED)
+}
+
+/// Like `convert_py_to_js`, but values without a JS equivalent (instances,
+/// functions, generators...) go through `fallback`, e.g. to wrap them in a handle.
+pub fn convert_py_to_js_with(
+    vm: &VirtualMachine,
+    value: PyObjectRef,
+    fallback: &dyn Fn(PyObjectRef) -> JsValue,
+) -> JsValue {
     if let Ok(int_obj) = i64::try_from_object(&vm, value.clone()) {
         JsValue::from_f64(int_obj as f64)
     } else if let Ok(float_obj) = f64::try_from_object(&vm, value.clone()) {
@@ -27,29 +45,285 @@ pub fn convert_py_to_js(vm: &VirtualMachine, value: PyObjectRef) -> JsValue {
     } else if let Some(list) = value.downcast_ref::<PyList>() {
         let js_arr = Array::new();
         list.borrow_vec().iter().for_each(|item| {
-            js_arr.push(&convert_py_to_js(vm, item.clone()));
+            js_arr.push(&convert_py_to_js_with(vm, item.clone(), fallback));
         });
         JsValue::from(js_arr)
         // for item in list.elements() {
         //     js_arr.push(&convery_py_to_js(vm, item.clone()));
         // }
+    } else if let Some(tuple) = value.downcast_ref::<PyTuple>() {
+        let js_arr = Array::new();
+        tuple.as_slice().iter().for_each(|item| {
+            js_arr.push(&convert_py_to_js_with(vm, item.clone(), fallback));
+        });
+        JsValue::from(js_arr)
     } else if let Some(dict) = value.downcast_ref::<PyDict>() {
         let js_obj = Object::new();
         dict.into_iter().for_each(|(key, val)| {
             Reflect::set(
                 &js_obj,
-                &convert_py_to_js(vm, key.clone()),
-                &convert_py_to_js(vm, val.clone()),
+                &convert_py_to_js_with(vm, key.clone(), fallback),
+                &convert_py_to_js_with(vm, val.clone(), fallback),
             )
             .expect("property to be settable");
         });
         JsValue::from(js_obj)
-    } else {
+    } else if vm.is_none(&value) {
         JsValue::UNDEFINED
+    } else {
+        fallback(value)
+    }
+}
+
+pub fn convert_python_error_to_js(error: &PythonError) -> JsValue {
+    let traceback = Array::new();
+    error.traceback.iter().for_each(|frame| {
+        let js_frame = Object::new();
+        let _ = Reflect::set(&js_frame, &"file".into(), &frame.file.as_str().into());
+        let _ = Reflect::set(&js_frame, &"line".into(), &(frame.line as f64).into());
+        let _ = Reflect::set(
+            &js_frame,
+            &"column".into(),
+            &match frame.column {
+                Some(column) => JsValue::from_f64(column as f64),
+                None => JsValue::NULL,
+            },
+        );
+        let _ = Reflect::set(
+            &js_frame,
+            &"function".into(),
+            &frame.function.as_str().into(),
+        );
+        traceback.push(&js_frame);
+    });
+
+    let js_obj = Object::new();
+    let _ = Reflect::set(&js_obj, &"type".into(), &error.type_name.as_str().into());
+    let _ = Reflect::set(&js_obj, &"module".into(), &error.module.as_str().into());
+    let _ = Reflect::set(&js_obj, &"message".into(), &error.message.as_str().into());
+    let _ = Reflect::set(&js_obj, &"traceback".into(), &traceback);
+    let _ = Reflect::set(
+        &js_obj,
+        &"cause".into(),
+        &match &error.cause {
+            Some(cause) => convert_python_error_to_js(cause),
+            None => JsValue::NULL,
+        },
+    );
+    let _ = Reflect::set(
+        &js_obj,
+        &"context".into(),
+        &match &error.context {
+            Some(context) => convert_python_error_to_js(context),
+            None => JsValue::NULL,
+        },
+    );
+    let _ = Reflect::set(
+        &js_obj,
+        &"formatted".into(),
+        &error.to_string().as_str().into(),
+    );
+    JsValue::from(js_obj)
+}
+
+pub fn convert_reload_diff_to_js(diff: &ReloadDiff) -> JsValue {
+    let to_array = |names: &Vec<String>| -> Array {
+        names.iter().map(|name| JsValue::from_str(name)).collect()
+    };
+
+    let js_obj = Object::new();
+    let _ = Reflect::set(&js_obj, &"added".into(), &to_array(&diff.added));
+    let _ = Reflect::set(&js_obj, &"removed".into(), &to_array(&diff.removed));
+    let _ = Reflect::set(&js_obj, &"changed".into(), &to_array(&diff.changed));
+    let _ = Reflect::set(&js_obj, &"persisted".into(), &to_array(&diff.persisted));
+    let _ = Reflect::set(
+        &js_obj,
+        &"updated_modules".into(),
+        &to_array(&diff.updated_modules),
+    );
+    JsValue::from(js_obj)
+}
+
+/// `{stream, text, formatted, source, timestamp_msec}`, `source` is null for eval and session code.
+pub fn convert_output_chunk_to_js(chunk: &OutputChunk) -> JsValue {
+    let js_obj = Object::new();
+    let _ = Reflect::set(&js_obj, &"stream".into(), &chunk.stream.as_str().into());
+    let _ = Reflect::set(&js_obj, &"text".into(), &chunk.text.as_str().into());
+    let _ = Reflect::set(
+        &js_obj,
+        &"formatted".into(),
+        &chunk.formatted.as_str().into(),
+    );
+    let _ = Reflect::set(
+        &js_obj,
+        &"source".into(),
+        &match &chunk.source {
+            Some(source) => JsValue::from_str(source),
+            None => JsValue::NULL,
+        },
+    );
+    let _ = Reflect::set(
+        &js_obj,
+        &"timestamp_msec".into(),
+        &chunk.timestamp_msec.into(),
+    );
+    JsValue::from(js_obj)
+}
+
+/// `{ok, value, error, stdout, stderr}`, what every call into Python returns. `ok` is
+/// false when Python raised, then `error` is the error object and `value` undefined.
+pub fn convert_result_to_js(r: Result<JsValue, PythonError>) -> JsValue {
+    convert_result_with_output_to_js(r, &CapturedOutput::default())
+}
+
+/// Like `convert_result_to_js`, with what a `_captured` call printed.
+pub fn convert_result_with_output_to_js(
+    r: Result<JsValue, PythonError>,
+    output: &CapturedOutput,
+) -> JsValue {
+    let (ok, value, error) = match r {
+        Ok(value) => (true, value, JsValue::NULL),
+        Err(error) => (
+            false,
+            JsValue::UNDEFINED,
+            convert_python_error_to_js(&error),
+        ),
+    };
+    let js_obj = Object::new();
+    let _ = Reflect::set(&js_obj, &"ok".into(), &ok.into());
+    let _ = Reflect::set(&js_obj, &"value".into(), &value);
+    let _ = Reflect::set(&js_obj, &"error".into(), &error);
+    let _ = Reflect::set(&js_obj, &"stdout".into(), &output.stdout.as_str().into());
+    let _ = Reflect::set(&js_obj, &"stderr".into(), &output.stderr.as_str().into());
+    JsValue::from(js_obj)
+}
+
+pub fn convert_members_to_js(members: &[ModuleMember]) -> Array {
+    members
+        .iter()
+        .map(|member| {
+            let js_obj = Object::new();
+            let _ = Reflect::set(&js_obj, &"name".into(), &member.name.as_str().into());
+            let _ = Reflect::set(&js_obj, &"kind".into(), &member.kind.as_str().into());
+            let _ = Reflect::set(&js_obj, &"type".into(), &member.type_name.as_str().into());
+            JsValue::from(js_obj)
+        })
+        .collect()
+}
+
+/// Defaults are converted like return values, annotations and the docstring are strings or null.
+pub fn convert_signature_to_js(
+    common_vm: &CommonPythonVM,
+    signature: &FunctionSignature,
+) -> JsValue {
+    let optional_string = |value: &Option<String>| match value {
+        Some(value) => JsValue::from_str(value),
+        None => JsValue::NULL,
+    };
+
+    let parameters = Array::new();
+    signature.parameters.iter().for_each(|parameter| {
+        let js_parameter = Object::new();
+        let _ = Reflect::set(
+            &js_parameter,
+            &"name".into(),
+            &parameter.name.as_str().into(),
+        );
+        let _ = Reflect::set(
+            &js_parameter,
+            &"kind".into(),
+            &parameter.kind.as_str().into(),
+        );
+        let _ = Reflect::set(
+            &js_parameter,
+            &"has_default".into(),
+            &parameter.default.is_some().into(),
+        );
+        let _ = Reflect::set(
+            &js_parameter,
+            &"default".into(),
+            &match &parameter.default {
+                Some(default) => convert_py_to_js_common(common_vm, default.clone()),
+                None => JsValue::UNDEFINED,
+            },
+        );
+        let _ = Reflect::set(
+            &js_parameter,
+            &"annotation".into(),
+            &optional_string(&parameter.annotation),
+        );
+        parameters.push(&js_parameter);
+    });
+
+    let js_obj = Object::new();
+    let _ = Reflect::set(&js_obj, &"name".into(), &signature.name.as_str().into());
+    let _ = Reflect::set(&js_obj, &"parameters".into(), &parameters);
+    let _ = Reflect::set(
+        &js_obj,
+        &"return_annotation".into(),
+        &optional_string(&signature.return_annotation),
+    );
+    let _ = Reflect::set(&js_obj, &"doc".into(), &optional_string(&signature.doc));
+    JsValue::from(js_obj)
+}
+
+/// Reads a policy from `{preset, allowed_modules, removed_builtins, allow_dunder_access}`.
+/// Missing keys keep the preset's value; the preset is "unrestricted" unless set to "restricted".
+pub fn convert_js_obj_to_sandbox_policy(obj: &Object) -> SandboxPolicy {
+    let get = |key: &str| {
+        Reflect::get(obj, &key.into())
+            .ok()
+            .filter(|value| !value.is_undefined())
+    };
+
+    let mut policy = match get("preset").and_then(|preset| preset.as_string()) {
+        Some(preset) if preset == "restricted" => SandboxPolicy::restricted(),
+        _ => SandboxPolicy::unrestricted(),
+    };
+    if let Some(allowed_modules) = get("allowed_modules") {
+        policy.allowed_modules = if allowed_modules.is_null() {
+            None
+        } else {
+            Some(convert_js_to_string_vec(allowed_modules))
+        };
+    }
+    if let Some(removed_builtins) = get("removed_builtins") {
+        policy.removed_builtins = convert_js_to_string_vec(removed_builtins);
+    }
+    if let Some(allow_dunder_access) = get("allow_dunder_access").and_then(|v| v.as_bool()) {
+        policy.allow_dunder_access = allow_dunder_access;
+    }
+    policy
+}
+
+fn convert_js_to_string_vec(value: JsValue) -> Vec<String> {
+    if Array::is_array(&value) {
+        Array::from(&value)
+            .iter()
+            .filter_map(|v| v.as_string())
+            .collect()
+    } else {
+        Vec::new()
     }
 }
 
 pub fn convert_js_to_py(vm: &VirtualMachine, js_val: JsValue) -> PyObjectRef {
+    convert_js_to_py_with(vm, js_val, &|_| None)
+}
+
+/// Like `convert_js_to_py`, but objects and functions are first offered to `resolve_object`,
+/// e.g. to unwrap handles back into the Python object they hold.
+pub fn convert_js_to_py_with(
+    vm: &VirtualMachine,
+    js_val: JsValue,
+    resolve_object: &dyn Fn(&JsValue) -> Option<PyObjectRef>,
+) -> PyObjectRef {
+    if js_val.is_object() || js_val.is_function() {
+        if let Some(object) = resolve_object(&js_val) {
+            return object;
+        }
+    }
+
     if js_val.is_bigint() {
         let bi = BigInt::from(js_val);
         let bis = bi.to_string(10).unwrap().as_string().unwrap();
@@ -70,7 +344,13 @@ pub fn convert_js_to_py(vm: &VirtualMachine, js_val: JsValue) -> PyObjectRef {
         let elems = js_arr
             .values()
             .into_iter()
-            .map(|val| convert_js_to_py(vm, val.expect("Iteration over array failed")))
+            .map(|val| {
+                convert_js_to_py_with(
+                    vm,
+                    val.expect("Iteration over array failed"),
+                    resolve_object,
+                )
+            })
             .collect();
         vm.ctx.new_list(elems).into()
     } else if ArrayBuffer::is_type_of(&js_val) {
@@ -89,7 +369,7 @@ pub fn convert_js_to_py(vm: &VirtualMachine, js_val: JsValue) -> PyObjectRef {
         let dict = vm.ctx.new_dict();
         for pair in object_entries(&Object::from(js_val)) {
             let (key, val) = pair.expect("iteration over object to not fail");
-            let py_val = convert_js_to_py(vm, val);
+            let py_val = convert_js_to_py_with(vm, val, resolve_object);
             dict.set_item(
                 String::from(js_sys::JsString::from(key)).as_str(),
                 py_val,
@@ -148,15 +428,23 @@ pub fn js_err_to_py_err(vm: &VirtualMachine, js_err: &JsValue) -> PyBaseExceptio
     }
 }
 
-pub fn convert_js_arr_to_args(common_vm: &CommonPythonVM, arr: Array) -> Vec<PyObjectRef> {
+pub fn convert_js_arr_to_args(
+    common_vm: &CommonPythonVM,
+    arr: Array,
+    resolve_object: &dyn Fn(&JsValue) -> Option<PyObjectRef>,
+) -> Vec<PyObjectRef> {
     common_vm.interpreter.enter(|vm| {
         arr.into_iter()
-            .map(|val| convert_js_to_py(vm, val))
+            .map(|val| convert_js_to_py_with(vm, val, resolve_object))
             .collect()
     })
 }
 
-pub fn convert_js_obj_to_kwargs(common_vm: &CommonPythonVM, obj: Object) -> KwArgs {
+pub fn convert_js_obj_to_kwargs(
+    common_vm: &CommonPythonVM,
+    obj: Object,
+    resolve_object: &dyn Fn(&JsValue) -> Option<PyObjectRef>,
+) -> KwArgs {
     common_vm.interpreter.enter(|vm| {
         let mut map = IndexMap::new();
 
@@ -164,7 +452,7 @@ pub fn convert_js_obj_to_kwargs(common_vm: &CommonPythonVM, obj: Object) -> KwAr
             let (key, val) = pair.expect("iteration over object to not fail");
             map.insert(
                 String::from(js_sys::JsString::from(key)),
-                convert_js_to_py(vm, val),
+                convert_js_to_py_with(vm, val, resolve_object),
             );
         });
         KwArgs::new(map)
@@ -184,15 +472,20 @@ pub fn object_entries(obj: &Object) -> impl Iterator<Item = Result<(JsValue, JsV
 #[cfg(test)]
 pub mod tests {
 
-    use js_sys::Map;
+    use std::{cell::RefCell, rc::Rc};
+
+    use js_sys::{Function, Map};
     use rustpython_vm::{
         builtins::{PyFloat, PyInt, PyStr},
         convert::ToPyObject,
-        PyPayload,
+        AsObject, PyPayload,
     };
     use wasm_bindgen_test::wasm_bindgen_test;
 
-    use super::*;
+    use super::{
+        super::python_object::{convert_js_to_func_args, convert_py_to_js_with_handles},
+        *,
+    };
 
     #[wasm_bindgen_test]
     fn test_convert_py_to_js_int() {
@@ -423,7 +716,7 @@ pub mod tests {
             value.push(&JsValue::from_bool(true));
             value.push(&JsValue::from_str("test"));
 
-            let result = convert_js_arr_to_args(&common_vm, value);
+            let result = convert_js_arr_to_args(&common_vm, value, &|_| None);
 
             let expected = vec![
                 vm.ctx.new_float(42.0).to_pyobject(vm),
@@ -445,7 +738,7 @@ pub mod tests {
         let common_vm = CommonPythonVM::init();
         let value = Array::new();
 
-        let result = convert_js_arr_to_args(&common_vm, value);
+        let result = convert_js_arr_to_args(&common_vm, value, &|_| None);
 
         let expected: Vec<PyObjectRef> = vec![];
 
@@ -465,7 +758,7 @@ pub mod tests {
         entries.set(&"num".into(), &JsValue::from_f64(42.0));
         let value = Object::from_entries(&entries).unwrap();
 
-        let result = convert_js_obj_to_kwargs(&common_vm, value);
+        let result = convert_js_obj_to_kwargs(&common_vm, value, &|_| None);
         let expected = common_vm.interpreter.enter(|vm| {
             let mut map = IndexMap::new();
             map.insert("bool".to_string(), vm.ctx.new_bool(true).to_pyobject(vm));
@@ -480,4 +773,26 @@ pub mod tests {
             assert_eq!(format!("{:?}", val), format!("{:?}", expected_val));
         }
     }
+
+    #[wasm_bindgen_test]
+    fn test_convert_py_function_to_js() {
+        let common_vm = Rc::new(RefCell::new(CommonPythonVM::init()));
+        let function = common_vm
+            .borrow()
+            .eval("lambda a, b: a + b".to_string())
+            .unwrap();
+
+        let js_function =
+            Function::from(convert_py_to_js_with_handles(&common_vm, function.clone()));
+        assert_eq!(String::from(js_function.name()), "<lambda>");
+        let result = js_function
+            .call2(&JsValue::UNDEFINED, &1.into(), &2.into())
+            .unwrap();
+        assert_eq!(result, JsValue::from_f64(3.0));
+        assert!(js_function.call1(&JsValue::UNDEFINED, &1.into()).is_err());
+
+        let f_args =
+            convert_js_to_func_args(&common_vm.borrow(), Array::of1(&js_function), Object::new());
+        assert!(f_args.args[0].is(&function));
+    }
 }
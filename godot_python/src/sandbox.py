@@ -0,0 +1,207 @@
+import builtins
+import sys
+import types
+
+# Dunder names user code may still use when dunder access is disabled
+SAFE_DUNDERS = {
+    "__init__",
+    "__name__",
+    "__doc__",
+    "__file__",
+    "__len__",
+    "__iter__",
+    "__next__",
+    "__enter__",
+    "__exit__",
+    "__str__",
+    "__repr__",
+    "__eq__",
+    "__ne__",
+    "__lt__",
+    "__le__",
+    "__gt__",
+    "__ge__",
+    "__hash__",
+    "__contains__",
+    "__getitem__",
+    "__setitem__",
+    "__delitem__",
+}
+
+# Modules the host provides for user code, importable whatever the policy allows.
+# `godot.math` is `godot_math`.
+HOST_MODULES = {"godot", "godot_math"}
+
+# Builtins that hand out the namespace dicts, which would bypass the dunder check
+NAMESPACE_BUILTINS = {"globals", "locals", "vars"}
+
+_original_import = builtins.__import__
+_active = False
+_allowed_modules = None
+_removed_builtins = set()
+_allow_dunder_access = True
+_user_modules = set()
+# Files of the code `code_compiler.instrument` compiled. A frame's file can't be
+# changed from Python, unlike the `__name__` of its globals.
+_user_files = set()
+# The VM's own helpers, e.g. this module, which user code must not reconfigure
+_internal_modules = set()
+# Tells whether a module not imported yet comes from a host module source
+_user_module_resolver = None
+
+
+def configure(allowed_modules, removed_builtins, allow_dunder_access, internal_modules):
+    global _active, _allowed_modules, _removed_builtins, _allow_dunder_access
+    global _internal_modules
+    _internal_modules = set(internal_modules)
+    _allowed_modules = None if allowed_modules is None else set(allowed_modules)
+    _removed_builtins = set(removed_builtins)
+    _allow_dunder_access = allow_dunder_access
+    if not allow_dunder_access:
+        _removed_builtins |= NAMESPACE_BUILTINS
+    _active = (
+        _allowed_modules is not None
+        or len(_removed_builtins) > 0
+        or not _allow_dunder_access
+    )
+
+    if _active:
+        builtins.__import__ = _guarded_import
+        builtins.__sandbox_check_import__ = check_import
+        builtins.__sandbox_removed__ = _removed_builtin
+        builtins.__sandbox_getattr__ = _safe_getattr
+        builtins.__sandbox_setattr__ = _safe_setattr
+        builtins.__sandbox_delattr__ = _safe_delattr
+        builtins.__sandbox_hasattr__ = _safe_hasattr
+
+
+def is_active():
+    return _active
+
+
+def register_user_module(name):
+    _user_modules.add(name)
+
+
+def set_user_module_resolver(resolver):
+    global _user_module_resolver
+    _user_module_resolver = resolver
+
+
+def _is_user_module(name):
+    if name in _user_modules:
+        return True
+    return _user_module_resolver is not None and _user_module_resolver(name)
+
+
+def is_removed_builtin(name):
+    return name in _removed_builtins
+
+
+def allows_dunder_access():
+    return _allow_dunder_access
+
+
+def is_blocked_dunder(name):
+    if _allow_dunder_access:
+        return False
+    return name.startswith("__") and name.endswith("__") and name not in SAFE_DUNDERS
+
+
+def check_import(name):
+    if "__import__" in _removed_builtins:
+        raise ImportError("imports are not allowed")
+    top_level = name.partition(".")[0]
+    if top_level in HOST_MODULES:
+        return
+    if top_level in _internal_modules:
+        raise ImportError(f"import of '{name}' is not allowed")
+    # Checked last, so a user module can't stand in for a module the policy blocks
+    if _allowed_modules is None or top_level in _allowed_modules:
+        return
+    if not _is_user_module(top_level):
+        raise ImportError(f"import of '{name}' is not allowed")
+
+
+def register_user_file(filename):
+    _user_files.add(filename)
+
+
+def is_user_frame(frame):
+    return frame is not None and frame.f_code.co_filename in _user_files
+
+
+def _guarded_import(name, globals=None, locals=None, fromlist=(), level=0):
+    # The frame running the import statement
+    if level > 0 or not is_user_frame(sys._getframe(1)):
+        return _original_import(name, globals, locals, fromlist, level)
+    check_import(name)
+    module = _original_import(name, globals, locals, fromlist, level)
+    # `from typing import sys` must not hand out what `import sys` wouldn't
+    for attribute in fromlist or ():
+        names = [attribute]
+        if attribute == "*":
+            names = getattr(module, "__all__", None) or [
+                n for n in dir(module) if not n.startswith("_")
+            ]
+        for n in names:
+            try:
+                _check_module_attribute(module, n, getattr(module, n, None))
+            except AttributeError as error:
+                raise ImportError(str(error)) from None
+    return module
+
+
+def _removed_builtin(name):
+    raise NameError(f"name '{name}' is not defined")
+
+
+def _is_private(name):
+    return name.startswith("_") and name not in SAFE_DUNDERS
+
+
+def _check_module_attribute(module, name, value):
+    # Modules keep what they import as attributes, e.g. `random._os` or `typing.sys`
+    if _is_private(name):
+        raise AttributeError(
+            f"access to attribute '{name}' of module '{module.__name__}' is not allowed"
+        )
+    if isinstance(value, types.ModuleType):
+        try:
+            check_import(value.__name__)
+        except ImportError:
+            raise AttributeError(
+                f"access to module '{value.__name__}' is not allowed"
+            ) from None
+
+
+def _check_attribute(obj, name):
+    if not isinstance(name, str):
+        return
+    if is_blocked_dunder(name):
+        raise AttributeError(f"access to attribute '{name}' is not allowed")
+    if isinstance(obj, types.ModuleType) and _is_private(name):
+        _check_module_attribute(obj, name, None)
+
+
+def _safe_getattr(obj, name, *default):
+    _check_attribute(obj, name)
+    value = getattr(obj, name, *default)
+    if isinstance(obj, types.ModuleType):
+        _check_module_attribute(obj, name, value)
+    return value
+
+
+def _safe_setattr(obj, name, value):
+    _check_attribute(obj, name)
+    return setattr(obj, name, value)
+
+
+def _safe_delattr(obj, name):
+    _check_attribute(obj, name)
+    return delattr(obj, name)
+
+
+def _safe_hasattr(obj, name):
+    _check_attribute(obj, name)
+    return hasattr(obj, name)
@@ -0,0 +1,144 @@
+import builtins
+import collections
+import contextlib
+import io
+import sys
+
+# Lines the host pushed that Python has not read yet
+_lines = collections.deque()
+# Futures of `await input()` calls waiting for a line, oldest first
+_waiters = collections.deque()
+# Set by the host: request_line(prompt) returns a line, or None when it has none yet
+_request_line = None
+_suspending = False
+# Steps of host-run coroutines in progress, see `coroutine_runner.step`
+_coroutine_depth = 0
+
+
+def configure(request_line, suspending):
+    global _request_line, _suspending
+    _request_line = request_line
+    _suspending = suspending
+
+
+def push_line(line):
+    line = line.removesuffix("\n")
+    while _waiters:
+        waiter = _waiters.popleft()
+        if not waiter.done():
+            waiter.set_result(line)
+            return
+    _lines.append(line)
+
+
+def take_line():
+    return _lines.popleft() if _lines else None
+
+
+def _read_line(prompt):
+    line = take_line()
+    if line is None and _request_line is not None:
+        line = _request_line(prompt)
+    return line
+
+
+@contextlib.contextmanager
+def running_coroutine():
+    global _coroutine_depth
+    _coroutine_depth += 1
+    try:
+        yield
+    finally:
+        _coroutine_depth -= 1
+
+
+def _running_loop():
+    try:
+        from asyncio import events
+    except ImportError:
+        return None
+    return events._get_running_loop()
+
+
+class ReadLine:
+    """What `input()` returns in suspending mode: `yield` it in a generator run
+    as a PythonCoroutine, or `await` it in an `async def`, to get the line."""
+
+    def __init__(self, prompt):
+        self.prompt = prompt
+        # The host may have a line ready, otherwise one is pushed later
+        self.line = _read_line(prompt)
+
+    def __await__(self):
+        if self.line is not None:
+            return self.line
+        line = take_line()
+        if line is not None:
+            return line
+        loop = _running_loop()
+        if loop is None:
+            # Run by a PythonCoroutine, which sends the line back
+            return (yield self)
+        waiter = loop.create_future()
+        _waiters.append(waiter)
+        return (yield from waiter.__await__())
+
+    def __repr__(self):
+        return f"ReadLine({self.prompt!r})"
+
+
+class HostStdin(io.TextIOBase):
+    """`sys.stdin` reading the lines the host pushes. Always blocking: once no line
+    is left and the host has none either, it reads as end of file."""
+
+    def __init__(self):
+        super().__init__()
+        # What a sized read left of the last line
+        self._rest = ""
+
+    def readable(self):
+        return True
+
+    def isatty(self):
+        return False
+
+    def readline(self, size=-1):
+        if self._rest:
+            line, self._rest = self._rest, ""
+        else:
+            line = _read_line("")
+            line = "" if line is None else line + "\n"
+        if 0 <= size < len(line):
+            line, self._rest = line[:size], line[size:]
+        return line
+
+    def read(self, size=-1):
+        text = ""
+        while size < 0 or len(text) < size:
+            line = self.readline(-1 if size < 0 else size - len(text))
+            if not line:
+                break
+            text += line
+        return text
+
+
+def host_input(prompt=""):
+    # Nothing could hand the ReadLine back once a line arrives
+    if _suspending and _coroutine_depth == 0 and _running_loop() is None:
+        raise RuntimeError(
+            "input() suspends until the host pushes a line, await it in an async "
+            "def or yield it in a generator the host runs as a coroutine"
+        )
+    if prompt:
+        sys.stdout.write(str(prompt))
+        sys.stdout.flush()
+    if _suspending:
+        return ReadLine(str(prompt))
+    line = _read_line(str(prompt))
+    if line is None:
+        raise EOFError("EOF when reading a line")
+    return line
+
+
+sys.stdin = HostStdin()
+builtins.input = host_input
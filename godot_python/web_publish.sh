@@ -1,3 +1,4 @@
-wasm-pack build --target web
+# Weak references let the JS garbage collector free the functions Python hands out
+WASM_BINDGEN_WEAKREF=1 wasm-pack build --target web
 wasm-pack pack
 wasm-pack publish
\ No newline at end of file
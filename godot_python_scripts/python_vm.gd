@@ -7,7 +7,8 @@ var is_ready = false
 var output: String = ""
 
 var is_web: bool = false
-var _wasm_version = "0.7.3"
+# The npm release of the crate the web build loads, bump it with the version in Cargo.toml
+var _wasm_version = "0.8.0"
 var _window
 var _document
 var _console
@@ -25,15 +26,24 @@ func _ready():
 		_document = JavaScriptBridge.get_interface("document")
 		_console = JavaScriptBridge.get_interface("console")
 		_import_script_loaded_callback  = JavaScriptBridge.create_callback(_script_loaded)
-		_output_callback = JavaScriptBridge.create_callback(append_output)
+		_output_callback = JavaScriptBridge.create_callback(_append_js_output)
 		import_wasm_python_vm()
 	else:
 		add_child(python_vm_wrapper.instantiate())
 		python_vm = $GodotPythonVMWrapper.python_vm
-		python_vm.setup_stdout(self)
+		python_vm.setup_stdout(append_output)
+		python_vm.setup_stderr(append_output)
 		setup_vm()
 	
 
+func _process(delta: float) -> void:
+	# Drives asyncio.sleep and the tasks of async def calls
+	if is_ready:
+		var polled = python_vm.poll(delta)
+		if not polled.ok:
+			append_output("[color=red]%s[/color]\n" % polled.error.formatted)
+
+
 func import_wasm_python_vm():
 	# print("importing wasm python vm")
 
@@ -63,6 +73,8 @@ func _script_loaded(_args) -> void:
 	# print("script loaded: ", args)
 	python_vm = _window.python_vm
 	python_vm.setup_stdout(_output_callback)
+	python_vm.setup_stderr(_output_callback)
+	python_vm.set_output_markup("bbcode")
 
 	setup_vm()
 
@@ -75,23 +87,20 @@ func setup_vm():
 	python_vm_ready.emit()
 
 
-func append_output(args) -> void:
-	# print(args)
-	# print("append_output: ", args)
-	if args is String:
-		output += args
-		stdout_updated.emit(args)
-		# print('append_output: ', args)
-	else:
-		for text in args:
-			output += text
-			stdout_updated.emit(text)
-			# print('append_output: ', text)
+func append_output(text: String) -> void:
+	output += text
+	stdout_updated.emit(text)
+
 
+func _append_js_output(args) -> void:
+	# JS callbacks get (formatted, chunk), only the text is shown
+	append_output(str(args[0]))
 
+
+# Calls return a PythonResult (a JS object on the web) with ok, value and error
 func eval(expr: String):
 	if not python_vm:
-		return "Python VM not loaded"
+		return _not_loaded()
 
 	return python_vm.eval(expr)
 
@@ -101,34 +110,76 @@ func load_module(m_name: String, c: String) -> bool:
 		return false
 	
 	var loaded_module = python_vm.load_module(m_name, c)
-	# print("loaded module: ", loaded_module)
-	# result.text = loaded_module
 
-	if loaded_module == "Success":
-		append_output("[color=green]Loaded Module: %s[/color]\n" % loaded_module)
+	if loaded_module.ok:
+		append_output("[color=green]Loaded Module: %s[/color]\n" % m_name)
 		return true
 	else:
-		append_output("[color=red]%s[/color]\n" % loaded_module)
+		append_output("[color=red]%s[/color]\n" % loaded_module.error.formatted)
+		return false
+
+
+func reload_module(m_name: String, c: String) -> bool:
+	if not python_vm:
 		return false
 
+	var reloaded = python_vm.reload_module(m_name, c)
+
+	if not reloaded.ok:
+		append_output("[color=red]%s[/color]\n" % reloaded.error.formatted)
+		return false
+
+	append_output("[color=green]Reloaded Module: %s[/color]\n" % m_name)
+	var diff = reloaded.value
+	for key in ["added", "removed", "changed", "persisted"]:
+		if diff[key].size() > 0:
+			append_output("  %s: %s\n" % [key, ", ".join(_to_array(diff[key]))])
+	return true
+
+
+# Same shape as a PythonResult, for calls made before the VM is loaded
+func _not_loaded() -> Dictionary:
+	return {
+		"ok": false,
+		"value": null,
+		"error": {"formatted": "Python VM not loaded"},
+		"stdout": "",
+		"stderr": "",
+	}
+
+
+func _to_array(value):
+	if is_web and value is JavaScriptObject:
+		var arr = []
+		for i in range(0, value.length):
+			arr.append(value[i])
+		return arr
+	return value
+
 
 
 func call_python_function(module_name: String, function_name: String, args: Array = [], kwargs: Dictionary = {}) -> Variant:
 	if not python_vm:
-		return "Python VM not loaded"
+		return _not_loaded()
 
 	var args_actual = create_args(args)
 	var kwargs_actual = create_kwargs(kwargs)
-	var result = python_vm.call_python_function(module_name, function_name, args_actual, kwargs_actual)
+	return python_vm.call_python_function(module_name, function_name, args_actual, kwargs_actual)
 
-	if is_web and result is JavaScriptObject:
-		_console.log(result)
-		var arr = []
-		for i in range(0, result.length):
-			arr.append(result[i])
-		return arr
 
-	return result
+# Like call_python_function, with what this one call printed in stdout and stderr
+func call_python_function_captured(module_name: String, function_name: String, args: Array = [], kwargs: Dictionary = {}) -> Variant:
+	if not python_vm:
+		return _not_loaded()
+
+	return python_vm.call_python_function_captured(module_name, function_name, create_args(args), create_kwargs(kwargs))
+
+
+func eval_captured(expr: String) -> Variant:
+	if not python_vm:
+		return _not_loaded()
+
+	return python_vm.eval_captured(expr)
 
 
 func create_args(args: Array):
@@ -11,6 +11,8 @@ extends Node2D
 var input: String = ""
 
 var user_typing = false
+# False until the module loads once, a failed first load leaves nothing to reload
+var module_loaded = false
 
 const module_name = "char_module"
 const MOVEMENT_SPEED = 300
@@ -26,14 +28,14 @@ func _ready():
 
 func _on_python_vm_ready():
 	print("Python VM Ready")
-	PythonVM.load_module(module_name, code.text)
+	module_loaded = PythonVM.load_module(module_name, code.text)
 
 	var arg_test = PythonVM.call_python_function(module_name, "args_test", ["test"])
-	print(arg_test)
+	print(arg_test.value)
 	var kwarg_test = PythonVM.call_python_function(module_name, "kwargs_test", [], {"test": {"x": 1, "y": 2}})
-	print(kwarg_test)
+	print(kwarg_test.value)
 	var i_kwarg_test = PythonVM.call_python_function(module_name, "i_kwargs_test", [1], {"test": {"x": 1, "y": 2}})
-	print(i_kwarg_test)
+	print(i_kwarg_test.value)
 
 
 func _on_output(args) -> void:
@@ -64,12 +66,9 @@ func _input(event: InputEvent) -> void:
 		return
 
 	if event is InputEventKey and not user_typing:
-		if event.is_pressed():
-			var i = get_keys(event.as_text_key_label())
-			input = PythonVM.call_python_function(module_name, "input", [i[0], true], {})
-		else: 
-			var i = get_keys(event.as_text_key_label())
-			input = PythonVM.call_python_function(module_name, "input", [i[0], false], {})
+		var i = get_keys(event.as_text_key_label())
+		var moved = PythonVM.call_python_function(module_name, "input", [i[0], event.is_pressed()], {})
+		input = moved.value if moved.ok else ""
 		
 		update_move_label()
 
@@ -93,7 +92,10 @@ func _process(_delta: float) -> void:
 
 
 func _on_deploy_pressed() -> void:
-	PythonVM.load_module(module_name, code.text)
+	if module_loaded:
+		PythonVM.reload_module(module_name, code.text)
+	else:
+		module_loaded = PythonVM.load_module(module_name, code.text)
 
 
 func _on_python_edit_focus_exited() -> void: